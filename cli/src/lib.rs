@@ -26,7 +26,7 @@ use iroha_core::{
     kiso::KisoHandle,
     kura::Kura,
     query::store::LiveQueryStore,
-    queue::Queue,
+    queue::{reaper::QueueReaper, Queue},
     smartcontracts::isi::Registrable as _,
     snapshot::{
         try_read_snapshot, SnapshotMaker, SnapshotMakerHandle, TryReadError as TryReadSnapshotError,
@@ -105,6 +105,8 @@ pub enum StartError {
     ListenOsSignal,
     #[error("Unable to start Torii (Iroha HTTP API Gateway)")]
     StartTorii,
+    #[error("Invalid queue configuration")]
+    InvalidQueueConfig,
 }
 
 /// Handle for freezing and unfreezing the network
@@ -301,7 +303,21 @@ impl Iroha {
         });
         let state = Arc::new(state);
 
-        let queue = Arc::new(Queue::from_config(config.queue, events_sender.clone()));
+        let queue =
+            Queue::from_config(config.queue.clone(), events_sender.clone()).map_err(|issues| {
+                issues.into_iter().fold(
+                    Report::new(StartError::InvalidQueueConfig),
+                    |report, issue| report.attach_printable(issue),
+                )
+            })?;
+        let queue = Arc::new(queue);
+        // Runs until shutdown is notified
+        let _queue_reaper = QueueReaper::from_config(
+            config.queue.clone(),
+            Arc::clone(&queue),
+            Arc::clone(&notify_shutdown),
+        )
+        .start();
 
         #[cfg(feature = "telemetry")]
         Self::start_telemetry(&logger, &config).await?;
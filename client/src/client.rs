@@ -655,6 +655,15 @@ impl Client {
                                 return Err((Clone::clone(&**reason)).into());
                             }
                             TransactionStatus::Expired => return Err(eyre!("Transaction expired")),
+                            TransactionStatus::Cancelled(None) => {
+                                return Err(eyre!("Transaction cancelled"))
+                            }
+                            TransactionStatus::Cancelled(Some(reason)) => {
+                                return Err(eyre!("Transaction cancelled: {reason}"))
+                            }
+                            TransactionStatus::Dropped => {
+                                return Err(eyre!("Transaction dropped from the full queue"))
+                            }
                         }
                     }
                     PipelineEventBox::Block(block_event) => {
@@ -664,6 +673,11 @@ impl Client {
                             }
                         }
                     }
+                    PipelineEventBox::TransactionsExpired(expired_event) => {
+                        if expired_event.hashes().contains(&hash) {
+                            return Err(eyre!("Transaction expired"));
+                        }
+                    }
                 }
             }
         }
@@ -2,6 +2,7 @@
 //! structures in a way that is efficient for Iroha internally.
 
 use std::{
+    collections::BTreeSet,
     num::{NonZeroU32, NonZeroUsize},
     path::PathBuf,
     time::Duration,
@@ -10,7 +11,7 @@ use std::{
 use error_stack::{Result, ResultExt};
 use iroha_config_base::{read::ConfigReader, toml::TomlSource, util::Bytes, WithOrigin};
 use iroha_crypto::{KeyPair, PublicKey};
-use iroha_data_model::{peer::PeerId, ChainId};
+use iroha_data_model::{isi::InstructionType, peer::PeerId, ChainId};
 use iroha_primitives::{addr::SocketAddr, unique_vec::UniqueVec};
 use url::Url;
 pub use user::{DevTelemetry, Logger, Snapshot};
@@ -18,6 +19,7 @@ pub use user::{DevTelemetry, Logger, Snapshot};
 use crate::{
     kura::InitMode,
     parameters::{defaults, user},
+    queue::{ConfigIssue, EventsOverflow as QueueEventsOverflow},
 };
 
 /// Parsed configuration root
@@ -89,12 +91,27 @@ pub struct Genesis {
 }
 
 #[allow(missing_docs)]
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone)]
 pub struct Queue {
     pub capacity: NonZeroUsize,
     pub capacity_per_user: NonZeroUsize,
     pub transaction_time_to_live: Duration,
+    pub max_transaction_time_to_live: Duration,
     pub future_threshold: Duration,
+    pub expiry_grace: Duration,
+    pub reaping_interval: Duration,
+    pub evict_on_full: bool,
+    pub events_capacity: NonZeroUsize,
+    pub events_on_overflow: QueueEventsOverflow,
+    pub soft_age_threshold: Option<Duration>,
+    pub shard_amount: Option<NonZeroUsize>,
+    pub max_pinned_transactions: usize,
+    pub denied_instruction_kinds: BTreeSet<InstructionType>,
+    pub emit_expiry_events: bool,
+    pub rejection_log_capacity: usize,
+    pub rate_limit_burst: Option<NonZeroUsize>,
+    pub rate_limit_refill_interval: Duration,
+    pub max_total_instructions: Option<NonZeroUsize>,
 }
 
 #[allow(missing_docs)]
@@ -109,9 +126,60 @@ impl Default for Queue {
     fn default() -> Self {
         Self {
             transaction_time_to_live: defaults::queue::TRANSACTION_TIME_TO_LIVE,
+            max_transaction_time_to_live: defaults::queue::MAX_TRANSACTION_TIME_TO_LIVE,
             future_threshold: defaults::queue::FUTURE_THRESHOLD,
+            expiry_grace: defaults::queue::EXPIRY_GRACE,
             capacity: defaults::queue::CAPACITY,
             capacity_per_user: defaults::queue::CAPACITY_PER_USER,
+            reaping_interval: defaults::queue::REAPING_INTERVAL,
+            evict_on_full: false,
+            events_capacity: defaults::queue::EVENTS_CAPACITY,
+            events_on_overflow: QueueEventsOverflow::default(),
+            soft_age_threshold: None,
+            shard_amount: None,
+            max_pinned_transactions: defaults::queue::MAX_PINNED_TRANSACTIONS,
+            denied_instruction_kinds: BTreeSet::new(),
+            emit_expiry_events: defaults::queue::EMIT_EXPIRY_EVENTS,
+            rejection_log_capacity: defaults::queue::REJECTION_LOG_CAPACITY,
+            rate_limit_burst: None,
+            rate_limit_refill_interval: defaults::queue::RATE_LIMIT_REFILL_INTERVAL,
+            max_total_instructions: None,
+        }
+    }
+}
+
+impl Queue {
+    /// Check the relationships between the parameters, e.g. that `capacity_per_user` doesn't exceed `capacity`.
+    ///
+    /// # Errors
+    /// Returns every [`ConfigIssue`] found.
+    pub fn validate(&self) -> core::result::Result<(), Vec<ConfigIssue>> {
+        let mut issues = Vec::new();
+        if self.capacity_per_user > self.capacity {
+            issues.push(ConfigIssue::CapacityPerUserExceedsCapacity {
+                capacity: self.capacity,
+                capacity_per_user: self.capacity_per_user,
+            });
+        }
+        if self.future_threshold > self.transaction_time_to_live {
+            issues.push(ConfigIssue::FutureThresholdExceedsTimeToLive {
+                future_threshold: self.future_threshold,
+                transaction_time_to_live: self.transaction_time_to_live,
+            });
+        }
+        if let Some(soft_age_threshold) = self.soft_age_threshold {
+            if soft_age_threshold >= self.transaction_time_to_live {
+                issues.push(ConfigIssue::SoftAgeThresholdExceedsTimeToLive {
+                    soft_age_threshold,
+                    transaction_time_to_live: self.transaction_time_to_live,
+                });
+            }
+        }
+
+        if issues.is_empty() {
+            Ok(())
+        } else {
+            Err(issues)
         }
     }
 }
@@ -17,7 +17,16 @@ pub mod queue {
     pub const CAPACITY_PER_USER: NonZeroUsize = nonzero!(2_usize.pow(16));
     // 24 hours
     pub const TRANSACTION_TIME_TO_LIVE: Duration = Duration::from_secs(24 * 60 * 60);
+    // 30 days
+    pub const MAX_TRANSACTION_TIME_TO_LIVE: Duration = Duration::from_secs(30 * 24 * 60 * 60);
     pub const FUTURE_THRESHOLD: Duration = Duration::from_secs(1);
+    pub const EXPIRY_GRACE: Duration = Duration::ZERO;
+    pub const REAPING_INTERVAL: Duration = Duration::from_secs(60);
+    pub const EVENTS_CAPACITY: NonZeroUsize = nonzero!(10_000_usize);
+    pub const MAX_PINNED_TRANSACTIONS: usize = 16;
+    pub const EMIT_EXPIRY_EVENTS: bool = true;
+    pub const REJECTION_LOG_CAPACITY: usize = 0;
+    pub const RATE_LIMIT_REFILL_INTERVAL: Duration = Duration::from_millis(100);
 }
 
 pub mod kura {
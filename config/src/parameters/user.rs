@@ -11,13 +11,14 @@
 
 use std::{
     borrow::Cow,
+    collections::BTreeSet,
     convert::Infallible,
     fmt::Debug,
     num::{NonZeroU32, NonZeroUsize},
     path::PathBuf,
 };
 
-use error_stack::{Result, ResultExt};
+use error_stack::{Report, Result, ResultExt};
 use iroha_config_base::{
     attach::ConfigValueAndOrigin,
     env::FromEnvStr,
@@ -25,7 +26,7 @@ use iroha_config_base::{
     ReadConfig, WithOrigin,
 };
 use iroha_crypto::{PrivateKey, PublicKey};
-use iroha_data_model::{peer::PeerId, ChainId};
+use iroha_data_model::{isi::InstructionType, peer::PeerId, ChainId};
 use iroha_primitives::{addr::SocketAddr, unique_vec::UniqueVec};
 use serde::Deserialize;
 use url::Url;
@@ -34,6 +35,7 @@ use crate::{
     kura::InitMode as KuraInitMode,
     logger::{Directives, Format as LoggerFormat},
     parameters::{actual, defaults},
+    queue::EventsOverflow as QueueEventsOverflow,
     snapshot::Mode as SnapshotMode,
 };
 
@@ -84,6 +86,10 @@ pub struct Root {
 pub enum ParseError {
     #[error("Failed to construct the key pair")]
     BadKeyPair,
+    #[error("Queue shard amount must be a power of two greater than one")]
+    BadQueueShardAmount,
+    #[error("Queue parameters are inconsistent")]
+    BadQueueParameters,
 }
 
 impl Root {
@@ -110,6 +116,20 @@ impl Root {
         let (network, block_sync, transaction_gossiper) = self.network.parse();
         let logger = self.logger;
         let queue = self.queue;
+        if let Some(shard_amount) = queue.shard_amount {
+            if shard_amount.get() < 2 || !shard_amount.is_power_of_two() {
+                emitter.emit(
+                    Report::new(ParseError::BadQueueShardAmount)
+                        .attach_printable(format!("got {shard_amount}")),
+                );
+            }
+        }
+        let queue = queue.parse();
+        if let Err(issues) = queue.validate() {
+            for issue in issues {
+                emitter.emit(Report::new(ParseError::BadQueueParameters).attach_printable(issue));
+            }
+        }
         let snapshot = self.snapshot;
         let dev_telemetry = self.dev_telemetry;
         let (torii, live_query_store) = self.torii.parse();
@@ -146,7 +166,7 @@ impl Root {
             transaction_gossiper,
             live_query_store,
             logger,
-            queue: queue.parse(),
+            queue,
             snapshot,
             telemetry,
             dev_telemetry,
@@ -312,7 +332,7 @@ impl Network {
     }
 }
 
-#[derive(Debug, Clone, Copy, ReadConfig)]
+#[derive(Debug, Clone, ReadConfig)]
 pub struct Queue {
     /// The upper limit of the number of transactions waiting in the queue.
     #[config(default = "defaults::queue::CAPACITY")]
@@ -324,9 +344,62 @@ pub struct Queue {
     /// The transaction will be dropped after this time if it is still in the queue.
     #[config(default = "defaults::queue::TRANSACTION_TIME_TO_LIVE.into()")]
     pub transaction_time_to_live_ms: DurationMs,
+    /// Transactions declaring a longer time to live are rejected.
+    /// The effective time to live is still limited by `transaction_time_to_live_ms`.
+    #[config(default = "defaults::queue::MAX_TRANSACTION_TIME_TO_LIVE.into()")]
+    pub max_transaction_time_to_live_ms: DurationMs,
     /// The threshold to determine if a transaction has been tampered to have a future timestamp.
     #[config(default = "defaults::queue::FUTURE_THRESHOLD.into()")]
     pub future_threshold_ms: DurationMs,
+    /// Expired transactions are no longer put into blocks, but are announced as expired and removed
+    /// from the queue only after this period, so that peers with slightly different clocks agree on it.
+    #[config(default = "defaults::queue::EXPIRY_GRACE.into()")]
+    pub expiry_grace_ms: DurationMs,
+    /// The interval between removals of expired transactions from the queue.
+    #[config(default = "defaults::queue::REAPING_INTERVAL.into()")]
+    pub reaping_interval_ms: DurationMs,
+    /// When the queue is full, evict the transaction expiring soonest to admit a new one
+    /// instead of rejecting the new transaction.
+    #[config(default)]
+    pub evict_on_full: bool,
+    /// The number of events subscribers can lag behind before `events_on_overflow` applies.
+    #[config(default = "defaults::queue::EVENTS_CAPACITY")]
+    pub events_capacity: NonZeroUsize,
+    /// Whether events are dropped for lagging subscribers or the queue waits for them to catch up.
+    /// Waiting can stall pushing of transactions if a subscriber is slow.
+    #[config(default)]
+    pub events_on_overflow: QueueEventsOverflow,
+    /// Transactions waiting in the queue longer than this are collected for a block only after fresher ones.
+    /// Unlike the time to live, it doesn't drop transactions.
+    pub soft_age_threshold_ms: Option<DurationMs>,
+    /// The number of shards of the map of transactions in the queue, a power of two greater than one.
+    /// More shards reduce contention between concurrent pushes. Chosen by the number of CPUs by default.
+    pub shard_amount: Option<NonZeroUsize>,
+    /// The maximum number of transactions pinned to be included into the next block at the same time.
+    #[config(default = "defaults::queue::MAX_PINNED_TRANSACTIONS")]
+    pub max_pinned_transactions: usize,
+    /// Transactions containing instructions of these kinds are rejected, e.g. to disable transfers during an incident.
+    #[config(default)]
+    pub denied_instruction_kinds: BTreeSet<InstructionType>,
+    /// Whether to emit events for transactions dropped from the queue as expired.
+    /// Expired transactions are removed regardless.
+    #[config(default = "defaults::queue::EMIT_EXPIRY_EVENTS")]
+    pub emit_expiry_events: bool,
+    /// The number of the most recent push rejections kept for debugging, see `Queue::recent_rejections`.
+    /// Zero disables the log.
+    #[config(default = "defaults::queue::REJECTION_LOG_CAPACITY")]
+    pub rejection_log_capacity: usize,
+    /// The maximum number of transactions a single user can push in a burst.
+    /// Unlike `capacity_per_user`, it limits the rate of pushes rather than the number of queued transactions.
+    /// Unlimited by default.
+    pub rate_limit_burst: Option<NonZeroUsize>,
+    /// The time after which a rate limited user can push one more transaction, see `rate_limit_burst`.
+    #[config(default = "defaults::queue::RATE_LIMIT_REFILL_INTERVAL.into()")]
+    pub rate_limit_refill_interval_ms: DurationMs,
+    /// The upper limit of the total number of instructions in all transactions waiting in the queue,
+    /// bounding the work to validate them. A smart contract counts as a single instruction.
+    /// Unlimited by default.
+    pub max_total_instructions: Option<NonZeroUsize>,
 }
 
 impl Queue {
@@ -335,13 +408,43 @@ impl Queue {
             capacity,
             capacity_per_user,
             transaction_time_to_live_ms: transaction_time_to_live,
+            max_transaction_time_to_live_ms: max_transaction_time_to_live,
             future_threshold_ms: future_threshold,
+            expiry_grace_ms: expiry_grace,
+            reaping_interval_ms: reaping_interval,
+            evict_on_full,
+            events_capacity,
+            events_on_overflow,
+            soft_age_threshold_ms: soft_age_threshold,
+            shard_amount,
+            max_pinned_transactions,
+            denied_instruction_kinds,
+            emit_expiry_events,
+            rejection_log_capacity,
+            rate_limit_burst,
+            rate_limit_refill_interval_ms: rate_limit_refill_interval,
+            max_total_instructions,
         } = self;
         actual::Queue {
             capacity,
             capacity_per_user,
             transaction_time_to_live: transaction_time_to_live.0,
+            max_transaction_time_to_live: max_transaction_time_to_live.0,
             future_threshold: future_threshold.0,
+            expiry_grace: expiry_grace.0,
+            reaping_interval: reaping_interval.0,
+            evict_on_full,
+            events_capacity,
+            events_on_overflow,
+            soft_age_threshold: soft_age_threshold.map(|threshold| threshold.0),
+            shard_amount,
+            max_pinned_transactions,
+            denied_instruction_kinds,
+            emit_expiry_events,
+            rejection_log_capacity,
+            rate_limit_burst,
+            rate_limit_refill_interval: rate_limit_refill_interval.0,
+            max_total_instructions,
         }
     }
 }
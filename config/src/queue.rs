@@ -0,0 +1,147 @@
+//! Configuration related to Queue specifically
+
+use core::{num::NonZeroUsize, time::Duration};
+
+/// What the queue does when subscribers lag behind its events
+#[derive(
+    Copy,
+    Clone,
+    Debug,
+    Default,
+    PartialEq,
+    Eq,
+    strum::Display,
+    strum::EnumString,
+    serde_with::SerializeDisplay,
+    serde_with::DeserializeFromStr,
+)]
+#[strum(serialize_all = "snake_case")]
+pub enum EventsOverflow {
+    /// Send events anyway, lagging subscribers miss the oldest events
+    #[default]
+    Drop,
+    /// Wait for lagging subscribers to catch up before sending more events
+    Block,
+}
+
+/// Relationship between queue parameters that makes no sense, see [`Queue::validate`](crate::parameters::actual::Queue::validate)
+#[derive(thiserror::Error, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConfigIssue {
+    /// A single user could never fill its share of the queue
+    #[error("`capacity_per_user` ({capacity_per_user}) is greater than `capacity` ({capacity})")]
+    CapacityPerUserExceedsCapacity {
+        /// Configured `capacity`
+        capacity: NonZeroUsize,
+        /// Configured `capacity_per_user`
+        capacity_per_user: NonZeroUsize,
+    },
+    /// Transactions from the future would be accepted only to expire right away
+    #[error("`future_threshold` ({future_threshold:?}) is greater than `transaction_time_to_live` ({transaction_time_to_live:?})")]
+    FutureThresholdExceedsTimeToLive {
+        /// Configured `future_threshold`
+        future_threshold: Duration,
+        /// Configured `transaction_time_to_live`
+        transaction_time_to_live: Duration,
+    },
+    /// Transactions would expire before they are deprioritized as old
+    #[error("`soft_age_threshold` ({soft_age_threshold:?}) is not less than `transaction_time_to_live` ({transaction_time_to_live:?})")]
+    SoftAgeThresholdExceedsTimeToLive {
+        /// Configured `soft_age_threshold`
+        soft_age_threshold: Duration,
+        /// Configured `transaction_time_to_live`
+        transaction_time_to_live: Duration,
+    },
+}
+
+#[cfg(test)]
+mod tests {
+    use core::time::Duration;
+
+    use nonzero_ext::nonzero;
+
+    use crate::{
+        parameters::actual::Queue,
+        queue::{ConfigIssue, EventsOverflow},
+    };
+
+    fn valid_config() -> Queue {
+        Queue {
+            capacity: nonzero!(100_usize),
+            capacity_per_user: nonzero!(10_usize),
+            transaction_time_to_live: Duration::from_secs(100),
+            future_threshold: Duration::from_secs(1),
+            soft_age_threshold: Some(Duration::from_secs(50)),
+            ..Queue::default()
+        }
+    }
+
+    #[test]
+    fn valid_config_has_no_issues() {
+        assert_eq!(valid_config().validate(), Ok(()));
+        assert_eq!(Queue::default().validate(), Ok(()));
+    }
+
+    #[test]
+    fn capacity_per_user_greater_than_capacity_is_reported() {
+        let config = Queue {
+            capacity_per_user: nonzero!(101_usize),
+            ..valid_config()
+        };
+        assert_eq!(
+            config.validate(),
+            Err(vec![ConfigIssue::CapacityPerUserExceedsCapacity {
+                capacity: nonzero!(100_usize),
+                capacity_per_user: nonzero!(101_usize),
+            }])
+        );
+    }
+
+    #[test]
+    fn future_threshold_greater_than_time_to_live_is_reported() {
+        let config = Queue {
+            future_threshold: Duration::from_secs(101),
+            ..valid_config()
+        };
+        assert_eq!(
+            config.validate(),
+            Err(vec![ConfigIssue::FutureThresholdExceedsTimeToLive {
+                future_threshold: Duration::from_secs(101),
+                transaction_time_to_live: Duration::from_secs(100),
+            }])
+        );
+    }
+
+    #[test]
+    fn soft_age_threshold_not_less_than_time_to_live_is_reported() {
+        let config = Queue {
+            soft_age_threshold: Some(Duration::from_secs(100)),
+            ..valid_config()
+        };
+        assert_eq!(
+            config.validate(),
+            Err(vec![ConfigIssue::SoftAgeThresholdExceedsTimeToLive {
+                soft_age_threshold: Duration::from_secs(100),
+                transaction_time_to_live: Duration::from_secs(100),
+            }])
+        );
+    }
+
+    #[test]
+    fn all_issues_are_reported() {
+        let config = Queue {
+            capacity_per_user: nonzero!(101_usize),
+            future_threshold: Duration::from_secs(101),
+            soft_age_threshold: Some(Duration::from_secs(101)),
+            ..valid_config()
+        };
+        assert_eq!(config.validate().unwrap_err().len(), 3);
+    }
+
+    #[test]
+    fn events_overflow_display_form() {
+        assert_eq!(
+            format!("{} {}", EventsOverflow::Drop, EventsOverflow::Block),
+            "drop block"
+        );
+    }
+}
@@ -181,7 +181,22 @@ fn minimal_config_snapshot() {
                 capacity: 65536,
                 capacity_per_user: 65536,
                 transaction_time_to_live: 86400s,
+                max_transaction_time_to_live: 2592000s,
                 future_threshold: 1s,
+                expiry_grace: 0ns,
+                reaping_interval: 60s,
+                evict_on_full: false,
+                events_capacity: 10000,
+                events_on_overflow: Drop,
+                soft_age_threshold: None,
+                shard_amount: None,
+                max_pinned_transactions: 16,
+                denied_instruction_kinds: {},
+                emit_expiry_events: true,
+                rejection_log_capacity: 0,
+                rate_limit_burst: None,
+                rate_limit_refill_interval: 100ms,
+                max_total_instructions: None,
             },
             snapshot: Snapshot {
                 mode: ReadWrite,
@@ -242,6 +257,33 @@ fn extra_fields() {
     assert_contains!(format!("{error:?}"), "unknown parameter: `foo`");
 }
 
+#[test]
+fn bad_queue_shard_amount() {
+    let error = load_config_from_fixtures("bad.queue_shard_amount.toml")
+        .expect_err("should fail with shard amount not being a power of two");
+
+    assert_contains!(
+        format!("{error:?}"),
+        "Queue shard amount must be a power of two greater than one"
+    );
+}
+
+#[test]
+fn bad_queue_parameters() {
+    let error = load_config_from_fixtures("bad.queue_parameters.toml")
+        .expect_err("should fail with inconsistent queue parameters");
+
+    assert_contains!(format!("{error:?}"), "Queue parameters are inconsistent");
+    assert_contains!(
+        format!("{error:?}"),
+        "`capacity_per_user` (20) is greater than `capacity` (10)"
+    );
+    assert_contains!(
+        format!("{error:?}"),
+        "`soft_age_threshold` (1s) is not less than `transaction_time_to_live` (1s)"
+    );
+}
+
 /// Aims the purpose of checking that every single provided env variable is consumed and parsed
 /// into a valid config.
 #[test]
@@ -0,0 +1,257 @@
+#![allow(missing_docs)]
+
+use std::{
+    sync::atomic::{AtomicBool, Ordering},
+    thread,
+};
+
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion, Throughput};
+use iroha_config::parameters::actual::Queue as Config;
+use iroha_core::{
+    kura::Kura,
+    prelude::*,
+    query::store::LiveQueryStore,
+    queue::Queue,
+    state::{State, World},
+};
+use iroha_data_model::{parameter::TransactionParameters, prelude::*};
+use iroha_primitives::time::TimeSource;
+use nonzero_ext::nonzero;
+use test_samples::gen_account_in;
+use tokio::sync::broadcast;
+
+const TRANSACTION_LIMITS: TransactionParameters =
+    TransactionParameters::new(nonzero!(4096_u64), nonzero!(1_u64));
+
+/// Capacity of the queue and the capacity per user benchmarked
+const CAPACITIES: [(usize, usize); 3] = [(1_000, 10), (1_000, 1_000), (10_000, 100)];
+
+/// Number of threads pushing transactions concurrently
+const PRODUCERS: [usize; 3] = [1, 4, 8];
+
+fn build_state() -> State {
+    State::new(
+        World::new(),
+        Kura::blank_kura_for_testing(),
+        LiveQueryStore::test().start(),
+    )
+}
+
+fn build_queue(capacity: usize, capacity_per_user: usize) -> Queue {
+    let (events_sender, _) = broadcast::channel(1);
+    Queue::from_config(
+        Config {
+            capacity: capacity.try_into().unwrap(),
+            capacity_per_user: capacity_per_user.try_into().unwrap(),
+            ..Config::default()
+        },
+        events_sender,
+    )
+    .expect("Valid queue configuration")
+}
+
+/// Build `n` distinct transactions by as few accounts as fit into `capacity_per_user`.
+///
+/// All transactions are created at the same time, so that only the queue is measured.
+fn build_transactions(n: usize, capacity_per_user: usize) -> Vec<AcceptedTransaction> {
+    let chain_id = ChainId::from("00000000-0000-0000-0000-000000000000");
+    let time_source = TimeSource::new_fixed(TimeSource::new_system().get_unix_time());
+
+    let mut transactions = Vec::with_capacity(n);
+    while transactions.len() < n {
+        let (account_id, key_pair) = gen_account_in("wonderland");
+        let per_account = capacity_per_user.min(n - transactions.len());
+        transactions.extend((0..per_account).map(|i| {
+            let domain_id: DomainId = format!("dummy{i}").parse().unwrap();
+            let tx = TransactionBuilder::new_with_time_source(
+                chain_id.clone(),
+                account_id.clone(),
+                &time_source,
+            )
+            .with_instructions([Unregister::domain(domain_id)])
+            .sign(key_pair.private_key());
+            AcceptedTransaction::accept(tx, &chain_id, TRANSACTION_LIMITS)
+                .expect("Failed to accept transaction")
+        }));
+    }
+
+    transactions
+}
+
+fn push(criterion: &mut Criterion) {
+    let state = build_state();
+    let mut group = criterion.benchmark_group("queue_push");
+
+    for (capacity, capacity_per_user) in CAPACITIES {
+        let transactions = build_transactions(capacity, capacity_per_user);
+        group.throughput(Throughput::Elements(capacity as u64));
+        group.bench_with_input(
+            BenchmarkId::from_parameter(format!("{capacity}/{capacity_per_user}")),
+            &transactions,
+            |b, transactions| {
+                b.iter_batched(
+                    || {
+                        (
+                            build_queue(capacity, capacity_per_user),
+                            transactions.clone(),
+                        )
+                    },
+                    |(queue, transactions)| {
+                        let state_view = state.view();
+                        for tx in transactions {
+                            queue.push(tx, &state_view).expect("Queue has space");
+                        }
+                        queue
+                    },
+                    BatchSize::PerIteration,
+                );
+            },
+        );
+    }
+
+    group.finish();
+}
+
+fn push_concurrent(criterion: &mut Criterion) {
+    let (capacity, capacity_per_user) = CAPACITIES[2];
+    let state = build_state();
+    let transactions = build_transactions(capacity, capacity_per_user);
+    let mut group = criterion.benchmark_group("queue_push_concurrent");
+    group.throughput(Throughput::Elements(capacity as u64));
+
+    for producers in PRODUCERS {
+        group.bench_with_input(
+            BenchmarkId::from_parameter(producers),
+            &transactions,
+            |b, transactions| {
+                b.iter_batched(
+                    || {
+                        (
+                            build_queue(capacity, capacity_per_user),
+                            transactions.clone(),
+                        )
+                    },
+                    |(queue, transactions)| {
+                        let chunk_size = transactions.len().div_ceil(producers);
+                        thread::scope(|scope| {
+                            for chunk in transactions.chunks(chunk_size) {
+                                let queue = &queue;
+                                let state = &state;
+                                scope.spawn(move || {
+                                    let state_view = state.view();
+                                    for tx in chunk {
+                                        queue
+                                            .push(tx.clone(), &state_view)
+                                            .expect("Queue has space");
+                                    }
+                                });
+                            }
+                        });
+                        queue
+                    },
+                    BatchSize::PerIteration,
+                );
+            },
+        );
+    }
+
+    group.finish();
+}
+
+/// Producers push while blocks are collected, like `concurrent_stress_test` of the queue
+fn push_while_collecting(criterion: &mut Criterion) {
+    let (capacity, capacity_per_user) = CAPACITIES[2];
+    let max_txs_in_block = nonzero!(512_usize);
+    let state = build_state();
+    let transactions = build_transactions(capacity, capacity_per_user);
+    let mut group = criterion.benchmark_group("queue_push_while_collecting");
+    group.throughput(Throughput::Elements(capacity as u64));
+
+    for producers in PRODUCERS {
+        group.bench_with_input(
+            BenchmarkId::from_parameter(producers),
+            &transactions,
+            |b, transactions| {
+                b.iter_batched(
+                    || {
+                        (
+                            build_queue(capacity, capacity_per_user),
+                            transactions.clone(),
+                        )
+                    },
+                    |(queue, transactions)| {
+                        let chunk_size = transactions.len().div_ceil(producers);
+                        let pushing = AtomicBool::new(true);
+                        thread::scope(|scope| {
+                            let (queue, state, pushing) = (&queue, &state, &pushing);
+                            scope.spawn(move || {
+                                let mut block = Vec::new();
+                                while pushing.load(Ordering::Acquire) {
+                                    block.clear();
+                                    queue.get_transactions_for_block(
+                                        &state.view(),
+                                        max_txs_in_block,
+                                        &mut block,
+                                    );
+                                }
+                            });
+                            let producers: Vec<_> = transactions
+                                .chunks(chunk_size)
+                                .map(|chunk| {
+                                    scope.spawn(move || {
+                                        let state_view = state.view();
+                                        for tx in chunk {
+                                            queue
+                                                .push(tx.clone(), &state_view)
+                                                .expect("Queue has space");
+                                        }
+                                    })
+                                })
+                                .collect();
+                            for producer in producers {
+                                producer.join().expect("Producer panicked");
+                            }
+                            pushing.store(false, Ordering::Release);
+                        });
+                        queue
+                    },
+                    BatchSize::PerIteration,
+                );
+            },
+        );
+    }
+
+    group.finish();
+}
+
+fn get_transactions_for_block(criterion: &mut Criterion) {
+    let max_txs_in_block = nonzero!(512_usize);
+    let state = build_state();
+    let mut group = criterion.benchmark_group("queue_get_transactions_for_block");
+    group.throughput(Throughput::Elements(max_txs_in_block.get() as u64));
+
+    for (capacity, capacity_per_user) in CAPACITIES {
+        let queue = build_queue(capacity, capacity_per_user);
+        for tx in build_transactions(capacity, capacity_per_user) {
+            queue.push(tx, &state.view()).expect("Queue has space");
+        }
+        group.bench_function(
+            BenchmarkId::from_parameter(format!("{capacity}/{capacity_per_user}")),
+            |b| {
+                let mut block = Vec::new();
+                b.iter(|| {
+                    block.clear();
+                    // Collected transactions stay in the queue until they are committed
+                    queue.get_transactions_for_block(&state.view(), max_txs_in_block, &mut block);
+                    block.len()
+                });
+            },
+        );
+    }
+
+    group.finish();
+}
+
+criterion_group!(pushes, push, push_concurrent, push_while_collecting);
+criterion_group!(collections, get_transactions_for_block);
+criterion_main!(pushes, collections);
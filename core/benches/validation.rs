@@ -73,6 +73,25 @@ fn build_test_and_transient_state() -> State {
     state
 }
 
+/// Number of accounts in the world used by [`validate_transaction_in_large_world`]
+const LARGE_WORLD_ACCOUNTS: usize = 10_000;
+
+fn build_large_state() -> State {
+    let kura = iroha_core::kura::Kura::blank_kura_for_testing();
+    let query_handle = LiveQueryStore::test().start();
+
+    let domain = Domain::new(STARTER_DOMAIN.clone()).build(&STARTER_ID);
+    let accounts = core::iter::once(Account::new(STARTER_ID.clone()).build(&STARTER_ID)).chain(
+        core::iter::repeat_with(|| {
+            let (account_id, _account_keypair) = gen_account_in(&*STARTER_DOMAIN);
+            Account::new(account_id.clone()).build(&account_id)
+        })
+        .take(LARGE_WORLD_ACCOUNTS),
+    );
+
+    State::new(World::with([domain], accounts, []), kura, query_handle)
+}
+
 fn accept_transaction(criterion: &mut Criterion) {
     let chain_id = ChainId::from("00000000-0000-0000-0000-000000000000");
 
@@ -134,6 +153,33 @@ fn validate_transaction(criterion: &mut Criterion) {
     println!("Success count: {success_count}, Failure count: {failure_count}");
 }
 
+/// Validation runs against a copy-on-write [`StateTransaction`](iroha_core::state::StateTransaction)
+/// which only records mutations, so its cost must not grow with the size of the world.
+fn validate_transaction_in_large_world(criterion: &mut Criterion) {
+    let chain_id = ChainId::from("00000000-0000-0000-0000-000000000000");
+
+    let transaction = AcceptedTransaction::accept(
+        build_test_transaction(chain_id.clone()).sign(STARTER_KEYPAIR.private_key()),
+        &chain_id,
+        TRANSACTION_LIMITS,
+    )
+    .expect("Failed to accept transaction.");
+    let mut success_count = 0;
+    let mut failure_count = 0;
+    let state = build_large_state();
+    let _ = criterion.bench_function("validate_large_world", move |b| {
+        let transaction_executor = TransactionExecutor::new(TRANSACTION_LIMITS);
+        b.iter(|| {
+            let mut state_block = state.block();
+            match transaction_executor.validate(transaction.clone(), &mut state_block) {
+                Ok(_) => success_count += 1,
+                Err(_) => failure_count += 1,
+            }
+        });
+    });
+    println!("Success count: {success_count}, Failure count: {failure_count}");
+}
+
 fn sign_blocks(criterion: &mut Criterion) {
     let chain_id = ChainId::from("00000000-0000-0000-0000-000000000000");
 
@@ -170,7 +216,8 @@ criterion_group!(
     transactions,
     accept_transaction,
     sign_transaction,
-    validate_transaction
+    validate_transaction,
+    validate_transaction_in_large_world
 );
 criterion_group!(blocks, sign_blocks);
 criterion_main!(transactions, blocks);
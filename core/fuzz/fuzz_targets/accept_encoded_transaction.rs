@@ -0,0 +1,22 @@
+//! Accept a transaction decoded from arbitrary bytes, the same way as one submitted through Torii.
+
+#![no_main]
+
+use iroha_core::tx::AcceptedTransaction;
+use iroha_core_fuzz::{assert_within_limits, split_limits, CHAIN_ID};
+use iroha_data_model::prelude::*;
+use iroha_version::scale::DecodeVersioned as _;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let Some((limits, data)) = split_limits(data) else {
+        return;
+    };
+    let Ok(tx) = SignedTransaction::decode_all_versioned(data) else {
+        return;
+    };
+
+    if let Ok(tx) = AcceptedTransaction::accept(tx, &ChainId::from(CHAIN_ID), limits) {
+        assert_within_limits(&tx, limits);
+    }
+});
@@ -0,0 +1,54 @@
+//! Accept a properly signed transaction with arbitrary contents.
+//!
+//! Random bytes almost never carry a valid signature, so the payload is decoded from them
+//! and signed by a fixed key to reach the checks past decoding.
+
+#![no_main]
+
+use std::{sync::OnceLock, time::Duration};
+
+use iroha_core::tx::AcceptedTransaction;
+use iroha_core_fuzz::{assert_within_limits, split_limits, CHAIN_ID};
+use iroha_crypto::{Algorithm, KeyPair};
+use iroha_data_model::prelude::*;
+use libfuzzer_sys::fuzz_target;
+use parity_scale_codec::Decode;
+
+/// Contents of the transaction decoded from the fuzzer input
+#[derive(Decode)]
+struct Input {
+    domain: DomainId,
+    executable: Executable,
+    metadata: Metadata,
+    creation_time_ms: u64,
+    time_to_live_ms: Option<u64>,
+}
+
+fn key_pair() -> &'static KeyPair {
+    static KEY_PAIR: OnceLock<KeyPair> = OnceLock::new();
+    KEY_PAIR.get_or_init(|| KeyPair::from_seed(vec![0; 32], Algorithm::Ed25519))
+}
+
+fuzz_target!(|data: &[u8]| {
+    let Some((limits, mut data)) = split_limits(data) else {
+        return;
+    };
+    let Ok(input) = Input::decode(&mut data) else {
+        return;
+    };
+
+    let chain_id = ChainId::from(CHAIN_ID);
+    let authority = AccountId::new(input.domain, key_pair().public_key().clone());
+    let mut tx = TransactionBuilder::new(chain_id.clone(), authority)
+        .with_executable(input.executable)
+        .with_metadata(input.metadata);
+    tx.set_creation_time(Duration::from_millis(input.creation_time_ms));
+    if let Some(time_to_live_ms) = input.time_to_live_ms {
+        tx.set_ttl(Duration::from_millis(time_to_live_ms));
+    }
+    let tx = tx.sign(key_pair().private_key());
+
+    if let Ok(tx) = AcceptedTransaction::accept(tx, &chain_id, limits) {
+        assert_within_limits(&tx, limits);
+    }
+});
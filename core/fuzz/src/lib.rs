@@ -0,0 +1,41 @@
+//! Helpers shared by the fuzz targets of `iroha_core`
+
+use core::num::NonZeroU64;
+
+use iroha_core::tx::AcceptedTransaction;
+use iroha_data_model::{parameter::TransactionParameters, prelude::*};
+
+/// Chain id expected by the targets
+pub const CHAIN_ID: &str = "00000000-0000-0000-0000-000000000000";
+
+/// Split transaction limits off the front of `data`, so they are fuzzed along with the transaction.
+pub fn split_limits(data: &[u8]) -> Option<(TransactionParameters, &[u8])> {
+    let (max_instructions, data) = split_non_zero(data)?;
+    let (smart_contract_size, data) = split_non_zero(data)?;
+
+    Some((
+        TransactionParameters {
+            max_instructions,
+            smart_contract_size,
+        },
+        data,
+    ))
+}
+
+fn split_non_zero(data: &[u8]) -> Option<(NonZeroU64, &[u8])> {
+    let (value, data) = data.split_first_chunk::<8>()?;
+    let value = NonZeroU64::new(u64::from_le_bytes(*value)).unwrap_or(NonZeroU64::MIN);
+    Some((value, data))
+}
+
+/// Assert that an accepted transaction respects the `limits` it was accepted with.
+pub fn assert_within_limits(tx: &AcceptedTransaction, limits: TransactionParameters) {
+    match tx.as_ref().instructions() {
+        Executable::Instructions(instructions) => {
+            assert!(instructions.len() as u64 <= limits.max_instructions.get());
+        }
+        Executable::Wasm(smart_contract) => {
+            assert!(smart_contract.size_bytes() as u64 <= limits.smart_contract_size.get());
+        }
+    }
+}
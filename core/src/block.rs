@@ -1267,6 +1267,178 @@ mod tests {
         );
     }
 
+    #[tokio::test]
+    async fn instructions_are_executed_in_payload_order() {
+        let chain_id = ChainId::from("00000000-0000-0000-0000-000000000000");
+
+        // Predefined world state
+        let (alice_id, alice_keypair) = gen_account_in("wonderland");
+        let account = Account::new(alice_id.clone()).build(&alice_id);
+        let domain_id = DomainId::from_str("wonderland").expect("Valid");
+        let domain = Domain::new(domain_id).build(&alice_id);
+        let world = World::with([domain], [account], []);
+        let kura = Kura::blank_kura_for_testing();
+        let query_handle = LiveQueryStore::test().start();
+        let state = State::new(world, kura, query_handle);
+        let mut state_block = state.block();
+        let transaction_limits = state_block.transaction_executor().limits;
+
+        // Registering an asset definition only succeeds after its domain is registered
+        let instructions = |domain: &str| -> [InstructionBox; 2] {
+            let domain_id = DomainId::from_str(domain).expect("Valid");
+            let asset_definition_id =
+                AssetDefinitionId::new(domain_id.clone(), "coin".parse().unwrap());
+            [
+                Register::domain(Domain::new(domain_id)).into(),
+                Register::asset_definition(AssetDefinition::numeric(asset_definition_id)).into(),
+            ]
+        };
+        let ordered = instructions("ordered");
+        let mut reversed = instructions("reversed");
+        reversed.reverse();
+
+        let tx_ordered = TransactionBuilder::new(chain_id.clone(), alice_id.clone())
+            .with_instructions(ordered.clone())
+            .sign(alice_keypair.private_key());
+        let tx_ordered =
+            AcceptedTransaction::accept(tx_ordered, &chain_id, transaction_limits).expect("Valid");
+        let tx_reversed = TransactionBuilder::new(chain_id.clone(), alice_id)
+            .with_instructions(reversed.clone())
+            .sign(alice_keypair.private_key());
+        let tx_reversed =
+            AcceptedTransaction::accept(tx_reversed, &chain_id, transaction_limits).expect("Valid");
+        assert_eq!(tx_ordered.instructions(), Some(ordered.as_slice()));
+        assert_eq!(tx_reversed.instructions(), Some(reversed.as_slice()));
+
+        let transactions = vec![tx_ordered.clone(), tx_reversed];
+        let valid_block = BlockBuilder::new(transactions)
+            .chain(0, &mut state_block)
+            .sign(alice_keypair.private_key())
+            .unpack(|_| {});
+
+        let committed_ordered = valid_block.as_ref().transactions().next().unwrap();
+        assert!(committed_ordered.error.is_none());
+        assert_eq!(
+            committed_ordered.as_ref().instructions(),
+            tx_ordered.as_ref().instructions()
+        );
+        assert!(
+            valid_block
+                .as_ref()
+                .transactions()
+                .nth(1)
+                .unwrap()
+                .error
+                .is_some(),
+            "Instructions must not be reordered to make the transaction succeed"
+        );
+    }
+
+    #[tokio::test]
+    async fn unaffordable_fee_is_rejected() {
+        let chain_id = ChainId::from("00000000-0000-0000-0000-000000000000");
+
+        // Predefined world state
+        let (alice_id, alice_keypair) = gen_account_in("wonderland");
+        let account = Account::new(alice_id.clone()).build(&alice_id);
+        let domain_id = DomainId::from_str("wonderland").expect("Valid");
+        let domain = Domain::new(domain_id).build(&alice_id);
+        let asset_definition_id = AssetDefinitionId::from_str("xor#wonderland").expect("Valid");
+        let asset_definition =
+            AssetDefinition::numeric(asset_definition_id.clone()).build(&alice_id);
+        let asset = Asset::new(
+            AssetId::new(asset_definition_id.clone(), alice_id.clone()),
+            AssetValue::Numeric(10_u32.into()),
+        );
+        let world = World::with_assets([domain], [account], [asset_definition], [asset]);
+        let kura = Kura::blank_kura_for_testing();
+        let query_handle = LiveQueryStore::test().start();
+        let state = State::new(world, kura, query_handle);
+        let mut state_block = state.block();
+        let transaction_limits = state_block.transaction_executor().limits;
+
+        let tx_with_fee = |amount: u32| {
+            let mut builder =
+                TransactionBuilder::new(chain_id.clone(), alice_id.clone()).with_instructions([
+                    Log::new(iroha_data_model::Level::DEBUG, format!("fee of {amount}")),
+                ]);
+            builder.set_fee(TransactionFee {
+                asset_definition: asset_definition_id.clone(),
+                amount: amount.into(),
+            });
+            let tx = builder.sign(alice_keypair.private_key());
+            AcceptedTransaction::accept(tx, &chain_id, transaction_limits).expect("Valid")
+        };
+        let affordable = tx_with_fee(10);
+        let unaffordable = tx_with_fee(11);
+        assert!(unaffordable.fee().is_some());
+
+        let transactions = vec![affordable, unaffordable.clone()];
+        let valid_block = BlockBuilder::new(transactions)
+            .chain(0, &mut state_block)
+            .sign(alice_keypair.private_key())
+            .unpack(|_| {});
+
+        let mut transactions = valid_block.as_ref().transactions();
+        assert!(transactions.next().unwrap().error.is_none());
+        assert_eq!(
+            transactions.next().unwrap().error,
+            Some(TransactionRejectionReason::InsufficientFee(
+                unaffordable.fee().unwrap().clone()
+            ))
+        );
+    }
+
+    #[tokio::test]
+    async fn transaction_past_valid_until_block_is_rejected() {
+        let chain_id = ChainId::from("00000000-0000-0000-0000-000000000000");
+
+        // Predefined world state
+        let (alice_id, alice_keypair) = gen_account_in("wonderland");
+        let account = Account::new(alice_id.clone()).build(&alice_id);
+        let domain_id = DomainId::from_str("wonderland").expect("Valid");
+        let domain = Domain::new(domain_id).build(&alice_id);
+        let world = World::with([domain], [account], []);
+        let kura = Kura::blank_kura_for_testing();
+        let query_handle = LiveQueryStore::test().start();
+        let state = State::new(world, kura, query_handle);
+        let mut state_block = state.block();
+        // The block built below is the second one
+        state_block
+            .block_hashes
+            .push(HashOf::from_untyped_unchecked(Hash::new([1])));
+        let transaction_limits = state_block.transaction_executor().limits;
+
+        let tx_valid_until = |height: u64| {
+            let mut builder = TransactionBuilder::new(chain_id.clone(), alice_id.clone())
+                .with_instructions([Log::new(
+                    iroha_data_model::Level::DEBUG,
+                    format!("valid until {height}"),
+                )]);
+            builder.set_valid_until_block(height.try_into().expect("Non zero"));
+            let tx = builder.sign(alice_keypair.private_key());
+            AcceptedTransaction::accept(tx, &chain_id, transaction_limits).expect("Valid")
+        };
+
+        let transactions = vec![tx_valid_until(2), tx_valid_until(1)];
+        let valid_block = BlockBuilder::new(transactions)
+            .chain(0, &mut state_block)
+            .sign(alice_keypair.private_key())
+            .unpack(|_| {});
+
+        let mut transactions = valid_block.as_ref().transactions();
+        assert!(transactions.next().unwrap().error.is_none());
+        assert_eq!(
+            transactions.next().unwrap().error,
+            Some(TransactionRejectionReason::ValidUntilBlockExceeded(
+                ValidUntilBlockExceeded {
+                    valid_until_block: 1,
+                    block_height: 2,
+                }
+            ))
+        );
+    }
+
     #[tokio::test]
     async fn genesis_public_key_is_checked() {
         let chain_id = ChainId::from("00000000-0000-0000-0000-000000000000");
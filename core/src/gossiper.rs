@@ -118,16 +118,18 @@ impl TransactionGossiper {
                     Err(crate::queue::Failure {
                         tx,
                         err: crate::queue::Error::InBlockchain,
+                        ..
                     }) => {
                         iroha_logger::debug!(tx = %tx.as_ref().hash(), "Transaction already in blockchain, ignoring...")
                     }
                     Err(crate::queue::Failure {
                         tx,
                         err: crate::queue::Error::IsInQueue,
+                        ..
                     }) => {
                         iroha_logger::trace!(tx = %tx.as_ref().hash(), "Transaction already in the queue, ignoring...")
                     }
-                    Err(crate::queue::Failure { tx, err }) => {
+                    Err(crate::queue::Failure { tx, err, .. }) => {
                         iroha_logger::error!(?err, tx = %tx.as_ref().hash(), "Failed to enqueue transaction.")
                     }
                 },
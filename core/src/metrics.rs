@@ -34,12 +34,17 @@ impl MetricsReporter {
         kura: Arc<Kura>,
         queue: Arc<Queue>,
     ) -> Self {
+        let metrics = Metrics::default();
+        metrics
+            .register(queue.metrics().clone())
+            .expect("Queue metrics are registered only once");
+
         Self {
             state,
             network,
             queue,
             kura,
-            metrics: Metrics::default(),
+            metrics,
             latest_block_height: Arc::new(Mutex::new(0)),
         }
     }
@@ -0,0 +1,88 @@
+//! Builder of a [`Queue`] for embedding it without a full node configuration
+
+use std::{num::NonZeroUsize, time::Duration};
+
+use iroha_config::{parameters::actual::Queue as Config, queue::ConfigIssue};
+use iroha_primitives::time::TimeSource;
+use tokio::sync::broadcast;
+
+use super::Queue;
+use crate::EventsSender;
+
+/// Builder of a [`Queue`], see [`Queue::builder`].
+///
+/// Parameters which aren't set are taken from [`Config::default`], except that the capacity per user
+/// is limited by the capacity of the queue. Unless given an events sender,
+/// the queue sends events into a channel without subscribers, i.e. they are dropped.
+#[derive(Debug, Default)]
+pub struct QueueBuilder {
+    config: Config,
+    capacity_per_user: Option<NonZeroUsize>,
+    time_source: Option<TimeSource>,
+    events_sender: Option<EventsSender>,
+}
+
+impl QueueBuilder {
+    /// Set the upper limit of the number of transactions in the queue.
+    #[must_use]
+    pub fn capacity(mut self, capacity: NonZeroUsize) -> Self {
+        self.config.capacity = capacity;
+        self
+    }
+
+    /// Set the upper limit of the number of transactions of a single user in the queue.
+    #[must_use]
+    pub fn capacity_per_user(mut self, capacity_per_user: NonZeroUsize) -> Self {
+        self.capacity_per_user = Some(capacity_per_user);
+        self
+    }
+
+    /// Set the time after which transactions are dropped from the queue.
+    #[must_use]
+    pub fn ttl(mut self, ttl: Duration) -> Self {
+        self.config.transaction_time_to_live = ttl;
+        self
+    }
+
+    /// Set how far in the future the creation time of a transaction may be.
+    #[must_use]
+    pub fn future_threshold(mut self, future_threshold: Duration) -> Self {
+        self.config.future_threshold = future_threshold;
+        self
+    }
+
+    /// Set the source of time, e.g. a mock one to control expiry. The system time is used by default.
+    #[must_use]
+    pub fn time_source(mut self, time_source: TimeSource) -> Self {
+        self.time_source = Some(time_source);
+        self
+    }
+
+    /// Set the sender of the events of the queue.
+    #[must_use]
+    pub fn events_sender(mut self, events_sender: EventsSender) -> Self {
+        self.events_sender = Some(events_sender);
+        self
+    }
+
+    /// Build the [`Queue`].
+    ///
+    /// # Errors
+    /// Fails with every issue found by [`Config::validate`], e.g. if the capacity per user exceeds the capacity.
+    pub fn build(self) -> Result<Queue, Vec<ConfigIssue>> {
+        let Self {
+            mut config,
+            capacity_per_user,
+            time_source,
+            events_sender,
+        } = self;
+        config.capacity_per_user =
+            capacity_per_user.unwrap_or_else(|| config.capacity_per_user.min(config.capacity));
+
+        Queue::with_time_source(
+            config,
+            events_sender.unwrap_or_else(|| broadcast::channel(1).0),
+            time_source.unwrap_or_else(TimeSource::new_system),
+        )
+    }
+}
@@ -0,0 +1,131 @@
+//! Lazy removal of transactions for a block from the [`Queue`], see [`Queue::drain_for_block`]
+
+use std::{collections::VecDeque, num::NonZeroUsize, time::Duration};
+
+use dashmap::mapref::entry::Entry;
+
+use super::{CollectionGuard, PopStep, Queue, QueueStateProbe, Reaped, Seen};
+use crate::prelude::*;
+
+/// Iterator removing transactions for a block from the [`Queue`].
+///
+/// Unlike [`Queue::get_transactions_for_block`], yielded transactions are removed from the queue:
+/// their authorities' per user counts are decreased and the space they took is freed right away.
+/// The caller owns them and is responsible for pushing back the ones it doesn't put into a block.
+///
+/// Transactions which were inspected but not yielded are returned to the queue when the iterator is dropped.
+pub struct DrainForBlock<'queue, S: QueueStateProbe> {
+    queue: &'queue Queue,
+    state_view: &'queue S,
+    remaining: usize,
+    time_padding: Duration,
+    seen: Seen,
+    reaped: Reaped,
+    old_transactions: VecDeque<AcceptedTransaction>,
+    /// Released after the inspected transactions are returned to the queue
+    _collection: CollectionGuard<'queue>,
+}
+
+impl Queue {
+    /// Lazily remove up to `max_txs_in_block` transactions for a block from the queue, see [`DrainForBlock`].
+    ///
+    /// Transactions expiring within `time_padding` are left in the queue since they would likely expire
+    /// before the block is committed. Transactions older than the soft age threshold are yielded
+    /// only after the queue is exhausted.
+    ///
+    /// Waits for a concurrent collection of transactions for a block to finish,
+    /// other collections wait until the returned iterator is dropped.
+    pub fn drain_for_block<'queue, S: QueueStateProbe>(
+        &'queue self,
+        state_view: &'queue S,
+        max_txs_in_block: NonZeroUsize,
+        time_padding: Duration,
+    ) -> DrainForBlock<'queue, S> {
+        let collection = self.lock_collection();
+        // Transactions of the previous drain are either requeued or left the queue for good by now
+        self.drained_first_seen.clear();
+        DrainForBlock {
+            queue: self,
+            state_view,
+            remaining: max_txs_in_block.get(),
+            time_padding,
+            seen: Seen::new(),
+            reaped: Reaped::default(),
+            old_transactions: VecDeque::new(),
+            _collection: collection,
+        }
+    }
+}
+
+impl<S: QueueStateProbe> DrainForBlock<'_, S> {
+    /// Remove popped `tx` from the queue, unless it was removed concurrently.
+    fn take(&mut self, tx: AcceptedTransaction) -> Option<AcceptedTransaction> {
+        let hash = tx.as_ref().hash();
+        match self.queue.accepted_txs.entry(hash) {
+            Entry::Occupied(entry) => entry.remove(),
+            Entry::Vacant(_) => {
+                // Its hash is no longer in the sub-queues, so the tombstone would never be cleared.
+                // Cleared under the entry, so that a concurrent push can't take over the missing hash
+                self.queue.tombstones.remove(&hash);
+                return None;
+            }
+        };
+        if let Some((hash, first_seen)) = self.queue.first_seen.remove(&hash) {
+            self.queue.drained_first_seen.insert(hash, first_seen);
+        }
+        self.queue.forget(&tx);
+        self.queue.notify_space_freed();
+        self.remaining -= 1;
+        Some(tx)
+    }
+}
+
+impl<S: QueueStateProbe> Iterator for DrainForBlock<'_, S> {
+    type Item = AcceptedTransaction;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.remaining > 0 {
+            match self.queue.pop_step(
+                &mut self.seen,
+                self.state_view,
+                &|_| false,
+                &mut self.reaped,
+            ) {
+                PopStep::Skipped | PopStep::Vacant => {}
+                PopStep::Popped(tx) => {
+                    if self.queue.expires_within(&tx, self.time_padding) {
+                        // Stays in `seen` and is returned to the queue
+                        continue;
+                    }
+                    if self.queue.is_old(&tx) {
+                        self.old_transactions.push_back(tx);
+                        continue;
+                    }
+                    self.seen.pop();
+                    if let Some(tx) = self.take(tx) {
+                        return Some(tx);
+                    }
+                }
+                PopStep::Empty => {
+                    let tx = self.old_transactions.pop_front()?;
+                    let hash = tx.as_ref().hash();
+                    self.seen.shift_remove(&hash);
+                    if let Some(tx) = self.take(tx) {
+                        return Some(tx);
+                    }
+                }
+            }
+        }
+
+        None
+    }
+}
+
+impl<S: QueueStateProbe> Drop for DrainForBlock<'_, S> {
+    fn drop(&mut self) {
+        self.queue.finish_collection(
+            core::mem::take(&mut self.seen),
+            core::mem::take(&mut self.reaped),
+        );
+    }
+}
@@ -0,0 +1,172 @@
+//! Delivery of the [`Queue`](super::Queue) events to subscribers
+
+use std::{num::NonZeroUsize, thread, time::Duration};
+
+use iroha_config::queue::EventsOverflow;
+use iroha_data_model::events::EventBox;
+use tokio::runtime::{Handle, RuntimeFlavor};
+
+use crate::EventsSender;
+
+/// How long to wait before checking again whether lagging subscribers caught up
+const BACKOFF: Duration = Duration::from_millis(1);
+
+/// Sender of the queue events applying the configured overflow policy.
+///
+/// With [`EventsOverflow::Drop`] events are broadcast right away
+/// and subscribers lagging behind by more than the capacity of the channel silently miss events.
+/// With [`EventsOverflow::Block`] sending waits while subscribers lag behind by `capacity` events,
+/// so no events are missed as long as `capacity` doesn't exceed the capacity of the channel.
+/// The channel is shared by all events of the node, so its other events count towards the lag as well:
+/// they are just as likely to push the queue events out of the channel.
+/// The tradeoff is that a slow subscriber stalls pushing of transactions into the queue.
+///
+/// Waiting blocks the calling thread. On a worker of a multi-threaded Tokio runtime its other tasks are
+/// handed over to another thread first (see [`tokio::task::block_in_place`]), so that they, including
+/// the lagging subscriber, keep running. A single-threaded runtime has no other thread to hand them over to,
+/// so transactions must not be pushed from its tasks in this mode.
+#[derive(Debug)]
+pub struct QueueEvents {
+    sender: EventsSender,
+    capacity: NonZeroUsize,
+    on_overflow: EventsOverflow,
+}
+
+impl QueueEvents {
+    /// Construct [`Self`]
+    pub fn new(sender: EventsSender, capacity: NonZeroUsize, on_overflow: EventsOverflow) -> Self {
+        Self {
+            sender,
+            capacity,
+            on_overflow,
+        }
+    }
+
+    /// Send the event to all subscribers, applying the overflow policy.
+    pub fn send(&self, event: EventBox) {
+        if self.on_overflow == EventsOverflow::Block && self.is_lagging() {
+            Self::block_off_runtime(|| {
+                while self.is_lagging() {
+                    thread::sleep(BACKOFF);
+                }
+            });
+        }
+        let _ = self.sender.send(event);
+    }
+
+    /// Whether subscribers lag behind by `capacity` events.
+    fn is_lagging(&self) -> bool {
+        self.sender.receiver_count() > 0 && self.sender.len() >= self.capacity.get()
+    }
+
+    /// Run blocking `wait`, moving other tasks off the current worker of a multi-threaded runtime, if any.
+    fn block_off_runtime(wait: impl FnOnce()) {
+        match Handle::try_current() {
+            Ok(handle) if handle.runtime_flavor() == RuntimeFlavor::MultiThread => {
+                tokio::task::block_in_place(wait);
+            }
+            _ => wait(),
+        }
+    }
+
+    /// Same as [`Self::send`], but the event is built only if there are subscribers.
+    ///
+    /// Nodes running without event consumers skip building events in hot paths altogether.
+    pub fn send_with(&self, event: impl FnOnce() -> EventBox) {
+        if self.has_subscribers() {
+            self.send(event());
+        }
+    }
+
+    /// Whether anyone is subscribed to the events.
+    pub fn has_subscribers(&self) -> bool {
+        self.sender.receiver_count() > 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use iroha_crypto::{Hash, HashOf};
+    use iroha_data_model::events::pipeline::{TransactionEvent, TransactionStatus};
+    use nonzero_ext::nonzero;
+
+    use super::*;
+
+    fn event() -> TransactionEvent {
+        TransactionEvent {
+            hash: HashOf::from_untyped_unchecked(Hash::prehashed([0_u8; Hash::LENGTH])),
+            block_height: None,
+            status: TransactionStatus::Queued,
+        }
+    }
+
+    #[test]
+    fn block_waits_for_lagging_subscriber() {
+        let (sender, mut receiver) = tokio::sync::broadcast::channel(2);
+        let events = QueueEvents::new(sender, nonzero!(1_usize), EventsOverflow::Block);
+        events.send(event().into());
+
+        thread::scope(|scope| {
+            let second = scope.spawn(|| events.send(event().into()));
+            thread::sleep(Duration::from_millis(20));
+            assert!(!second.is_finished());
+
+            receiver.try_recv().expect("First event is sent");
+            second.join().unwrap();
+        });
+        receiver.try_recv().expect("Second event is sent");
+    }
+
+    #[test]
+    fn block_keeps_runtime_running() {
+        // The subscriber can only run if the blocked worker hands it over to another thread
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(1)
+            .enable_all()
+            .build()
+            .unwrap();
+        let (sender, mut receiver) = tokio::sync::broadcast::channel(2);
+        let events = Arc::new(QueueEvents::new(
+            sender,
+            nonzero!(1_usize),
+            EventsOverflow::Block,
+        ));
+
+        events.send(event().into());
+
+        runtime.block_on(async {
+            let subscriber = tokio::spawn(async move {
+                // Lag behind, so that the next event is sent only after the sender started blocking
+                tokio::time::sleep(Duration::from_millis(20)).await;
+                for _ in 0..2 {
+                    receiver.recv().await.expect("Event is sent");
+                }
+            });
+            let sender = tokio::spawn({
+                let events = Arc::clone(&events);
+                async move { events.send(event().into()) }
+            });
+
+            tokio::time::timeout(Duration::from_secs(10), async {
+                sender.await.unwrap();
+                subscriber.await.unwrap();
+            })
+            .await
+            .expect("Subscriber kept running while sending was blocked");
+        });
+    }
+
+    #[test]
+    fn event_is_built_only_for_subscribers() {
+        let (sender, receiver) = tokio::sync::broadcast::channel(2);
+        let events = QueueEvents::new(sender, nonzero!(1_usize), EventsOverflow::Drop);
+        drop(receiver);
+        events.send_with(|| panic!("Event is built without subscribers"));
+
+        let mut receiver = events.sender.subscribe();
+        events.send_with(|| event().into());
+        receiver.try_recv().expect("Event is sent");
+    }
+}
@@ -0,0 +1,71 @@
+//! Health verdict of the [`Queue`] for liveness and readiness probes, see [`Queue::health_check`]
+
+use super::Queue;
+
+/// Share of the capacity above which the queue is reported as [`QueueHealth::Degraded`], in percent
+const DEGRADED_FULLNESS_PERCENT: usize = 90;
+/// Number of consistent readings required to report corrupted accounting
+const ACCOUNTING_READINGS: usize = 3;
+
+/// Health of the [`Queue`]
+#[derive(Debug, Clone, PartialEq, Eq, displaydoc::Display)]
+pub enum QueueHealth {
+    /// Queue is healthy
+    Healthy,
+    /// Queue is degraded: {reason}
+    Degraded {
+        /// Why the queue is degraded
+        reason: String,
+    },
+    /// Queue is unhealthy: {reason}
+    Unhealthy {
+        /// Why the queue is unhealthy
+        reason: String,
+    },
+}
+
+impl Queue {
+    /// Check the health of the queue.
+    ///
+    /// The queue is unhealthy if its accounting is corrupted, i.e. the sub-queues hold hashes
+    /// neither of stored transactions nor of removed ones. The reverse can't be detected reliably
+    /// since hashes of transactions being pushed or collected are missing from the sub-queues.
+    /// The queue is degraded if it's almost full.
+    ///
+    /// Doesn't iterate over transactions, so it's cheap enough to be called frequently.
+    pub fn health_check(&self) -> QueueHealth {
+        // Concurrent operations can make a single reading inconsistent, while corruption persists
+        let corrupted = (0..ACCOUNTING_READINGS)
+            .map(|_| self.leaked_hashes())
+            .reduce(Option::and)
+            .flatten();
+        if let Some((queued, stored)) = corrupted {
+            return QueueHealth::Unhealthy {
+                reason: format!(
+                    "{queued} hashes are queued, but only {stored} transactions are stored or removed"
+                ),
+            };
+        }
+
+        let len = self.accepted_txs.len();
+        let capacity = self.capacity.get();
+        if len.saturating_mul(100) >= capacity.saturating_mul(DEGRADED_FULLNESS_PERCENT) {
+            return QueueHealth::Degraded {
+                reason: format!("{len} out of {capacity} transactions are queued"),
+            };
+        }
+
+        QueueHealth::Healthy
+    }
+
+    /// Numbers of queued hashes and of stored or removed transactions, if the former is greater.
+    fn leaked_hashes(&self) -> Option<(usize, usize)> {
+        let queued: usize = self
+            .sub_queues
+            .iter()
+            .map(|sub_queue| sub_queue.tx_hashes.len())
+            .sum();
+        let stored = self.accepted_txs.len() + self.tombstones.len();
+        (queued > stored).then_some((queued, stored))
+    }
+}
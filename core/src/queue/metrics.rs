@@ -0,0 +1,160 @@
+//! Prometheus metrics of the [`Queue`](super::Queue)
+
+use std::collections::BTreeMap;
+
+use prometheus::{
+    core::{Collector, Desc},
+    proto::MetricFamily,
+    IntCounter, IntCounterVec, IntGauge, Opts,
+};
+
+use super::Error;
+
+/// Metrics updated by the [`Queue`](super::Queue) itself.
+///
+/// Register them in the node metrics registry to export them.
+#[derive(Debug, Clone)]
+pub struct QueueMetrics {
+    /// Number of transactions in the queue
+    pub len: IntGauge,
+    /// Number of transactions successfully pushed into the queue
+    pub pushes_total: IntCounter,
+    /// Number of previously removed transactions successfully pushed back into the queue
+    pub requeues_total: IntCounter,
+    /// Number of transactions rejected by the queue, by reason
+    pub rejections_total: IntCounterVec,
+    /// Number of transactions dropped from the queue due to expiration
+    pub expired_total: IntCounter,
+    /// Number of hashes popped from the queue without a transaction
+    pub vacant_pops_total: IntCounter,
+}
+
+impl Default for QueueMetrics {
+    fn default() -> Self {
+        let len = IntGauge::new("iroha_queue_len", "Number of transactions in the queue")
+            .expect("Infallible");
+        let pushes_total = IntCounter::new(
+            "iroha_queue_pushes_total",
+            "Transactions successfully pushed into the queue",
+        )
+        .expect("Infallible");
+        let requeues_total = IntCounter::new(
+            "iroha_queue_requeues_total",
+            "Previously removed transactions successfully pushed back into the queue",
+        )
+        .expect("Infallible");
+        let rejections_total = IntCounterVec::new(
+            Opts::new(
+                "iroha_queue_rejections_total",
+                "Transactions rejected by the queue",
+            ),
+            &["reason"],
+        )
+        .expect("Infallible");
+        let expired_total = IntCounter::new(
+            "iroha_queue_expired_total",
+            "Transactions dropped from the queue due to expiration",
+        )
+        .expect("Infallible");
+        let vacant_pops_total = IntCounter::new(
+            "iroha_queue_vacant_pops_total",
+            "Hashes popped from the queue without a transaction",
+        )
+        .expect("Infallible");
+
+        Self {
+            len,
+            pushes_total,
+            requeues_total,
+            rejections_total,
+            expired_total,
+            vacant_pops_total,
+        }
+    }
+}
+
+impl QueueMetrics {
+    pub(super) fn record_rejection(&self, err: &Error) {
+        self.rejections_total
+            .with_label_values(&[reason_label(err)])
+            .inc();
+    }
+
+    /// Number of rejected transactions by the `reason` label, only reasons with rejections are included.
+    pub fn rejection_counts(&self) -> BTreeMap<&'static str, u64> {
+        self.rejections_total
+            .collect()
+            .iter()
+            .flat_map(MetricFamily::get_metric)
+            .filter_map(|metric| {
+                let label = metric.get_label().first()?.get_value();
+                let reason = REASON_LABELS
+                    .iter()
+                    .copied()
+                    .find(|reason| *reason == label)?;
+                // Counters hold whole numbers, so nothing is lost
+                #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+                let count = metric.get_counter().get_value() as u64;
+                Some((reason, count))
+            })
+            .collect()
+    }
+}
+
+/// All values of the `reason` label, see [`reason_label`]
+const REASON_LABELS: &[&str] = &[
+    "full",
+    "in_future",
+    "expired",
+    "block_height_expired",
+    "ttl_too_long",
+    "instruction_kind_denied",
+    "in_blockchain",
+    "maximum_transactions_per_user",
+    "is_in_queue",
+    "duplicate_idempotency_key",
+    "rate_limited",
+    "instruction_budget_exceeded",
+    "halted",
+];
+
+/// Label value of the `reason` of a rejection, must be listed in [`REASON_LABELS`]
+fn reason_label(err: &Error) -> &'static str {
+    match err {
+        Error::Full => "full",
+        Error::InFuture => "in_future",
+        Error::Expired => "expired",
+        Error::BlockHeightExpired { .. } => "block_height_expired",
+        Error::TtlTooLong => "ttl_too_long",
+        Error::InstructionKindDenied(_) => "instruction_kind_denied",
+        Error::InBlockchain => "in_blockchain",
+        Error::MaximumTransactionsPerUser => "maximum_transactions_per_user",
+        Error::IsInQueue => "is_in_queue",
+        Error::DuplicateIdempotencyKey => "duplicate_idempotency_key",
+        Error::RateLimited => "rate_limited",
+        Error::InstructionBudgetExceeded => "instruction_budget_exceeded",
+        Error::Halted(_) => "halted",
+    }
+}
+
+impl Collector for QueueMetrics {
+    fn desc(&self) -> Vec<&Desc> {
+        let mut desc = self.len.desc();
+        desc.extend(self.pushes_total.desc());
+        desc.extend(self.requeues_total.desc());
+        desc.extend(self.rejections_total.desc());
+        desc.extend(self.expired_total.desc());
+        desc.extend(self.vacant_pops_total.desc());
+        desc
+    }
+
+    fn collect(&self) -> Vec<MetricFamily> {
+        let mut families = self.len.collect();
+        families.extend(self.pushes_total.collect());
+        families.extend(self.requeues_total.collect());
+        families.extend(self.rejections_total.collect());
+        families.extend(self.expired_total.collect());
+        families.extend(self.vacant_pops_total.collect());
+        families
+    }
+}
@@ -0,0 +1,6078 @@
+//! Module with queue actor
+use core::time::Duration;
+use std::{
+    cmp::Reverse,
+    collections::{BTreeMap, BTreeSet, VecDeque},
+    num::NonZeroUsize,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Instant,
+};
+
+use crossbeam_queue::ArrayQueue;
+use dashmap::{mapref::entry::Entry, DashMap, DashSet};
+use eyre::Result;
+use indexmap::{IndexMap, IndexSet};
+use iroha_config::{parameters::actual::Queue as Config, queue::ConfigIssue};
+use iroha_crypto::HashOf;
+use iroha_data_model::{
+    account::AccountId,
+    events::pipeline::{TransactionEvent, TransactionStatus, TransactionsExpiredEvent},
+    isi::InstructionType,
+    transaction::prelude::*,
+};
+use iroha_logger::{debug, error, info, prelude::Span, trace, trace_span, warn, Instrument as _};
+use iroha_primitives::{numeric::Numeric, time::TimeSource};
+use parity_scale_codec::Encode as _;
+use parking_lot::{Condvar, Mutex, RwLock};
+use prometheus::IntCounter;
+use rand::{
+    distributions::{Distribution as _, WeightedIndex},
+    seq::IteratorRandom,
+};
+use thiserror::Error;
+use tokio::sync::{oneshot, Notify};
+
+use self::{
+    builder::QueueBuilder, events::QueueEvents, metrics::QueueMetrics, rate_limit::TokenBucket,
+};
+use crate::{prelude::*, EventsSender};
+
+pub mod builder;
+pub mod drain;
+pub mod events;
+pub mod health;
+pub mod metrics;
+mod rate_limit;
+pub mod reaper;
+pub mod snapshot;
+
+/// State consulted by the [`Queue`] to check transactions, implemented by [`StateView`].
+///
+/// Lets the queue be tested without constructing a full [`State`](crate::state::State).
+pub trait QueueStateProbe: Sync {
+    /// Check if the transaction with `hash` is committed or rejected.
+    fn has_transaction(&self, hash: HashOf<SignedTransaction>) -> bool;
+
+    /// Height of the latest committed block.
+    fn height(&self) -> usize;
+}
+
+impl QueueStateProbe for StateView<'_> {
+    fn has_transaction(&self, hash: HashOf<SignedTransaction>) -> bool {
+        StateReadOnly::has_transaction(self, hash)
+    }
+
+    fn height(&self) -> usize {
+        StateReadOnly::height(self)
+    }
+}
+
+/// Observer of the reads of the state the [`Queue`] makes to check transactions, see [`Queue::with_observer`].
+///
+/// Meant for profiling: callbacks are invoked synchronously on the checking thread, so they should be cheap.
+/// The queue doesn't look up accounts, so only the reads of [`QueueStateProbe`] are observed.
+pub trait QueueObserver: core::fmt::Debug + Send + Sync {
+    /// Called before checking if the transaction with `hash` is committed or rejected.
+    fn on_has_transaction(&self, _hash: &HashOf<SignedTransaction>) {}
+
+    /// Called before reading the height of the latest committed block.
+    fn on_height(&self) {}
+}
+
+/// Decides when transactions in the [`Queue`] expire, see [`Queue::with_expiry_policy`].
+///
+/// The policy must be pure and deterministic, i.e. depend only on its arguments,
+/// so that all peers agree on which transactions are expired.
+pub trait ExpiryPolicy: core::fmt::Debug + Send + Sync {
+    /// Check if `tx` is expired at `now`, given the time to live from [`Config`].
+    fn is_expired(&self, tx: &AcceptedTransaction, now: Duration, config_ttl: Duration) -> bool;
+}
+
+/// Transactions expire after the time to live they declare, limited by the one from [`Config`]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DefaultExpiryPolicy;
+
+impl DefaultExpiryPolicy {
+    /// Time `tx` is allowed to wait in the queue.
+    fn time_limit(tx: &AcceptedTransaction, config_ttl: Duration) -> Duration {
+        tx.time_to_live().map_or(config_ttl, |tx_time_to_live| {
+            core::cmp::min(config_ttl, tx_time_to_live)
+        })
+    }
+}
+
+impl ExpiryPolicy for DefaultExpiryPolicy {
+    fn is_expired(&self, tx: &AcceptedTransaction, now: Duration, config_ttl: Duration) -> bool {
+        now.saturating_sub(tx.creation_time()) > Self::time_limit(tx, config_ttl)
+    }
+}
+
+/// [`QueueStateProbe`] notifying the [`QueueObserver`] of the [`Queue`] about every read
+struct ObservedState<'a, S> {
+    observer: Option<&'a dyn QueueObserver>,
+    state_view: &'a S,
+}
+
+impl<S: QueueStateProbe> QueueStateProbe for ObservedState<'_, S> {
+    fn has_transaction(&self, hash: HashOf<SignedTransaction>) -> bool {
+        if let Some(observer) = self.observer {
+            observer.on_has_transaction(&hash);
+        }
+        self.state_view.has_transaction(hash)
+    }
+
+    fn height(&self) -> usize {
+        if let Some(observer) = self.observer {
+            observer.on_height();
+        }
+        self.state_view.height()
+    }
+}
+
+impl AcceptedTransaction {
+    // TODO: We should have another type of transaction like `CheckedTransaction` in the type system?
+    /// Check if [`self`] is committed or rejected.
+    fn is_in_blockchain(&self, state_view: &impl QueueStateProbe) -> bool {
+        state_view.has_transaction(self.as_ref().hash())
+    }
+}
+
+/// Class of transactions with its own sub-queue inside of the [`Queue`], see [`Queue::with_classes`]
+#[derive(Debug, Clone)]
+pub struct TransactionClass {
+    /// Name of the class, used for diagnostics
+    pub name: String,
+    /// The maximum number of transactions of this class in the queue
+    pub capacity: NonZeroUsize,
+}
+
+/// Maps a transaction to the index of its [`TransactionClass`]
+pub struct Classifier(Box<dyn Fn(&AcceptedTransaction) -> usize + Send + Sync>);
+
+impl core::fmt::Debug for Classifier {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str("Classifier")
+    }
+}
+
+/// Outcome of a single attempt to pop a transaction from the [`Queue`]
+enum PopStep {
+    /// There are no more transactions in the queue
+    Empty,
+    /// Popped hash was stale or its transaction failed the checks and was removed
+    Skipped,
+    /// Popped hash has neither a transaction nor a tombstone, see [`Queue::pop_from_queue`]
+    Vacant,
+    /// Popped transaction can be put into a block
+    Popped(AcceptedTransaction),
+}
+
+/// Exclusive right to drain and restore the sub-queues of the [`Queue`], released on drop
+struct CollectionGuard<'queue>(&'queue Queue);
+
+impl Drop for CollectionGuard<'_> {
+    fn drop(&mut self) {
+        *self.0.collecting.lock() = false;
+        self.0.collection_finished.notify_one();
+        self.0.collection_finished_async.notify_one();
+    }
+}
+
+/// Metadata key of the idempotency key of a transaction.
+///
+/// While a transaction is in the queue, other transactions of the same authority carrying
+/// the same idempotency key are rejected with [`Error::DuplicateIdempotencyKey`].
+/// This makes client retries safe even if the retried transaction has a different hash.
+pub const IDEMPOTENCY_KEY: &str = "idempotency_key";
+
+/// Hashes popped while collecting transactions for a block with indices of their sub-queues, in the order of popping
+type Seen = IndexMap<HashOf<SignedTransaction>, usize>;
+
+/// Transactions removed from the queue while collecting transactions for a block
+#[derive(Default)]
+struct Reaped {
+    /// Expired transactions, to be announced
+    expired: Vec<AcceptedTransaction>,
+    /// Number of transactions which are already in the blockchain
+    in_blockchain: usize,
+    /// Transactions which no longer pass the checks, e.g. after [`Queue::update_config`], to be announced
+    /// with the failed check
+    cancelled: Vec<(HashOf<SignedTransaction>, Error)>,
+}
+
+/// Readings of the wall-clock and the monotonic clock taken when a transaction is pushed, see [`Queue::ttl_clock`]
+#[derive(Debug, Clone, Copy)]
+struct FirstSeen {
+    unix_time: Duration,
+    monotonic_time: Duration,
+}
+
+impl FirstSeen {
+    fn now(time_source: &TimeSource) -> Self {
+        Self {
+            unix_time: time_source.get_unix_time(),
+            monotonic_time: time_source.get_monotonic_time(),
+        }
+    }
+}
+
+/// Number of transactions removed from the queue while collecting transactions for a block,
+/// see [`Queue::get_transactions_for_block_with_summary`]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ReapSummary {
+    /// Number of expired transactions
+    pub expired: usize,
+    /// Number of transactions which are already in the blockchain
+    pub in_blockchain: usize,
+}
+
+/// Number of pops after which [`Queue::get_transactions_for_block_async`] yields to the runtime
+const POPS_PER_YIELD: usize = 256;
+/// Number of consecutive [`PopStep::Vacant`] pops after which [`Queue::pop_from_queue`] gives up
+const MAX_CONSECUTIVE_VACANT_POPS: usize = 64;
+
+#[derive(Debug)]
+struct SubQueue {
+    name: String,
+    /// The maximum number of transactions of this class in the queue
+    capacity: NonZeroUsize,
+    /// Number of transactions of this class in the queue, see [`SubQueue::reserve_slot`]
+    len: AtomicUsize,
+    /// The queue for transactions of this class
+    ///
+    /// Holds twice the capacity, so that hashes of removed transactions awaiting
+    /// [`Queue::compact_sub_queues`] don't keep new transactions out.
+    tx_hashes: ArrayQueue<HashOf<SignedTransaction>>,
+}
+
+impl TransactionClass {
+    fn default_for(capacity: NonZeroUsize) -> Self {
+        Self {
+            name: "default".to_owned(),
+            capacity,
+        }
+    }
+}
+
+impl Classifier {
+    /// Classifier of the queue with a single class
+    fn single() -> Self {
+        Self(Box::new(|_| 0))
+    }
+}
+
+impl SubQueue {
+    fn new(TransactionClass { name, capacity }: TransactionClass) -> Self {
+        Self {
+            name,
+            capacity,
+            len: AtomicUsize::new(0),
+            tx_hashes: ArrayQueue::new(capacity.get().saturating_mul(2)),
+        }
+    }
+
+    /// Take a slot for one more transaction of this class, unless the class is full.
+    fn reserve_slot(&self) -> bool {
+        self.len
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |len| {
+                (len < self.capacity.get()).then_some(len + 1)
+            })
+            .is_ok()
+    }
+
+    /// Free the slot of a transaction removed from the queue.
+    fn release_slot(&self) {
+        self.len.fetch_sub(1, Ordering::Relaxed);
+    }
+
+    fn is_full(&self) -> bool {
+        self.len.load(Ordering::Relaxed) >= self.capacity.get()
+    }
+}
+
+/// Total order of transactions used wherever the queue selects among them regardless of their arrival,
+/// e.g. [`Queue::n_random_transactions`], so that equal inputs give equal outputs, see [`Queue::selection_key`].
+///
+/// Transactions are ordered by creation time, then by the priority of their classes,
+/// then by the amount of the offered fee, higher first, then by hash.
+/// Collection for a block follows the order of arrival instead.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct TxSelectionKey {
+    creation_time: Duration,
+    /// Index of the class, lower is higher priority
+    class: usize,
+    /// Transactions without a fee go last
+    fee: Reverse<Option<Numeric>>,
+    hash: HashOf<SignedTransaction>,
+}
+
+/// Idempotency key of `tx`, see [`IDEMPOTENCY_KEY`]
+fn idempotency_key(tx: &AcceptedTransaction) -> Option<Vec<u8>> {
+    tx.as_ref()
+        .metadata()
+        .get(IDEMPOTENCY_KEY)
+        .map(|key| key.get().as_bytes().to_vec())
+}
+
+/// Number of instructions of `tx` counted towards [`Config::max_total_instructions`]
+fn instruction_count(tx: &AcceptedTransaction) -> usize {
+    // Smart contract is validated as a whole
+    tx.instructions().map_or(1, <[_]>::len)
+}
+
+/// Map of transactions in the queue, with `shard_amount` shards if given
+fn accepted_txs_map(
+    shard_amount: Option<NonZeroUsize>,
+) -> DashMap<HashOf<SignedTransaction>, AcceptedTransaction> {
+    shard_amount.map_or_else(DashMap::new, |shard_amount| {
+        DashMap::with_shard_amount(shard_amount.get())
+    })
+}
+
+/// [`Duration`] which can be updated concurrently, stored as whole nanoseconds
+#[derive(Debug)]
+struct AtomicDuration(AtomicU64);
+
+impl AtomicDuration {
+    fn new(duration: Duration) -> Self {
+        Self(AtomicU64::new(Self::as_nanos(duration)))
+    }
+
+    fn load(&self) -> Duration {
+        Duration::from_nanos(self.0.load(Ordering::Relaxed))
+    }
+
+    fn store(&self, duration: Duration) {
+        self.0.store(Self::as_nanos(duration), Ordering::Relaxed);
+    }
+
+    /// Saturates at [`u64::MAX`] nanoseconds, which is more than 500 years
+    fn as_nanos(duration: Duration) -> u64 {
+        duration.as_nanos().try_into().unwrap_or(u64::MAX)
+    }
+}
+
+/// Lockfree queue for transactions
+///
+/// Multiple producers, single consumer
+#[derive(Debug)]
+pub struct Queue {
+    events: QueueEvents,
+    /// Queues for transactions of each class, in the order of priority
+    sub_queues: Vec<SubQueue>,
+    /// Maps transactions to indices in `sub_queues`
+    classifier: Classifier,
+    /// Notified about reads of the state, see [`Queue::with_observer`]
+    observer: Option<Arc<dyn QueueObserver>>,
+    /// Decides when transactions expire, see [`Queue::with_expiry_policy`]
+    expiry_policy: Box<dyn ExpiryPolicy>,
+    /// Recompute hashes of popped transactions, see [`Queue::with_hash_verification`]
+    verify_hashes: bool,
+    /// [`AcceptedTransaction`]s addressed by `Hash`
+    accepted_txs: DashMap<HashOf<SignedTransaction>, AcceptedTransaction>,
+    /// Amount of transactions per user in the queue
+    txs_per_user: DashMap<AccountId, usize>,
+    /// Set when `txs_per_user` is found out of sync with `accepted_txs`, see [`Queue::rebuild_user_counts`]
+    user_counts_drifted: AtomicBool,
+    /// Transactions in the queue by the authority and the idempotency key, see [`IDEMPOTENCY_KEY`]
+    idempotency_keys: DashMap<(AccountId, Vec<u8>), HashOf<SignedTransaction>>,
+    /// Hashes of transactions removed from `accepted_txs` while still present in `sub_queues`
+    tombstones: DashSet<HashOf<SignedTransaction>>,
+    /// Transactions selected for the next block before any others, see [`Queue::pin`]
+    pinned: DashSet<HashOf<SignedTransaction>>,
+    /// Notified when the transaction expires, see [`Queue::on_expiry`]
+    expiry_watchers: DashMap<HashOf<SignedTransaction>, oneshot::Sender<()>>,
+    /// Time at which the transaction was pushed into the queue, see [`Queue::first_seen`]
+    first_seen: DashMap<HashOf<SignedTransaction>, FirstSeen>,
+    /// First seen times of transactions removed by [`Queue::drain_for_block`], kept for [`Queue::requeue`]
+    /// until the next drain
+    drained_first_seen: DashMap<HashOf<SignedTransaction>, FirstSeen>,
+    /// Labels set by the operator, see [`Queue::set_label`]
+    labels: DashMap<HashOf<SignedTransaction>, BTreeSet<String>>,
+    /// The maximum number of transactions in `pinned`
+    max_pinned_transactions: usize,
+    /// Transactions with instructions of these kinds are rejected
+    denied_instruction_kinds: RwLock<BTreeSet<InstructionType>>,
+    /// Why the queue is halted, all transactions are rejected while it's set, see [`Queue::halt`]
+    halt_reason: RwLock<Option<String>>,
+    /// The maximum number of transactions in the queue
+    capacity: NonZeroUsize,
+    /// The maximum number of transactions in the queue per user. Used to apply throttling
+    /// unless the `no-throttling` feature is enabled
+    capacity_per_user: AtomicUsize,
+    /// Evict the transaction expiring soonest instead of rejecting new transactions when the queue is full
+    evict_on_full: bool,
+    /// Rate limiters of accounts which pushed transactions recently
+    rate_limits: DashMap<AccountId, TokenBucket>,
+    /// The maximum number of transactions an account can push in a burst, unlimited if `None`
+    rate_limit_burst: Option<NonZeroUsize>,
+    /// Time after which an account can push one more transaction
+    rate_limit_refill_interval: Duration,
+    /// Number of instructions in all transactions in the queue, see [`Queue::total_instructions`]
+    total_instructions: AtomicUsize,
+    /// The maximum of `total_instructions`, unlimited if `None`
+    max_total_instructions: Option<NonZeroUsize>,
+    /// The time source used to check transaction against
+    ///
+    /// A mock time source is used in tests for determinism
+    time_source: TimeSource,
+    /// Length of time after which transactions are dropped.
+    tx_time_to_live: AtomicDuration,
+    /// Transactions declaring a longer time to live are rejected
+    max_tx_time_to_live: Duration,
+    /// A point in time that is considered `Future` we cannot use
+    /// current time, because of network time synchronisation issues
+    future_threshold: AtomicDuration,
+    /// Expired transactions are announced and removed only after this period, see [`Queue::is_expired`]
+    expiry_grace: Duration,
+    /// Transactions older than this are collected for a block only after fresher ones
+    soft_age_threshold: Option<Duration>,
+    /// Whether to emit events for transactions dropped as expired
+    emit_expiry_events: bool,
+    /// Metrics updated on every change of the queue
+    metrics: QueueMetrics,
+    /// The most recent push rejections, oldest first, see [`Queue::recent_rejections`]
+    recent_rejections: Mutex<VecDeque<(HashOf<SignedTransaction>, Error, Duration)>>,
+    /// The maximum number of entries in `recent_rejections`
+    rejection_log_capacity: usize,
+    /// Incremented every time transactions are removed from the queue
+    space_generation: Mutex<u64>,
+    /// Signaled every time transactions are removed from the queue, see [`Queue::push_blocking`]
+    space_freed: Condvar,
+    /// Same as `space_freed`, but for [`Queue::push_blocking_async`]
+    space_freed_async: Notify,
+    /// Notified when a transaction is pushed into the empty queue, see [`Queue::wait_non_empty`]
+    non_empty: Notify,
+    /// Number of started collections of transactions for a block, used to correlate their logs
+    collection_attempts: AtomicU64,
+    /// Whether the sub-queues are being drained, see [`Queue::lock_collection`]
+    collecting: Mutex<bool>,
+    /// Signaled every time draining of the sub-queues is finished
+    collection_finished: Condvar,
+    /// Same as `collection_finished`, but for [`Queue::lock_collection_async`]
+    collection_finished_async: Notify,
+}
+
+/// Queue push error
+#[derive(Error, Clone, Debug, displaydoc::Display)]
+#[allow(variant_size_differences)]
+pub enum Error {
+    /// Queue is full
+    Full,
+    /// Transaction is regarded to have been tampered to have a future timestamp
+    InFuture,
+    /// Transaction expired
+    Expired,
+    /// Transaction was valid until block {limit}, but the blockchain height is {height}
+    BlockHeightExpired {
+        /// Height of the blockchain
+        height: u64,
+        /// Height of the last block the transaction can be committed in
+        limit: u64,
+    },
+    /// Transaction declares a time to live exceeding the configured maximum
+    TtlTooLong,
+    /// Instructions of kind `{0}` are denied
+    InstructionKindDenied(InstructionType),
+    /// Transaction is already applied
+    InBlockchain,
+    /// User reached maximum number of transactions in the queue
+    MaximumTransactionsPerUser,
+    /// The transaction is already in the queue
+    IsInQueue,
+    /// A transaction with the same idempotency key from the same authority is already in the queue
+    DuplicateIdempotencyKey,
+    /// User pushes transactions faster than allowed
+    RateLimited,
+    /// Queue can't fit any more instructions
+    InstructionBudgetExceeded,
+    /// Queue is halted: {0}
+    Halted(String),
+}
+
+/// Failure that can pop up when pushing transaction into the queue
+#[derive(Debug)]
+pub struct Failure {
+    /// Transaction failed to be pushed into the queue
+    pub tx: AcceptedTransaction,
+    /// Push failure reason
+    pub err: Error,
+    /// Size of the SCALE encoded transaction, for diagnostics
+    pub size_bytes: usize,
+    /// How far ahead of the clock of this peer the transaction timestamp is,
+    /// for diagnostics of misconfigured client clocks. Only set for [`Error::InFuture`]
+    pub clock_skew: Option<Duration>,
+}
+
+impl Failure {
+    fn new(tx: AcceptedTransaction, err: Error) -> Self {
+        let size_bytes = tx.as_ref().encoded_size();
+        Self {
+            tx,
+            err,
+            size_bytes,
+            clock_skew: None,
+        }
+    }
+}
+
+impl Queue {
+    /// Makes queue from configuration
+    ///
+    /// # Errors
+    /// Fails with every issue found by [`Config::validate`].
+    pub fn from_config(
+        config: Config,
+        events_sender: EventsSender,
+    ) -> Result<Self, Vec<ConfigIssue>> {
+        Self::with_time_source(config, events_sender, TimeSource::new_system())
+    }
+
+    fn with_time_source(
+        config: Config,
+        events_sender: EventsSender,
+        time_source: TimeSource,
+    ) -> Result<Self, Vec<ConfigIssue>> {
+        config.validate()?;
+        let Config {
+            capacity,
+            capacity_per_user,
+            transaction_time_to_live,
+            max_transaction_time_to_live,
+            future_threshold,
+            expiry_grace,
+            reaping_interval: _,
+            evict_on_full,
+            events_capacity,
+            events_on_overflow,
+            soft_age_threshold,
+            shard_amount,
+            max_pinned_transactions,
+            denied_instruction_kinds,
+            emit_expiry_events,
+            rejection_log_capacity,
+            rate_limit_burst,
+            rate_limit_refill_interval,
+            max_total_instructions,
+        } = config;
+
+        Ok(Self {
+            events: QueueEvents::new(events_sender, events_capacity, events_on_overflow),
+            sub_queues: vec![SubQueue::new(TransactionClass::default_for(capacity))],
+            classifier: Classifier::single(),
+            observer: None,
+            expiry_policy: Box::new(DefaultExpiryPolicy),
+            verify_hashes: cfg!(debug_assertions),
+            accepted_txs: accepted_txs_map(shard_amount),
+            txs_per_user: DashMap::new(),
+            user_counts_drifted: AtomicBool::new(false),
+            idempotency_keys: DashMap::new(),
+            tombstones: DashSet::new(),
+            pinned: DashSet::new(),
+            expiry_watchers: DashMap::new(),
+            first_seen: DashMap::new(),
+            drained_first_seen: DashMap::new(),
+            labels: DashMap::new(),
+            max_pinned_transactions,
+            denied_instruction_kinds: RwLock::new(denied_instruction_kinds),
+            halt_reason: RwLock::new(None),
+            capacity,
+            capacity_per_user: AtomicUsize::new(capacity_per_user.get()),
+            evict_on_full,
+            rate_limits: DashMap::new(),
+            rate_limit_burst,
+            rate_limit_refill_interval,
+            total_instructions: AtomicUsize::new(0),
+            max_total_instructions,
+            time_source,
+            tx_time_to_live: AtomicDuration::new(transaction_time_to_live),
+            max_tx_time_to_live: max_transaction_time_to_live,
+            future_threshold: AtomicDuration::new(future_threshold),
+            expiry_grace,
+            soft_age_threshold,
+            emit_expiry_events,
+            metrics: QueueMetrics::default(),
+            recent_rejections: Mutex::new(VecDeque::new()),
+            rejection_log_capacity,
+            space_generation: Mutex::new(0),
+            space_freed: Condvar::new(),
+            space_freed_async: Notify::new(),
+            non_empty: Notify::new(),
+            collecting: Mutex::new(false),
+            collection_finished: Condvar::new(),
+            collection_finished_async: Notify::new(),
+            collection_attempts: AtomicU64::new(0),
+        })
+    }
+
+    /// Start building a queue with defaults for all parameters, see [`QueueBuilder`].
+    pub fn builder() -> QueueBuilder {
+        QueueBuilder::default()
+    }
+
+    /// Split the queue into independent sub-queues, one per class.
+    ///
+    /// `classes` are given in the order of priority: transactions of higher priority classes
+    /// are always collected for a block first, so they never wait behind transactions of lower priority classes.
+    /// `classifier` maps a transaction to the index of its class; out of range indices map to the last class.
+    /// The total capacity of the queue still applies on top of the capacities of the classes.
+    ///
+    /// By default there is a single class with the capacity of the queue.
+    ///
+    /// # Panics
+    /// If `classes` is empty
+    #[must_use]
+    pub fn with_classes(
+        mut self,
+        classes: impl IntoIterator<Item = TransactionClass>,
+        classifier: impl Fn(&AcceptedTransaction) -> usize + Send + Sync + 'static,
+    ) -> Self {
+        self.sub_queues = classes.into_iter().map(SubQueue::new).collect();
+        assert!(
+            !self.sub_queues.is_empty(),
+            "Queue must have at least one transaction class"
+        );
+        self.classifier = Classifier(Box::new(classifier));
+        self
+    }
+
+    /// Notify `observer` about every read of the state made to check transactions, e.g. to profile them.
+    ///
+    /// There is no observer by default.
+    #[must_use]
+    pub fn with_observer(mut self, observer: Arc<dyn QueueObserver>) -> Self {
+        self.observer = Some(observer);
+        self
+    }
+
+    /// Check that every transaction popped from the queue hashes to the hash it's stored by.
+    ///
+    /// Mismatching transactions are logged and dropped from the queue. Catches corruption
+    /// of the queue early at the cost of hashing every popped transaction,
+    /// so it's enabled by default only in debug builds.
+    #[must_use]
+    pub fn with_hash_verification(mut self, enabled: bool) -> Self {
+        self.verify_hashes = enabled;
+        self
+    }
+
+    /// Decide when transactions expire with `policy` instead of [`DefaultExpiryPolicy`],
+    /// e.g. to never expire transactions of administrators.
+    ///
+    /// Eviction of the transaction expiring soonest from the full queue still orders transactions by their time to live.
+    #[must_use]
+    pub fn with_expiry_policy(mut self, policy: impl ExpiryPolicy + 'static) -> Self {
+        self.expiry_policy = Box::new(policy);
+        self
+    }
+
+    /// Wrap `state_view` to notify the observer about reads of it, if any.
+    fn observed<'a, S: QueueStateProbe>(&'a self, state_view: &'a S) -> ObservedState<'a, S> {
+        ObservedState {
+            observer: self.observer.as_deref(),
+            state_view,
+        }
+    }
+
+    fn classify(&self, tx: &AcceptedTransaction) -> usize {
+        (self.classifier.0)(tx).min(self.sub_queues.len() - 1)
+    }
+
+    /// Position of `tx` in the total order of selection, see [`TxSelectionKey`].
+    pub fn selection_key(&self, tx: &AcceptedTransaction) -> TxSelectionKey {
+        TxSelectionKey {
+            creation_time: tx.creation_time(),
+            class: self.classify(tx),
+            fee: Reverse(tx.fee().map(|fee| fee.amount)),
+            hash: tx.as_ref().hash(),
+        }
+    }
+
+    /// The maximum number of transactions in the queue.
+    pub fn capacity(&self) -> NonZeroUsize {
+        self.capacity
+    }
+
+    /// The maximum number of transactions in the queue per user.
+    ///
+    /// Not enforced if the `no-throttling` feature is enabled.
+    pub fn capacity_per_user(&self) -> NonZeroUsize {
+        NonZeroUsize::new(self.capacity_per_user.load(Ordering::Relaxed))
+            .expect("Only non-zero values are stored")
+    }
+
+    /// Length of time after which transactions are dropped.
+    pub fn tx_time_to_live(&self) -> Duration {
+        self.tx_time_to_live.load()
+    }
+
+    /// How far in the future the timestamp of a transaction can be before it's regarded as tampered.
+    pub fn future_threshold(&self) -> Duration {
+        self.future_threshold.load()
+    }
+
+    /// Apply the time to live, the future threshold, the per user capacity
+    /// and the denied instruction kinds from the reloaded `cfg`.
+    ///
+    /// Transactions in the queue are kept and checked against the new values from now on,
+    /// e.g. a shorter time to live can expire transactions which were admitted under the previous one.
+    /// Other values, such as the capacity of the queue, can't be changed without recreating the queue.
+    ///
+    /// # Errors
+    /// Fails with every issue found by [`Config::validate`] with the values which can't be changed kept,
+    /// e.g. if the capacity per user exceeds the capacity of the queue. Nothing is applied then.
+    pub fn update_config(&self, cfg: &Config) -> Result<(), Vec<ConfigIssue>> {
+        Config {
+            capacity: self.capacity,
+            soft_age_threshold: self.soft_age_threshold,
+            ..cfg.clone()
+        }
+        .validate()?;
+
+        self.tx_time_to_live.store(cfg.transaction_time_to_live);
+        self.future_threshold.store(cfg.future_threshold);
+        self.capacity_per_user
+            .store(cfg.capacity_per_user.get(), Ordering::Relaxed);
+        self.denied_instruction_kinds
+            .write()
+            .clone_from(&cfg.denied_instruction_kinds);
+        Ok(())
+    }
+
+    /// Halt the queue, e.g. when the node enters maintenance: all pending transactions are removed
+    /// with [`TransactionStatus::Cancelled`] events and new ones are rejected with [`Error::Halted`],
+    /// both carrying the `reason`, until the queue is [resumed](Self::resume).
+    ///
+    /// Unlike shutting down, the queue stays alive. Returns the number of removed transactions.
+    pub fn halt(&self, reason: String) -> usize {
+        info!(%reason, "Halting the queue");
+        *self.halt_reason.write() = Some(reason.clone());
+        // Pushes which passed the check before the flag was set are removed as well
+        self.remove_where(|_| true, &TransactionStatus::Cancelled(Some(reason)), true)
+            .len()
+    }
+
+    /// Accept transactions again after [`Self::halt`].
+    pub fn resume(&self) {
+        if let Some(reason) = self.halt_reason.write().take() {
+            info!(%reason, "Resuming the halted queue");
+        }
+    }
+
+    /// Why the queue is halted, `None` unless it's halted, see [`Self::halt`].
+    pub fn halt_reason(&self) -> Option<String> {
+        self.halt_reason.read().clone()
+    }
+
+    fn check_halted(&self) -> Result<(), Error> {
+        if let Some(reason) = self.halt_reason.read().as_ref() {
+            return Err(Error::Halted(reason.clone()));
+        }
+        Ok(())
+    }
+
+    /// Metrics of this queue, to be registered in the node metrics registry.
+    pub fn metrics(&self) -> &QueueMetrics {
+        &self.metrics
+    }
+
+    /// Number of pushes rejected since the start by the reason, e.g. `"full"`, see [`QueueMetrics::rejection_counts`].
+    pub fn rejection_counts(&self) -> BTreeMap<&'static str, u64> {
+        self.metrics.rejection_counts()
+    }
+
+    fn is_pending(&self, tx: &AcceptedTransaction, state_view: &impl QueueStateProbe) -> bool {
+        !self.is_expired(tx) && !tx.is_in_blockchain(&self.observed(state_view))
+    }
+
+    /// Checks if the transaction is waiting longer than its TTL or than the TTL from [`Config`].
+    ///
+    /// Expired transactions are never put into a block, but they are announced with
+    /// [`TransactionStatus::Expired`] and removed from the queue only once they are past the expiry grace period.
+    /// This separates "don't include in a block" from "announce as dead", so that peers with
+    /// slightly different clocks don't emit inconsistent events for transactions at the TTL boundary.
+    pub fn is_expired(&self, tx: &AcceptedTransaction) -> bool {
+        self.is_expired_at(tx, self.ttl_clock(tx))
+    }
+
+    /// Checks if the transaction is expired and past the expiry grace period.
+    fn is_past_expiry_grace(&self, tx: &AcceptedTransaction) -> bool {
+        self.is_expired_at(tx, self.ttl_clock(tx).saturating_sub(self.expiry_grace))
+    }
+
+    /// Checks if the transaction will be expired in `period`.
+    fn expires_within(&self, tx: &AcceptedTransaction, period: Duration) -> bool {
+        self.is_expired_at(tx, self.ttl_clock(tx).saturating_add(period))
+    }
+
+    fn is_expired_at(&self, tx: &AcceptedTransaction, now: Duration) -> bool {
+        self.expiry_policy
+            .is_expired(tx, now, self.tx_time_to_live())
+    }
+
+    /// Time the transaction is allowed to wait in the queue by its time to live.
+    fn time_limit(&self, tx: &AcceptedTransaction) -> Duration {
+        DefaultExpiryPolicy::time_limit(tx, self.tx_time_to_live())
+    }
+
+    /// If `true`, this transaction declares a time to live longer than the network allows.
+    fn is_ttl_too_long(&self, tx: &AcceptedTransaction) -> bool {
+        tx.time_to_live()
+            .is_some_and(|time_to_live| time_to_live > self.max_tx_time_to_live)
+    }
+
+    /// Point in time after which the transaction is expired by its time to live.
+    fn expires_at(&self, tx: &AcceptedTransaction) -> Duration {
+        tx.creation_time().saturating_add(self.time_limit(tx))
+    }
+
+    /// Current time used to measure how long `tx` has lived.
+    ///
+    /// The time elapsed since the transaction was pushed is measured with the monotonic clock
+    /// from the readings taken at push, see [`Self::first_seen`], so steps of the system clock
+    /// (e.g. by NTP) can't spuriously expire it. The wall-clock is only read for transactions which
+    /// aren't in the queue, e.g. when they are checked before being pushed, and to detect transactions from the future.
+    fn ttl_clock(&self, tx: &AcceptedTransaction) -> Duration {
+        let hash = tx.as_ref().hash();
+        let Some(first_seen) = self
+            .first_seen
+            .get(&hash)
+            .or_else(|| self.drained_first_seen.get(&hash))
+            .map(|seen| *seen)
+        else {
+            return self.time_source.get_unix_time();
+        };
+        let elapsed = self
+            .time_source
+            .get_monotonic_time()
+            .saturating_sub(first_seen.monotonic_time);
+        first_seen.unix_time.saturating_add(elapsed)
+    }
+
+    /// If `true`, this transaction is older than the soft age threshold and is deprioritized.
+    fn is_old(&self, tx: &AcceptedTransaction) -> bool {
+        self.soft_age_threshold.is_some_and(|threshold| {
+            self.ttl_clock(tx).saturating_sub(tx.creation_time()) > threshold
+        })
+    }
+
+    /// If `true`, this transaction is regarded to have been tampered to have a future timestamp.
+    fn is_in_future(&self, tx: &AcceptedTransaction) -> bool {
+        self.clock_skew(tx) > self.future_threshold()
+    }
+
+    /// How far ahead of the current time the timestamp of this transaction is.
+    fn clock_skew(&self, tx: &AcceptedTransaction) -> Duration {
+        let tx_timestamp = tx.creation_time();
+        let curr_time = self.time_source.get_unix_time();
+        tx_timestamp.saturating_sub(curr_time)
+    }
+
+    /// Returns all pending transactions.
+    ///
+    /// Filter them with [`Self::has_label`] to get the ones labelled by the operator.
+    pub fn all_transactions<'state>(
+        &'state self,
+        state_view: &'state impl QueueStateProbe,
+    ) -> impl Iterator<Item = AcceptedTransaction> + 'state {
+        self.accepted_txs.iter().filter_map(|tx| {
+            if self.is_pending(tx.value(), state_view) {
+                return Some(tx.value().clone());
+            }
+
+            None
+        })
+    }
+
+    /// Estimate the share of `max_txs_in_block` slots that pending transactions would fill, from `0.0` to `1.0`.
+    ///
+    /// Cheaper than collecting transactions since it only counts pending transactions, stopping at
+    /// `max_txs_in_block`, and doesn't touch the sub-queues. The estimate is stale as soon as it's returned
+    /// under concurrency: transactions can be pushed or removed meanwhile. Only expiry and presence in the
+    /// blockchain are checked, so transactions dropped by other checks during collection (e.g. past their
+    /// block height limit) can make the block less full than estimated.
+    #[allow(clippy::cast_precision_loss)]
+    pub fn estimated_block_fill(
+        &self,
+        state_view: &impl QueueStateProbe,
+        max_txs_in_block: NonZeroUsize,
+    ) -> f64 {
+        let pending = self
+            .accepted_txs
+            .iter()
+            .filter(|tx| self.is_pending(tx.value(), state_view))
+            .take(max_txs_in_block.get())
+            .count();
+        pending as f64 / max_txs_in_block.get() as f64
+    }
+
+    /// Returns pending transactions which expire within `window`, with the time remaining until they expire.
+    ///
+    /// The remaining time is measured by the time to live the same way [`Self::is_expired`] does, transactions
+    /// which the [`ExpiryPolicy`] doesn't expire within `window` are left out. Meant for monitoring, e.g.
+    /// to alert ahead of a mass expiration; transactions aren't ordered.
+    pub fn expiring_within(
+        &self,
+        window: Duration,
+        state_view: &impl QueueStateProbe,
+    ) -> Vec<(HashOf<SignedTransaction>, Duration)> {
+        self.accepted_txs
+            .iter()
+            .filter(|tx| self.expires_within(tx.value(), window))
+            .filter(|tx| self.is_pending(tx.value(), state_view))
+            .map(|tx| {
+                let remaining = self
+                    .expires_at(tx.value())
+                    .saturating_sub(self.ttl_clock(tx.value()));
+                (*tx.key(), remaining)
+            })
+            .collect()
+    }
+
+    /// Returns all pending transactions in the order they would be collected for a block.
+    ///
+    /// Pinned transactions come first, followed by the queued ones in the order of priority of their classes
+    /// and of their arrival, with transactions older than the soft age threshold last.
+    /// This is O(n) since the sub-queues are drained and restored, see [`Self::compact_sub_queues`]: transactions pushed
+    /// concurrently can be put ahead of the restored ones. Waits for a concurrent collection of transactions for a block to finish.
+    pub fn iter_ordered(&self, state_view: &impl QueueStateProbe) -> Vec<AcceptedTransaction> {
+        let _collection = self.lock_collection();
+        let pending = |hash: &HashOf<SignedTransaction>| {
+            self.accepted_txs
+                .get(hash)
+                .map(|tx| tx.value().clone())
+                .filter(|tx| self.is_pending(tx, state_view))
+        };
+
+        // Copied first, so that the pinned set isn't locked while looking up transactions,
+        // removals lock them in the opposite order
+        let pinned: Vec<_> = self.pinned.iter().map(|hash| *hash).collect();
+        let mut ordered: Vec<_> = pinned.iter().filter_map(pending).collect();
+        let mut old_transactions = Vec::new();
+        self.compact_sub_queues(Seen::new(), |_, hash| {
+            if self.pinned.contains(hash) {
+                return;
+            }
+            let Some(tx) = pending(hash) else {
+                return;
+            };
+            if self.is_old(&tx) {
+                old_transactions.push(tx);
+            } else {
+                ordered.push(tx);
+            }
+        });
+        ordered.extend(old_transactions);
+
+        ordered
+    }
+
+    /// Returns `n` randomly selected transaction from the queue, ordered by [`TxSelectionKey`].
+    pub fn n_random_transactions(
+        &self,
+        n: u32,
+        state_view: &impl QueueStateProbe,
+    ) -> Vec<AcceptedTransaction> {
+        let mut selected = self
+            .accepted_txs
+            .iter()
+            .filter(|e| self.is_pending(e.value(), state_view))
+            .map(|e| e.value().clone())
+            .choose_multiple(
+                &mut rand::thread_rng(),
+                n.try_into().expect("u32 should always fit in usize"),
+            );
+        selected.sort_by_cached_key(|tx| self.selection_key(tx));
+        selected
+    }
+
+    /// Returns up to `n` distinct transactions from the queue, selected at random with probability
+    /// proportional to `weight_fn`, e.g. to gossip older or higher fee transactions first.
+    ///
+    /// Transactions of zero weight are never selected.
+    /// The sum of the weights of all pending transactions must fit into [`u64`].
+    pub fn n_weighted_transactions(
+        &self,
+        n: u32,
+        state_view: &impl QueueStateProbe,
+        weight_fn: impl Fn(&AcceptedTransaction) -> u64,
+    ) -> Vec<AcceptedTransaction> {
+        let n: usize = n.try_into().expect("u32 should always fit in usize");
+        let mut candidates: Vec<_> = self
+            .accepted_txs
+            .iter()
+            .filter(|e| self.is_pending(e.value(), state_view))
+            .map(|e| e.value().clone())
+            .collect();
+        // Iteration order of the map is arbitrary
+        candidates.sort_by_cached_key(|tx| self.selection_key(tx));
+        let weights: Vec<_> = candidates.iter().map(&weight_fn).collect();
+
+        let Ok(mut distribution) = WeightedIndex::new(&weights) else {
+            // There are no pending transactions of non-zero weight
+            return Vec::new();
+        };
+        let mut rng = rand::thread_rng();
+        let mut selected = Vec::with_capacity(n.min(candidates.len()));
+        while selected.len() < n {
+            let index = distribution.sample(&mut rng);
+            selected.push(candidates[index].clone());
+            // Sample without replacement
+            if distribution.update_weights(&[(index, &0)]).is_err() {
+                break;
+            }
+        }
+        selected
+    }
+
+    fn check_tx(
+        &self,
+        tx: &AcceptedTransaction,
+        state_view: &impl QueueStateProbe,
+    ) -> Result<(), Error> {
+        let state_view = &self.observed(state_view);
+        if self.is_in_future(tx) {
+            Err(Error::InFuture)
+        } else if self.is_ttl_too_long(tx) {
+            Err(Error::TtlTooLong)
+        } else if self.is_expired(tx) {
+            Err(Error::Expired)
+        } else if let Some(err) = Self::block_height_expired(tx, state_view) {
+            Err(err)
+        } else if tx.is_in_blockchain(state_view) {
+            Err(Error::InBlockchain)
+        } else if let Some(kind) = self.denied_instruction_kind(tx) {
+            Err(Error::InstructionKindDenied(kind))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Error if `tx` can't be committed in the next block since it was valid until an earlier one.
+    fn block_height_expired(
+        tx: &AcceptedTransaction,
+        state_view: &impl QueueStateProbe,
+    ) -> Option<Error> {
+        let limit = tx.as_ref().valid_until_block()?.get();
+        let height = state_view.height() as u64;
+        (height >= limit).then_some(Error::BlockHeightExpired { height, limit })
+    }
+
+    /// Kind of the first instruction of `tx` which is denied, if any.
+    ///
+    /// WASM smart contracts can't be inspected and are never denied.
+    fn denied_instruction_kind(&self, tx: &AcceptedTransaction) -> Option<InstructionType> {
+        let denied_instruction_kinds = self.denied_instruction_kinds.read();
+        if denied_instruction_kinds.is_empty() {
+            return None;
+        }
+        tx.instructions()?
+            .iter()
+            .map(InstructionType::from)
+            .find(|kind| denied_instruction_kinds.contains(kind))
+    }
+
+    /// Push transaction into queue.
+    ///
+    /// The queue doesn't require the authority of `tx` to exist in the world state,
+    /// so transactions from accounts registered by transactions still pending (e.g. earlier in the genesis batch)
+    /// are accepted. Existence of the authority is checked during validation instead.
+    ///
+    /// No signatures are checked here either: the signature of a transaction is verified
+    /// when it's decoded, and the queue doesn't look up the authority to evaluate any other condition.
+    ///
+    /// # Errors
+    /// See [`enum@Error`]
+    pub fn push(
+        &self,
+        tx: AcceptedTransaction,
+        state_view: &impl QueueStateProbe,
+    ) -> Result<(), Failure> {
+        let result = self.push_inner(tx, state_view);
+        self.record_push_result(&result, &self.metrics.pushes_total);
+        result
+    }
+
+    /// Push back a transaction previously removed by [`Self::drain_for_block`],
+    /// e.g. when it failed during block execution and should be retried in the next block.
+    ///
+    /// The transaction is checked and accounted for as a pushed one, so it's rejected if it
+    /// expired or was committed in the meantime. Its time to live is still measured from when it was
+    /// first pushed, see [`Self::first_seen`], as long as it's requeued before the next drain. Unlike [`Self::push`], successful requeues are
+    /// counted separately in [`QueueMetrics::requeues_total`] to tell retries from new submissions.
+    ///
+    /// # Errors
+    /// See [`enum@Error`]
+    pub fn requeue(
+        &self,
+        tx: AcceptedTransaction,
+        state_view: &impl QueueStateProbe,
+    ) -> Result<(), Failure> {
+        let result = self.push_inner(tx, state_view);
+        self.record_push_result(&result, &self.metrics.requeues_total);
+        result
+    }
+
+    /// Check whether [`Self::push`] would admit `tx`, without modifying the queue.
+    ///
+    /// Runs the same checks as [`Self::push`], so callers can report a precise rejection reason before pushing.
+    /// The queue can change concurrently, so a subsequent push can still be rejected.
+    ///
+    /// # Errors
+    /// See [`enum@Error`]
+    pub fn would_accept(
+        &self,
+        tx: &AcceptedTransaction,
+        state_view: &impl QueueStateProbe,
+    ) -> Result<(), Error> {
+        self.check_halted()?;
+        self.check_tx(tx, state_view)?;
+
+        if self.accepted_txs.contains_key(&tx.as_ref().hash()) {
+            return Err(Error::IsInQueue);
+        }
+        let class = self.classify(tx);
+        let can_evict = self.evict_on_full
+            && self
+                .eviction_candidate(class, self.expires_at(tx))
+                .is_some();
+        let mut txs_len = self.accepted_txs.len();
+        if can_evict && txs_len >= self.capacity.get() {
+            txs_len -= 1;
+        }
+        self.check_capacity(txs_len)?;
+        self.check_rate_limit(tx.authority())?;
+        self.check_instruction_budget(instruction_count(tx))?;
+        self.check_per_user_capacity(
+            self.txs_per_user
+                .get(tx.authority())
+                .map_or(0, |count| *count),
+        )?;
+        if let Some(key) = idempotency_key(tx) {
+            if self
+                .idempotency_keys
+                .contains_key(&(tx.authority().clone(), key))
+            {
+                return Err(Error::DuplicateIdempotencyKey);
+            }
+        }
+        if self.sub_queues[class].is_full() && !can_evict {
+            return Err(Error::Full);
+        }
+
+        Ok(())
+    }
+
+    /// Same as [`Self::push`], but if the queue is full waits up to `timeout` for a free slot.
+    ///
+    /// Only [`Error::Full`] is waited on, transactions exceeding
+    /// the per user limit (and failing any other check) are still rejected immediately.
+    ///
+    /// # Errors
+    /// See [`enum@Error`]
+    pub fn push_blocking(
+        &self,
+        mut tx: AcceptedTransaction,
+        state_view: &impl QueueStateProbe,
+        timeout: Duration,
+    ) -> Result<(), Failure> {
+        // `None` if `timeout` is too long for the deadline to be represented, i.e. there is no deadline
+        let deadline = Instant::now().checked_add(timeout);
+
+        loop {
+            let generation = *self.space_generation.lock();
+            let result = match self.push_inner(tx, state_view) {
+                Err(failure) if matches!(failure.err, Error::Full) => {
+                    if self.wait_for_space(generation, deadline) {
+                        tx = failure.tx;
+                        continue;
+                    }
+                    Err(failure)
+                }
+                result => result,
+            };
+
+            self.record_push_result(&result, &self.metrics.pushes_total);
+            return result;
+        }
+    }
+
+    /// Async version of [`Self::push_blocking`].
+    ///
+    /// # Errors
+    /// See [`enum@Error`]
+    pub async fn push_blocking_async(
+        &self,
+        mut tx: AcceptedTransaction,
+        state_view: &impl QueueStateProbe,
+        timeout: Duration,
+    ) -> Result<(), Failure> {
+        // `None` if `timeout` is too long for the deadline to be represented, i.e. there is no deadline
+        let deadline = tokio::time::Instant::now().checked_add(timeout);
+
+        loop {
+            let space_freed = self.space_freed_async.notified();
+            tokio::pin!(space_freed);
+            // Register interest before pushing so that space freed in between isn't missed
+            space_freed.as_mut().enable();
+
+            let result = match self.push_inner(tx, state_view) {
+                Err(failure) if matches!(failure.err, Error::Full) => {
+                    let freed = if let Some(deadline) = deadline {
+                        tokio::time::timeout_at(deadline, space_freed).await.is_ok()
+                    } else {
+                        space_freed.await;
+                        true
+                    };
+                    if freed {
+                        tx = failure.tx;
+                        continue;
+                    }
+                    Err(failure)
+                }
+                result => result,
+            };
+
+            self.record_push_result(&result, &self.metrics.pushes_total);
+            return result;
+        }
+    }
+
+    /// Wait until transactions are removed from the queue after `generation` was observed.
+    ///
+    /// Returns `false` if `deadline` was reached first. Waits indefinitely without a `deadline`.
+    fn wait_for_space(&self, generation: u64, deadline: Option<Instant>) -> bool {
+        let mut current = self.space_generation.lock();
+        while *current == generation {
+            let Some(deadline) = deadline else {
+                self.space_freed.wait(&mut current);
+                continue;
+            };
+            if self
+                .space_freed
+                .wait_until(&mut current, deadline)
+                .timed_out()
+            {
+                return false;
+            }
+        }
+        true
+    }
+
+    fn notify_space_freed(&self) {
+        *self.space_generation.lock() += 1;
+        self.space_freed.notify_all();
+        self.space_freed_async.notify_waiters();
+    }
+
+    /// Wait until no other collection drains the sub-queues and start one.
+    ///
+    /// Popped hashes are restored only once a collection is finished, so concurrent collections
+    /// would miss each other's transactions and mix up their order.
+    fn lock_collection(&self) -> CollectionGuard<'_> {
+        let mut collecting = self.collecting.lock();
+        while *collecting {
+            self.collection_finished.wait(&mut collecting);
+        }
+        *collecting = true;
+        CollectionGuard(self)
+    }
+
+    /// Async version of [`Self::lock_collection`].
+    async fn lock_collection_async(&self) -> CollectionGuard<'_> {
+        loop {
+            let finished = self.collection_finished_async.notified();
+            tokio::pin!(finished);
+            // Register interest before checking so that a collection finished in between isn't missed
+            finished.as_mut().enable();
+            if let Some(guard) = self.try_lock_collection() {
+                return guard;
+            }
+            finished.await;
+        }
+    }
+
+    fn try_lock_collection(&self) -> Option<CollectionGuard<'_>> {
+        let mut collecting = self.collecting.lock();
+        if *collecting {
+            return None;
+        }
+        *collecting = true;
+        Some(CollectionGuard(self))
+    }
+
+    /// Update metrics after a push, `admitted` counts successful ones.
+    fn record_push_result(&self, result: &Result<(), Failure>, admitted: &IntCounter) {
+        match result {
+            Ok(()) => admitted.inc(),
+            Err(failure) => {
+                self.metrics.record_rejection(&failure.err);
+                self.log_rejection(failure);
+            }
+        }
+        self.update_len_metric();
+        self.rebuild_drifted_user_counts();
+    }
+
+    fn log_rejection(&self, failure: &Failure) {
+        if self.rejection_log_capacity == 0 {
+            return;
+        }
+        let mut recent_rejections = self.recent_rejections.lock();
+        if recent_rejections.len() >= self.rejection_log_capacity {
+            recent_rejections.pop_front();
+        }
+        recent_rejections.push_back((
+            failure.tx.as_ref().hash(),
+            failure.err.clone(),
+            self.time_source.get_unix_time(),
+        ));
+    }
+
+    /// The most recent push rejections, oldest first, with the Unix time at which they happened.
+    ///
+    /// At most [`Config::rejection_log_capacity`] rejections are kept, the log is disabled by default.
+    pub fn recent_rejections(&self) -> Vec<(HashOf<SignedTransaction>, Error, Duration)> {
+        self.recent_rejections.lock().iter().cloned().collect()
+    }
+
+    fn push_inner(
+        &self,
+        tx: AcceptedTransaction,
+        state_view: &impl QueueStateProbe,
+    ) -> Result<(), Failure> {
+        let span = trace_span!(
+            "queue_push",
+            tx=%tx.as_ref().hash(),
+            authority=%tx.authority(),
+            queue_len=self.accepted_txs.len()
+        );
+        let _enter = span.enter();
+        trace!("Pushing to the queue");
+        if let Err(err) = self.check_halted() {
+            return Err(Failure::new(tx, err));
+        }
+        if let Err(err) = self.check_tx(&tx, state_view) {
+            let mut failure = Failure::new(tx, err);
+            if let Error::InFuture = failure.err {
+                let clock_skew = self.clock_skew(&failure.tx);
+                debug!(
+                    authority=%failure.tx.authority(),
+                    ?clock_skew,
+                    "Transaction timestamp is ahead of the clock of this peer"
+                );
+                failure.clock_skew = Some(clock_skew);
+            }
+            return Err(failure);
+        }
+
+        let hash = tx.as_ref().hash();
+        // Checked before making room for it, it's checked again under the entry
+        if self.accepted_txs.contains_key(&hash) {
+            return Err(Failure::new(tx, Error::IsInQueue));
+        }
+        let sub_queue = match self.reserve_slot_for(&tx) {
+            Ok(sub_queue) => sub_queue,
+            Err(err) => return Err(Failure::new(tx, err)),
+        };
+
+        // Get `txs_len` before entry to avoid deadlock
+        let txs_len = self.accepted_txs.len();
+        let entry = match self.accepted_txs.entry(hash) {
+            Entry::Occupied(_) => {
+                sub_queue.release_slot();
+                return Err(Failure::new(tx, Error::IsInQueue));
+            }
+            Entry::Vacant(entry) => entry,
+        };
+
+        if let Err(err) = self.check_capacity(txs_len) {
+            warn!(
+                max = self.capacity,
+                "Achieved maximum amount of transactions"
+            );
+            sub_queue.release_slot();
+            return Err(Failure::new(tx, err));
+        }
+
+        let instructions = instruction_count(&tx);
+        if let Err(err) = self.reserve_instructions(instructions) {
+            sub_queue.release_slot();
+            return Err(Failure::new(tx, err));
+        }
+        if let Err(err) = self.check_and_increase_per_user_tx_count(tx.authority()) {
+            sub_queue.release_slot();
+            self.total_instructions
+                .fetch_sub(instructions, Ordering::Relaxed);
+            return Err(Failure::new(tx, err));
+        }
+        if let Some(key) = idempotency_key(&tx) {
+            match self.idempotency_keys.entry((tx.authority().clone(), key)) {
+                Entry::Occupied(_) => {
+                    sub_queue.release_slot();
+                    self.total_instructions
+                        .fetch_sub(instructions, Ordering::Relaxed);
+                    self.decrease_per_user_tx_count(tx.authority());
+                    return Err(Failure::new(tx, Error::DuplicateIdempotencyKey));
+                }
+                Entry::Vacant(entry) => {
+                    entry.insert(hash);
+                }
+            }
+        }
+        // Taken last, so that the token isn't spent on a transaction rejected for another reason
+        if let Err(err) = self.take_rate_token(tx.authority()) {
+            self.forget(&tx);
+            return Err(Failure::new(tx, err));
+        }
+
+        // Recorded before the transaction becomes visible, so a concurrent removal can't leave it behind
+        let first_seen = self
+            .drained_first_seen
+            .remove(&hash)
+            .map_or_else(|| FirstSeen::now(&self.time_source), |(_, seen)| seen);
+        self.first_seen.insert(hash, first_seen);
+        // The hash of a removed transaction is still queued, so it's taken over instead of queueing a duplicate.
+        // Checked under the entry, so that it can't be dropped as stale concurrently
+        let hash_is_queued = self.tombstones.remove(&hash).is_some();
+        // Insert entry first so that the `tx` popped from `queue` will always have a `(hash, tx)` record in `txs`.
+        entry.insert(tx);
+        if !hash_is_queued {
+            sub_queue.tx_hashes.push(hash).map_err(|err_hash| {
+                warn!(class = %sub_queue.name, "Queue is full");
+                let (_, err_tx) = self
+                    .accepted_txs
+                    .remove(&err_hash)
+                    .expect("Inserted just before match");
+                self.forget(&err_tx);
+                self.refund_rate_token(err_tx.authority());
+                Failure::new(err_tx, Error::Full)
+            })?;
+        }
+        if txs_len == 0 {
+            self.non_empty.notify_waiters();
+        }
+        self.events.send_with(|| {
+            TransactionEvent {
+                hash,
+                block_height: None,
+                status: TransactionStatus::Queued,
+            }
+            .into()
+        });
+        trace!("Transaction queue length = {}", self.accepted_txs.len());
+        Ok(())
+    }
+
+    /// Pop single transaction from the queue. Removes all transactions that fail the `tx_check`.
+    ///
+    /// Gives up after [`MAX_CONSECUTIVE_VACANT_POPS`] hashes without a transaction in a row,
+    /// so the queue is reported empty instead of spinning under high load.
+    fn pop_from_queue(
+        &self,
+        seen: &mut Seen,
+        state_view: &impl QueueStateProbe,
+        is_committed: &impl Fn(HashOf<SignedTransaction>) -> bool,
+        reaped: &mut Reaped,
+    ) -> Option<AcceptedTransaction> {
+        let mut vacant_pops = 0;
+        loop {
+            match self.pop_step(seen, state_view, is_committed, reaped) {
+                PopStep::Empty => return None,
+                PopStep::Skipped => vacant_pops = 0,
+                PopStep::Vacant => {
+                    vacant_pops += 1;
+                    if vacant_pops >= MAX_CONSECUTIVE_VACANT_POPS {
+                        warn!(vacant_pops, "Giving up popping from the queue");
+                        return None;
+                    }
+                }
+                PopStep::Popped(tx) => return Some(tx),
+            }
+        }
+    }
+
+    /// Attempt to pop a single transaction from the queue. Removes it if it fails the `tx_check`.
+    fn pop_step(
+        &self,
+        seen: &mut Seen,
+        state_view: &impl QueueStateProbe,
+        is_committed: &impl Fn(HashOf<SignedTransaction>) -> bool,
+        reaped: &mut Reaped,
+    ) -> PopStep {
+        let Some((class, hash)) = self
+            .sub_queues
+            .iter()
+            .enumerate()
+            .find_map(|(class, sub_queue)| Some((class, sub_queue.tx_hashes.pop()?)))
+        else {
+            return PopStep::Empty;
+        };
+
+        if seen.contains_key(&hash) {
+            // Duplicate of a hash popped earlier during this collection.
+            // Only the first copy is kept, the transaction itself stays in the queue
+            warn!(tx=%hash, "Dropping duplicate hash from the queue");
+            return PopStep::Skipped;
+        }
+
+        let entry = match self.accepted_txs.entry(hash) {
+            Entry::Occupied(entry) => entry,
+            // FIXME: Reachable under high load. Investigate, see if it's a problem.
+            // As practice shows this code is not `unreachable!()`.
+            // When transactions are submitted quickly it can be reached.
+            Entry::Vacant(_) => {
+                if self.tombstones.remove(&hash).is_some() {
+                    return PopStep::Skipped;
+                }
+                warn!(tx=%hash, "Looks like we're experiencing a high load");
+                self.metrics.vacant_pops_total.inc();
+                return PopStep::Vacant;
+            }
+        };
+
+        let tx = entry.get();
+        if self.verify_hashes && tx.as_ref().hash() != hash {
+            error!(tx=%hash, actual=%tx.as_ref().hash(), "Transaction in the queue doesn't match its hash, dropping it");
+            let (_, tx) = entry.remove_entry();
+            self.forget(&tx);
+            self.notify_space_freed();
+            self.events.send(
+                TransactionEvent {
+                    hash,
+                    block_height: None,
+                    status: TransactionStatus::Dropped,
+                }
+                .into(),
+            );
+            return PopStep::Skipped;
+        }
+        let check = self.check_tx(tx, state_view).and_then(|()| {
+            if is_committed(hash) {
+                Err(Error::InBlockchain)
+            } else {
+                Ok(())
+            }
+        });
+        if let Err(Error::Expired) = check {
+            if !self.is_past_expiry_grace(tx) {
+                // Keep the transaction until it can be announced as expired
+                seen.insert(hash, class);
+                return PopStep::Skipped;
+            }
+        }
+        if let Err(e) = check {
+            let (_, tx) = entry.remove_entry();
+            if let Error::Expired | Error::BlockHeightExpired { .. } = e {
+                self.notify_expired(&hash);
+            }
+            self.forget(&tx);
+            self.notify_space_freed();
+            match e {
+                Error::Expired | Error::BlockHeightExpired { .. } => reaped.expired.push(tx),
+                // Already announced with the block it was committed in
+                Error::InBlockchain => reaped.in_blockchain += 1,
+                _ => {
+                    debug!(tx=%hash, %e, "Transaction no longer passes the checks, cancelling it");
+                    reaped.cancelled.push((hash, e));
+                }
+            }
+            return PopStep::Skipped;
+        }
+
+        seen.insert(hash, class);
+        PopStep::Popped(tx.clone())
+    }
+
+    /// Remove all transactions of the given `account` from the queue, e.g. when the account is suspended.
+    ///
+    /// Emits [`TransactionStatus::Cancelled`] events for removed transactions.
+    pub fn remove_by_account(&self, account: &AccountId) -> Vec<AcceptedTransaction> {
+        self.retain(|tx| tx.authority() != account)
+    }
+
+    /// Remove all transactions for which `f` returns `false` in a single pass, returning the removed ones.
+    ///
+    /// Emits [`TransactionStatus::Cancelled`] events for removed transactions.
+    ///
+    /// `f` is evaluated over a snapshot of the queue without holding any of its locks, so it may call into the queue.
+    /// Transactions pushed concurrently may or may not be checked, so a transaction which doesn't satisfy `f`
+    /// can remain in the queue. Likewise a removed transaction can be re-submitted and pushed right away.
+    /// Callers needing a stronger guarantee should stop accepting such transactions first,
+    /// e.g. by denying their instruction kinds with [`Self::update_config`].
+    pub fn retain(&self, f: impl Fn(&AcceptedTransaction) -> bool) -> Vec<AcceptedTransaction> {
+        let snapshot: Vec<_> = self
+            .accepted_txs
+            .iter()
+            .map(|entry| entry.value().clone())
+            .collect();
+        let rejected: BTreeSet<_> = snapshot
+            .iter()
+            .filter(|tx| !f(tx))
+            .map(|tx| tx.as_ref().hash())
+            .collect();
+        if rejected.is_empty() {
+            return Vec::new();
+        }
+        self.remove_where(
+            |tx| rejected.contains(&tx.as_ref().hash()),
+            &TransactionStatus::Cancelled(None),
+            true,
+        )
+    }
+
+    /// Remove all expired transactions past the expiry grace period from the queue without waiting for them
+    /// to be reaped during collection of transactions for a block.
+    ///
+    /// Emits [`TransactionStatus::Expired`] events for removed transactions, unless disabled in [`Config`],
+    /// many of them are announced with a single [`TransactionsExpiredEvent`].
+    /// Their space is freed right away, or once the ongoing collection of transactions for a block is finished.
+    /// Returns the number of removed transactions.
+    pub fn clear_expired(&self) -> usize {
+        let expired = self.remove_where(
+            |tx| self.is_past_expiry_grace(tx),
+            &TransactionStatus::Expired,
+            false,
+        );
+        self.announce_expired(&expired);
+        self.metrics.expired_total.inc_by(expired.len() as u64);
+        self.prune_rate_limits();
+        self.rebuild_drifted_user_counts();
+        expired.len()
+    }
+
+    /// Remove all transactions matching `predicate` with the given `status`, emitting events if `emit_events`.
+    fn remove_where(
+        &self,
+        predicate: impl Fn(&AcceptedTransaction) -> bool,
+        status: &TransactionStatus,
+        emit_events: bool,
+    ) -> Vec<AcceptedTransaction> {
+        let mut removed = Vec::new();
+
+        self.accepted_txs.retain(|hash, tx| {
+            if !predicate(tx) {
+                return true;
+            }
+            // Hash is still in `sub_queues` until it's popped or compacted
+            self.tombstones.insert(*hash);
+            if let TransactionStatus::Expired = status {
+                self.notify_expired(hash);
+            }
+            self.forget(tx);
+            removed.push(tx.clone());
+            false
+        });
+
+        if !removed.is_empty() {
+            self.try_compact_sub_queues();
+            self.notify_space_freed();
+            self.update_len_metric();
+        }
+        if !emit_events || !self.events.has_subscribers() {
+            return removed;
+        }
+        for tx in &removed {
+            self.events.send(
+                TransactionEvent {
+                    hash: tx.as_ref().hash(),
+                    block_height: None,
+                    status: status.clone(),
+                }
+                .into(),
+            );
+        }
+
+        removed
+    }
+
+    /// The transaction of `class` expiring soonest, if it expires before `deadline`.
+    fn eviction_candidate(
+        &self,
+        class: usize,
+        deadline: Duration,
+    ) -> Option<HashOf<SignedTransaction>> {
+        self.accepted_txs
+            .iter()
+            .filter(|entry| self.classify(entry.value()) == class)
+            .map(|entry| (self.expires_at(entry.value()), *entry.key()))
+            .filter(|(expires_at, _)| *expires_at < deadline)
+            .min()
+            .map(|(_, hash)| hash)
+    }
+
+    /// Evict the transaction of `class` expiring soonest, if it expires before `deadline`,
+    /// emitting [`TransactionStatus::Dropped`] event for it. Returns `true` if a transaction was evicted.
+    ///
+    /// Must not be called while holding an entry of `accepted_txs`.
+    fn evict_expiring_before(&self, class: usize, deadline: Duration) -> bool {
+        let Some(victim) = self.eviction_candidate(class, deadline) else {
+            return false;
+        };
+
+        let victim = match self.accepted_txs.entry(victim) {
+            Entry::Occupied(entry) => {
+                // Hash is still in `sub_queues` until it's popped or compacted
+                self.tombstones.insert(victim);
+                entry.remove_entry().1
+            }
+            // Already removed concurrently
+            Entry::Vacant(_) => return false,
+        };
+        self.forget(&victim);
+        self.try_compact_sub_queues();
+        self.notify_space_freed();
+        trace!(tx=%victim.as_ref().hash(), "Evicted from the full queue");
+
+        self.events.send(
+            TransactionEvent {
+                hash: victim.as_ref().hash(),
+                block_height: None,
+                status: TransactionStatus::Dropped,
+            }
+            .into(),
+        );
+        true
+    }
+
+    /// Reserve a slot for `tx` in the sub-queue of its class.
+    ///
+    /// If the class or the whole queue is full and [`Config::evict_on_full`] is set, the transaction of
+    /// the same class expiring soonest is evicted to make room, see [`Self::evict_expiring_before`].
+    /// Transactions of other classes are never evicted, since the space they free isn't usable by `tx`.
+    ///
+    /// Must not be called while holding an entry of `accepted_txs`.
+    fn reserve_slot_for(&self, tx: &AcceptedTransaction) -> Result<&SubQueue, Error> {
+        let class = self.classify(tx);
+        let sub_queue = &self.sub_queues[class];
+        let mut is_reserved = sub_queue.reserve_slot();
+        let is_full = !is_reserved || self.accepted_txs.len() >= self.capacity.get();
+        if self.evict_on_full
+            && is_full
+            && self.evict_expiring_before(class, self.expires_at(tx))
+            && !is_reserved
+        {
+            is_reserved = sub_queue.reserve_slot();
+        }
+        if !is_reserved {
+            warn!(class = %sub_queue.name, "Queue is full");
+            return Err(Error::Full);
+        }
+        Ok(sub_queue)
+    }
+
+    /// Wait until there is a transaction in the queue, so that a block producer doesn't have to poll it.
+    ///
+    /// Returns right away if the queue isn't empty. Transactions can be removed concurrently,
+    /// so the queue can turn out empty again by the time the caller looks at it.
+    pub async fn wait_non_empty(&self) {
+        loop {
+            let non_empty = self.non_empty.notified();
+            tokio::pin!(non_empty);
+            // Register interest before checking so that a push in between isn't missed
+            non_empty.as_mut().enable();
+            if !self.accepted_txs.is_empty() {
+                return;
+            }
+            non_empty.await;
+        }
+    }
+
+    /// Return the number of transactions in the queue.
+    pub fn tx_len(&self) -> usize {
+        self.accepted_txs.len()
+    }
+
+    /// Returns `true` if there are no transactions in the queue.
+    pub fn is_empty(&self) -> bool {
+        self.accepted_txs.is_empty()
+    }
+
+    /// Returns `true` if no hashes are queued, i.e. there is nothing to collect for a block.
+    ///
+    /// Hashes are popped from the sub-queues while collecting, so it's only reliable under [`Self::lock_collection`].
+    fn sub_queues_are_empty(&self) -> bool {
+        self.sub_queues
+            .iter()
+            .all(|sub_queue| sub_queue.tx_hashes.is_empty())
+    }
+
+    /// Return the number of transactions of the given `account` in the queue.
+    ///
+    /// This is a live value which may change concurrently with pushes and removals.
+    pub fn len_for_account(&self, account: &AccountId) -> usize {
+        if cfg!(feature = "no-throttling") {
+            // Transactions per user aren't tracked
+            return self
+                .accepted_txs
+                .iter()
+                .filter(|tx| tx.authority() == account)
+                .count();
+        }
+        self.txs_per_user.get(account).map_or(0, |count| *count)
+    }
+
+    /// Estimate the position of the transaction in the queue, where `0` means it's the next to be collected for a block.
+    ///
+    /// Returns `None` if the transaction isn't in the queue. Transactions of higher priority
+    /// classes are counted first, see [`Self::with_classes`].
+    ///
+    /// The sub-queues don't support indexed access, so they are drained and restored, see [`Self::compact_sub_queues`].
+    /// The cost is linear in the length of the queue. Transactions pushed concurrently can end up in front of
+    /// the restored ones, so the result is only an estimate under concurrency.
+    /// Waits for a concurrent collection of transactions for a block to finish.
+    pub fn position(&self, hash: &HashOf<SignedTransaction>) -> Option<usize> {
+        if !self.accepted_txs.contains_key(hash) {
+            return None;
+        }
+        let _collection = self.lock_collection();
+
+        let mut ahead = 0;
+        let mut position = None;
+        self.compact_sub_queues(Seen::new(), |_, queued| {
+            if position.is_some() {
+                return;
+            }
+            if queued == hash {
+                position = Some(ahead);
+            } else {
+                ahead += 1;
+            }
+        });
+
+        position
+    }
+
+    /// Return distinct authorities of transactions in the queue.
+    ///
+    /// This is a point-in-time snapshot: under concurrent pushes and removals
+    /// it may miss or include authorities whose transactions are being added or removed.
+    pub fn active_users(&self) -> Vec<AccountId> {
+        if cfg!(feature = "no-throttling") {
+            // Transactions per user aren't tracked
+            return self
+                .accepted_txs
+                .iter()
+                .map(|tx| tx.authority().clone())
+                .collect::<IndexSet<_>>()
+                .into_iter()
+                .collect();
+        }
+        self.txs_per_user
+            .iter()
+            .map(|entry| entry.key().clone())
+            .collect()
+    }
+
+    /// Gets transactions till they fill whole block or till the end of queue.
+    #[cfg(test)]
+    fn collect_transactions_for_block(
+        &self,
+        state_view: &impl QueueStateProbe,
+        max_txs_in_block: NonZeroUsize,
+    ) -> Vec<AcceptedTransaction> {
+        let mut transactions = Vec::with_capacity(max_txs_in_block.get());
+        self.get_transactions_for_block(state_view, max_txs_in_block, &mut transactions);
+        transactions
+    }
+
+    /// Put transactions into provided vector until they fill the whole block or there are no more transactions in the queue.
+    ///
+    /// Concurrent collections are serialized, i.e. each one waits for the previous one to finish.
+    pub fn get_transactions_for_block(
+        &self,
+        state_view: &impl QueueStateProbe,
+        max_txs_in_block: NonZeroUsize,
+        transactions: &mut Vec<AcceptedTransaction>,
+    ) {
+        self.get_transactions_for_block_checked(state_view, max_txs_in_block, transactions, |_| {
+            false
+        });
+    }
+
+    /// Same as [`Self::get_transactions_for_block`], but additionally skips (and removes from the queue)
+    /// transactions for which `is_committed` returns `true`.
+    ///
+    /// `state_view` is a snapshot taken before collection, so a transaction committed while transactions
+    /// are being collected can still be selected. `is_committed` is called for every popped transaction
+    /// and can consult a fresh [`StateView`] or a set of transactions committed during this round.
+    /// This trades the cost of the additional check for every transaction for consistency.
+    pub fn get_transactions_for_block_checked(
+        &self,
+        state_view: &impl QueueStateProbe,
+        max_txs_in_block: NonZeroUsize,
+        transactions: &mut Vec<AcceptedTransaction>,
+        is_committed: impl Fn(HashOf<SignedTransaction>) -> bool,
+    ) {
+        self.collect_for_block(state_view, max_txs_in_block, transactions, is_committed);
+    }
+
+    /// Same as [`Self::get_transactions_for_block`], but also returns the number of transactions
+    /// removed from the queue while collecting, e.g. for logging and metrics.
+    pub fn get_transactions_for_block_with_summary(
+        &self,
+        state_view: &impl QueueStateProbe,
+        max_txs_in_block: NonZeroUsize,
+        transactions: &mut Vec<AcceptedTransaction>,
+    ) -> ReapSummary {
+        self.collect_for_block(state_view, max_txs_in_block, transactions, |_| false)
+    }
+
+    fn collect_for_block(
+        &self,
+        state_view: &impl QueueStateProbe,
+        max_txs_in_block: NonZeroUsize,
+        transactions: &mut Vec<AcceptedTransaction>,
+        is_committed: impl Fn(HashOf<SignedTransaction>) -> bool,
+    ) -> ReapSummary {
+        let _collection = self.lock_collection();
+        if self.sub_queues_are_empty() {
+            // Nothing to set up for an idle node polling the queue
+            return ReapSummary::default();
+        }
+        let span = self.collection_span(max_txs_in_block);
+        let _enter = span.enter();
+
+        self.select_pinned(state_view, max_txs_in_block, transactions, &is_committed);
+        if transactions.len() >= max_txs_in_block.get() {
+            return ReapSummary::default();
+        }
+
+        let mut seen_queue = Seen::new();
+        let mut reaped = Reaped::default();
+
+        let txs_from_queue = core::iter::from_fn(|| {
+            self.pop_from_queue(&mut seen_queue, state_view, &is_committed, &mut reaped)
+        });
+
+        let transactions_hashes: IndexSet<HashOf<SignedTransaction>> =
+            transactions.iter().map(|tx| tx.as_ref().hash()).collect();
+        // Transactions older than the soft age threshold only fill the block after fresher ones
+        let mut old_transactions = Vec::new();
+        let txs = txs_from_queue
+            .filter(|tx| !transactions_hashes.contains(&tx.as_ref().hash()))
+            .filter_map(|tx| {
+                if self.is_old(&tx) {
+                    old_transactions.push(tx);
+                    return None;
+                }
+                Some(tx)
+            })
+            .take(max_txs_in_block.get() - transactions.len());
+        transactions.extend(txs);
+        let remaining = max_txs_in_block.get() - transactions.len();
+        transactions.extend(old_transactions.into_iter().take(remaining));
+
+        self.finish_collection(seen_queue, reaped)
+    }
+
+    /// Same as [`Self::get_transactions_for_block`], but periodically yields to the runtime
+    /// so that scanning a large queue doesn't starve other tasks.
+    ///
+    /// Given the same queue contents, collects the same transactions as the synchronous version.
+    pub async fn get_transactions_for_block_async(
+        &self,
+        state_view: &impl QueueStateProbe,
+        max_txs_in_block: NonZeroUsize,
+        transactions: &mut Vec<AcceptedTransaction>,
+    ) {
+        let span = self.collection_span(max_txs_in_block);
+        self.collect_for_block_async(state_view, max_txs_in_block, transactions)
+            .instrument(span)
+            .await;
+    }
+
+    async fn collect_for_block_async(
+        &self,
+        state_view: &impl QueueStateProbe,
+        max_txs_in_block: NonZeroUsize,
+        transactions: &mut Vec<AcceptedTransaction>,
+    ) {
+        let _collection = self.lock_collection_async().await;
+        if self.sub_queues_are_empty() {
+            return;
+        }
+        self.select_pinned(state_view, max_txs_in_block, transactions, &|_| false);
+        if transactions.len() >= max_txs_in_block.get() {
+            return;
+        }
+
+        let mut seen_queue = Seen::new();
+        let mut reaped = Reaped::default();
+
+        let transactions_hashes: IndexSet<HashOf<SignedTransaction>> =
+            transactions.iter().map(|tx| tx.as_ref().hash()).collect();
+        let mut old_transactions = Vec::new();
+        let mut pops = 0_usize;
+        while transactions.len() < max_txs_in_block.get() {
+            pops += 1;
+            if pops % POPS_PER_YIELD == 0 {
+                tokio::task::yield_now().await;
+            }
+
+            let tx = match self.pop_step(&mut seen_queue, state_view, &|_| false, &mut reaped) {
+                PopStep::Empty => break,
+                // Yielding periodically already prevents a busy spin
+                PopStep::Skipped | PopStep::Vacant => continue,
+                PopStep::Popped(tx) => tx,
+            };
+            if transactions_hashes.contains(&tx.as_ref().hash()) {
+                continue;
+            }
+            if self.is_old(&tx) {
+                old_transactions.push(tx);
+                continue;
+            }
+            transactions.push(tx);
+        }
+        let remaining = max_txs_in_block.get() - transactions.len();
+        transactions.extend(old_transactions.into_iter().take(remaining));
+
+        self.finish_collection(seen_queue, reaped);
+    }
+
+    /// Span correlating logs of a single collection of transactions for a block.
+    fn collection_span(&self, max_txs_in_block: NonZeroUsize) -> Span {
+        trace_span!(
+            "queue_collect",
+            attempt = self.collection_attempts.fetch_add(1, Ordering::Relaxed),
+            max_txs_in_block = max_txs_in_block.get(),
+            queue_len = self.accepted_txs.len()
+        )
+    }
+
+    /// Register `watcher` to be notified when the transaction expires and is removed from the queue.
+    ///
+    /// If the transaction leaves the queue otherwise (e.g. it's committed or cancelled), `watcher` is dropped instead.
+    /// Replaces the previously registered watcher of the transaction, if any.
+    /// Returns `false` (dropping `watcher`) if the transaction isn't in the queue.
+    pub fn on_expiry(&self, hash: HashOf<SignedTransaction>, watcher: oneshot::Sender<()>) -> bool {
+        if !self.accepted_txs.contains_key(&hash) {
+            return false;
+        }
+        self.expiry_watchers.insert(hash, watcher);
+        // The transaction could've been removed concurrently, before the watcher was registered
+        if !self.accepted_txs.contains_key(&hash) {
+            self.expiry_watchers.remove(&hash);
+            return false;
+        }
+        true
+    }
+
+    /// Time at which the transaction was pushed into the queue of this peer, if it's in the queue.
+    ///
+    /// Unlike [`SignedTransaction::creation_time`] it excludes the time the transaction took to reach this peer,
+    /// so the difference of the two attributes latency to the network rather than to the queue.
+    /// A transaction pushed back after removal is seen anew, unless it was removed by [`Queue::drain_for_block`]
+    /// and is pushed back (e.g. with [`Queue::requeue`]) before the next drain.
+    pub fn first_seen(&self, hash: &HashOf<SignedTransaction>) -> Option<Duration> {
+        self.first_seen.get(hash).map(|seen| seen.unix_time)
+    }
+
+    /// Label the transaction, e.g. to flag it for review.
+    ///
+    /// Labels are metadata local to this queue: they don't change the transaction and don't affect consensus
+    /// or contents of blocks. They are cleared once the transaction leaves the queue.
+    /// Use [`Self::has_label`] to filter transactions, e.g. with [`Self::retain`].
+    /// Returns `false` if the transaction isn't in the queue.
+    pub fn set_label(&self, hash: HashOf<SignedTransaction>, label: String) -> bool {
+        if !self.accepted_txs.contains_key(&hash) {
+            return false;
+        }
+        self.labels.entry(hash).or_default().insert(label);
+        // The transaction could've been removed concurrently, before the label was set
+        if !self.accepted_txs.contains_key(&hash) {
+            self.labels.remove(&hash);
+            return false;
+        }
+        true
+    }
+
+    /// Labels of the transaction in lexicographical order, see [`Self::set_label`].
+    pub fn labels(&self, hash: &HashOf<SignedTransaction>) -> Vec<String> {
+        self.labels
+            .get(hash)
+            .map(|labels| labels.iter().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    /// Check if the transaction has the `label`, see [`Self::set_label`].
+    pub fn has_label(&self, hash: &HashOf<SignedTransaction>, label: &str) -> bool {
+        self.labels
+            .get(hash)
+            .is_some_and(|labels| labels.contains(label))
+    }
+
+    /// Pin the transaction so that it's selected for the next block before any other transactions.
+    ///
+    /// Unlike transaction classes, this guarantees inclusion as long as the transaction stays valid.
+    /// A pinned transaction which fails the checks is unpinned and reported when transactions are collected for a block.
+    /// Returns `false` if the transaction isn't in the queue or the maximum number of pinned transactions is reached.
+    pub fn pin(&self, hash: &HashOf<SignedTransaction>) -> bool {
+        if !self.accepted_txs.contains_key(hash) {
+            return false;
+        }
+        if self.pinned.len() >= self.max_pinned_transactions && !self.pinned.contains(hash) {
+            warn!(
+                max = self.max_pinned_transactions,
+                "Achieved maximum amount of pinned transactions"
+            );
+            return false;
+        }
+        self.pinned.insert(*hash);
+        true
+    }
+
+    /// Unpin the transaction, see [`Self::pin`]. Returns `false` if it wasn't pinned.
+    pub fn unpin(&self, hash: &HashOf<SignedTransaction>) -> bool {
+        self.pinned.remove(hash).is_some()
+    }
+
+    /// Put valid pinned transactions into `transactions`, unpinning invalid ones.
+    fn select_pinned(
+        &self,
+        state_view: &impl QueueStateProbe,
+        max_txs_in_block: NonZeroUsize,
+        transactions: &mut Vec<AcceptedTransaction>,
+        is_committed: &impl Fn(HashOf<SignedTransaction>) -> bool,
+    ) {
+        if self.pinned.is_empty() {
+            return;
+        }
+
+        let pinned: Vec<_> = self.pinned.iter().map(|hash| *hash).collect();
+        for hash in pinned {
+            if transactions.len() >= max_txs_in_block.get() {
+                break;
+            }
+            if transactions.iter().any(|tx| tx.as_ref().hash() == hash) {
+                continue;
+            }
+            let check = self.accepted_txs.get(&hash).map(|tx| {
+                self.check_tx(&tx, state_view)
+                    .and_then(|()| {
+                        if is_committed(hash) {
+                            Err(Error::InBlockchain)
+                        } else {
+                            Ok(())
+                        }
+                    })
+                    .map(|()| tx.clone())
+            });
+            match check {
+                Some(Ok(tx)) => transactions.push(tx),
+                // Removed from the queue when popped
+                Some(Err(err)) => {
+                    warn!(tx=%hash, ?err, "Pinned transaction is invalid, unpinning");
+                    self.pinned.remove(&hash);
+                }
+                None => {
+                    self.pinned.remove(&hash);
+                }
+            }
+        }
+    }
+
+    /// Return transactions seen during collection back to the queue and report reaped ones.
+    fn finish_collection(&self, seen_queue: Seen, reaped: Reaped) -> ReapSummary {
+        if self.tombstones.is_empty() {
+            for (hash, class) in seen_queue {
+                self.restore_hash(class, hash);
+            }
+        } else {
+            // Transactions were removed while their hashes couldn't be compacted
+            self.compact_sub_queues(seen_queue, |_, _| {});
+        }
+
+        let summary = ReapSummary {
+            expired: reaped.expired.len(),
+            in_blockchain: reaped.in_blockchain,
+        };
+        self.metrics.expired_total.inc_by(summary.expired as u64);
+        self.update_len_metric();
+
+        for (hash, err) in reaped.cancelled {
+            self.events.send(
+                TransactionEvent {
+                    hash,
+                    block_height: None,
+                    status: TransactionStatus::Cancelled(Some(err.to_string())),
+                }
+                .into(),
+            );
+        }
+
+        self.announce_expired(&reaped.expired);
+
+        summary
+    }
+
+    /// Emit events for `expired` transactions, unless disabled in [`Config`].
+    ///
+    /// Many transactions expiring together are announced with a single [`TransactionsExpiredEvent`].
+    fn announce_expired(&self, expired: &[AcceptedTransaction]) {
+        if !self.emit_expiry_events {
+            return;
+        }
+        match expired {
+            [] => {}
+            [tx] => self.events.send_with(|| {
+                TransactionEvent {
+                    hash: tx.as_ref().hash(),
+                    block_height: None,
+                    status: TransactionStatus::Expired,
+                }
+                .into()
+            }),
+            txs => self.events.send_with(|| {
+                TransactionsExpiredEvent {
+                    hashes: txs.iter().map(|tx| tx.as_ref().hash()).collect(),
+                }
+                .into()
+            }),
+        }
+    }
+
+    /// Return `seen` hashes to the queue, dropping hashes of removed transactions and duplicates from the sub-queues.
+    ///
+    /// Hashes of removed transactions stay in the sub-queues until they are popped, taking up space there.
+    /// `inspect` is called for every hash left in the queue, in the order of collection within its class.
+    /// Must be called under [`Self::lock_collection`].
+    fn compact_sub_queues(
+        &self,
+        seen: Seen,
+        mut inspect: impl FnMut(usize, &HashOf<SignedTransaction>),
+    ) {
+        let mut seen_by_class = vec![Vec::new(); self.sub_queues.len()];
+        for (hash, class) in seen {
+            seen_by_class[class].push(hash);
+        }
+
+        for (class, (sub_queue, seen)) in self.sub_queues.iter().zip(seen_by_class).enumerate() {
+            let queued: Vec<_> = core::iter::from_fn(|| sub_queue.tx_hashes.pop()).collect();
+            let mut kept = BTreeSet::new();
+            for hash in queued.into_iter().chain(seen) {
+                if !kept.insert(hash) {
+                    continue;
+                }
+                // Checked under the entry, so that a concurrent push can't take over the dropped hash
+                let is_removed = match self.accepted_txs.entry(hash) {
+                    Entry::Occupied(_) => false,
+                    Entry::Vacant(_) => {
+                        self.tombstones.remove(&hash);
+                        true
+                    }
+                };
+                if !is_removed && self.restore_hash(class, hash) {
+                    inspect(class, &hash);
+                }
+            }
+        }
+    }
+
+    /// Compact the sub-queues after transactions were removed, see [`Self::compact_sub_queues`].
+    ///
+    /// Skipped while transactions are collected for a block, the collection compacts them once it's finished.
+    fn try_compact_sub_queues(&self) {
+        if let Some(_collection) = self.try_lock_collection() {
+            self.compact_sub_queues(Seen::new(), |_, _| {});
+        }
+    }
+
+    /// Put `hash` back into the sub-queue of `class`, returning `false` if it didn't fit.
+    ///
+    /// Slots are reserved for every transaction in the queue, so it only fails if hashes of removed transactions
+    /// pile up during a collection. The transaction is then dropped from the queue rather than lost.
+    fn restore_hash(&self, class: usize, hash: HashOf<SignedTransaction>) -> bool {
+        let sub_queue = &self.sub_queues[class];
+        let Err(hash) = sub_queue.tx_hashes.push(hash) else {
+            return true;
+        };
+
+        error!(tx=%hash, class=%sub_queue.name, "No space to return the transaction to the queue, dropping it");
+        let dropped = match self.accepted_txs.entry(hash) {
+            Entry::Occupied(entry) => Some(entry.remove_entry().1),
+            Entry::Vacant(_) => {
+                self.tombstones.remove(&hash);
+                None
+            }
+        };
+        if let Some(tx) = dropped {
+            self.forget(&tx);
+            self.notify_space_freed();
+            self.events.send(
+                TransactionEvent {
+                    hash,
+                    block_height: None,
+                    status: TransactionStatus::Dropped,
+                }
+                .into(),
+            );
+        }
+        false
+    }
+
+    fn update_len_metric(&self) {
+        self.metrics.len.set(
+            self.accepted_txs
+                .len()
+                .try_into()
+                .expect("Queue length should fit into i64"),
+        );
+    }
+
+    /// Check that the user adhered to the maximum transaction per user limit and increment their transaction count.
+    fn check_and_increase_per_user_tx_count(&self, account_id: &AccountId) -> Result<(), Error> {
+        if cfg!(feature = "no-throttling") {
+            return Ok(());
+        }
+        match self.txs_per_user.entry(account_id.clone()) {
+            Entry::Vacant(vacant) => {
+                vacant.insert(1);
+            }
+            Entry::Occupied(mut occupied) => {
+                if let Err(err) = self.check_per_user_capacity(*occupied.get()) {
+                    warn!(
+                        max_txs_per_user = self.capacity_per_user(),
+                        %account_id,
+                        "Account reached maximum allowed number of transactions in the queue per user"
+                    );
+                    return Err(err);
+                }
+                *occupied.get_mut() += 1;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Check that there is space for one more transaction when there are already `txs_len` in the queue.
+    fn check_capacity(&self, txs_len: usize) -> Result<(), Error> {
+        if txs_len >= self.capacity.get() {
+            return Err(Error::Full);
+        }
+        Ok(())
+    }
+
+    /// Number of instructions in all transactions in the queue.
+    ///
+    /// A smart contract counts as a single instruction.
+    pub fn total_instructions(&self) -> usize {
+        self.total_instructions.load(Ordering::Relaxed)
+    }
+
+    /// Check that `instructions` more fit into the queue.
+    fn check_instruction_budget(&self, instructions: usize) -> Result<(), Error> {
+        let total = self.total_instructions();
+        if self
+            .max_total_instructions
+            .is_some_and(|max| total.saturating_add(instructions) > max.get())
+        {
+            return Err(Error::InstructionBudgetExceeded);
+        }
+        Ok(())
+    }
+
+    /// Add `instructions` to the total, unless they don't fit into the queue.
+    fn reserve_instructions(&self, instructions: usize) -> Result<(), Error> {
+        self.total_instructions
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |total| {
+                let total = total.checked_add(instructions)?;
+                match self.max_total_instructions {
+                    Some(max) if total > max.get() => None,
+                    _ => Some(total),
+                }
+            })
+            .map(|_| ())
+            .map_err(|total| {
+                warn!(
+                    total,
+                    instructions,
+                    max = self.max_total_instructions.map(NonZeroUsize::get),
+                    "Achieved maximum amount of instructions"
+                );
+                Error::InstructionBudgetExceeded
+            })
+    }
+
+    /// Check that a user with `txs` transactions in the queue can add one more.
+    fn check_per_user_capacity(&self, txs: usize) -> Result<(), Error> {
+        if cfg!(feature = "no-throttling") {
+            return Ok(());
+        }
+        if txs >= self.capacity_per_user().get() {
+            return Err(Error::MaximumTransactionsPerUser);
+        }
+        Ok(())
+    }
+
+    /// Drop the bookkeeping of a transaction removed from the queue.
+    fn forget(&self, tx: &AcceptedTransaction) {
+        self.sub_queues[self.classify(tx)].release_slot();
+        self.decrease_per_user_tx_count(tx.authority());
+        self.total_instructions
+            .fetch_sub(instruction_count(tx), Ordering::Relaxed);
+        let hash = tx.as_ref().hash();
+        if let Some(key) = idempotency_key(tx) {
+            self.idempotency_keys
+                .remove_if(&(tx.authority().clone(), key), |_, owner| *owner == hash);
+        }
+        self.pinned.remove(&hash);
+        // Dropping the sender tells the watcher that the transaction left the queue otherwise
+        self.expiry_watchers.remove(&hash);
+        self.first_seen.remove(&hash);
+        self.labels.remove(&hash);
+    }
+
+    /// Notify the watcher registered with [`Self::on_expiry`] that the transaction expired.
+    ///
+    /// Must be called before the transaction is forgotten.
+    fn notify_expired(&self, hash: &HashOf<SignedTransaction>) {
+        if let Some((_, watcher)) = self.expiry_watchers.remove(hash) {
+            // The watcher may have lost interest
+            let _ = watcher.send(());
+        }
+    }
+
+    /// Recompute the number of transactions per user from the transactions in the queue.
+    ///
+    /// Recovers from a bug in the accounting without restarting the node. Transactions pushed
+    /// or removed concurrently can be miscounted, so it's best called when the queue is quiet.
+    pub fn rebuild_user_counts(&self) {
+        if cfg!(feature = "no-throttling") {
+            return;
+        }
+        self.txs_per_user.clear();
+        for tx in &self.accepted_txs {
+            *self.txs_per_user.entry(tx.authority().clone()).or_default() += 1;
+        }
+    }
+
+    /// Rebuild the number of transactions per user if it was found out of sync.
+    ///
+    /// Must not be called while holding any references into `accepted_txs`.
+    fn rebuild_drifted_user_counts(&self) {
+        if self.user_counts_drifted.swap(false, Ordering::Relaxed) {
+            self.rebuild_user_counts();
+        }
+    }
+
+    fn decrease_per_user_tx_count(&self, account_id: &AccountId) {
+        if cfg!(feature = "no-throttling") {
+            return;
+        }
+        let Entry::Occupied(mut occupied) = self.txs_per_user.entry(account_id.clone()) else {
+            // Can be called while iterating over `accepted_txs`, so the rebuild is deferred
+            error!(%account_id, "Decreasing the number of transactions of a user without any, rebuilding the accounting");
+            self.user_counts_drifted.store(true, Ordering::Relaxed);
+            return;
+        };
+
+        let count = occupied.get_mut();
+        if *count > 1 {
+            *count -= 1;
+        } else {
+            occupied.remove_entry();
+        }
+    }
+}
+
+#[cfg(test)]
+// this is `pub` to re-use internal utils
+pub mod tests {
+    use std::{str::FromStr, sync::Arc, thread, time::Duration};
+
+    use iroha_crypto::Hash;
+    use iroha_data_model::{parameter::TransactionParameters, prelude::*};
+    use nonzero_ext::nonzero;
+    use proptest::{
+        collection::vec,
+        prelude::{any, Just, ProptestConfig, Strategy, TestCaseError},
+        prop_assert, prop_assert_eq, prop_oneof,
+        test_runner::TestRunner,
+    };
+    use rand::Rng as _;
+    use test_samples::gen_account_in;
+    use tokio::test;
+
+    use super::{health::QueueHealth, reaper::QueueReaper, *};
+    use crate::{
+        kura::Kura,
+        query::store::LiveQueryStore,
+        smartcontracts::isi::Registrable as _,
+        state::{State, World},
+    };
+
+    impl Queue {
+        pub fn test(cfg: Config, time_source: &TimeSource) -> Self {
+            Self {
+                events: QueueEvents::new(
+                    tokio::sync::broadcast::Sender::new(1),
+                    cfg.events_capacity,
+                    cfg.events_on_overflow,
+                ),
+                sub_queues: vec![SubQueue::new(TransactionClass::default_for(cfg.capacity))],
+                classifier: Classifier::single(),
+                observer: None,
+                expiry_policy: Box::new(DefaultExpiryPolicy),
+                verify_hashes: cfg!(debug_assertions),
+                accepted_txs: accepted_txs_map(cfg.shard_amount),
+                txs_per_user: DashMap::new(),
+                user_counts_drifted: AtomicBool::new(false),
+                idempotency_keys: DashMap::new(),
+                pinned: DashSet::new(),
+                expiry_watchers: DashMap::new(),
+                first_seen: DashMap::new(),
+                drained_first_seen: DashMap::new(),
+                labels: DashMap::new(),
+                max_pinned_transactions: cfg.max_pinned_transactions,
+                denied_instruction_kinds: RwLock::new(cfg.denied_instruction_kinds.clone()),
+                halt_reason: RwLock::new(None),
+                tombstones: DashSet::new(),
+                capacity: cfg.capacity,
+                capacity_per_user: AtomicUsize::new(cfg.capacity_per_user.get()),
+                evict_on_full: cfg.evict_on_full,
+                rate_limits: DashMap::new(),
+                rate_limit_burst: cfg.rate_limit_burst,
+                rate_limit_refill_interval: cfg.rate_limit_refill_interval,
+                total_instructions: AtomicUsize::new(0),
+                max_total_instructions: cfg.max_total_instructions,
+                time_source: time_source.clone(),
+                tx_time_to_live: AtomicDuration::new(cfg.transaction_time_to_live),
+                max_tx_time_to_live: cfg.max_transaction_time_to_live,
+                future_threshold: AtomicDuration::new(cfg.future_threshold),
+                expiry_grace: cfg.expiry_grace,
+                soft_age_threshold: cfg.soft_age_threshold,
+                emit_expiry_events: cfg.emit_expiry_events,
+                metrics: QueueMetrics::default(),
+                recent_rejections: Mutex::new(VecDeque::new()),
+                rejection_log_capacity: cfg.rejection_log_capacity,
+                space_generation: Mutex::new(0),
+                space_freed: Condvar::new(),
+                space_freed_async: Notify::new(),
+                non_empty: Notify::new(),
+                collecting: Mutex::new(false),
+                collection_finished: Condvar::new(),
+                collection_finished_async: Notify::new(),
+                collection_attempts: AtomicU64::new(0),
+            }
+        }
+    }
+
+    fn accepted_tx_by_someone(time_source: &TimeSource) -> AcceptedTransaction {
+        let (account_id, key_pair) = gen_account_in("wonderland");
+        accepted_tx_by(account_id, &key_pair, time_source)
+    }
+
+    fn accepted_tx_by(
+        account_id: AccountId,
+        key_pair: &KeyPair,
+        time_source: &TimeSource,
+    ) -> AcceptedTransaction {
+        let chain_id = ChainId::from("00000000-0000-0000-0000-000000000000");
+        // Random name needed so all transactions will be different
+        let domain_name = format!("dummy{}", rand::random::<u64>());
+        let fail_isi = Unregister::domain(domain_name.parse().unwrap());
+        let instructions = [fail_isi];
+        let tx =
+            TransactionBuilder::new_with_time_source(chain_id.clone(), account_id, time_source)
+                .with_instructions(instructions)
+                .sign(key_pair.private_key());
+        let limits = TransactionParameters {
+            max_instructions: nonzero!(4096_u64),
+            smart_contract_size: nonzero!(1024_u64),
+        };
+        AcceptedTransaction::accept(tx, &chain_id, limits).expect("Failed to accept Transaction.")
+    }
+
+    pub fn world_with_test_domains() -> World {
+        let domain_id = DomainId::from_str("wonderland").expect("Valid");
+        let (account_id, _account_keypair) = gen_account_in("wonderland");
+        let domain = Domain::new(domain_id).build(&account_id);
+        let account = Account::new(account_id.clone()).build(&account_id);
+        World::with([domain], [account], [])
+    }
+
+    fn config_factory() -> Config {
+        Config {
+            transaction_time_to_live: Duration::from_secs(100),
+            capacity: 100.try_into().unwrap(),
+            ..Config::default()
+        }
+    }
+
+    #[test]
+    async fn push_tx() {
+        let kura = Kura::blank_kura_for_testing();
+        let query_handle = LiveQueryStore::test().start();
+        let state = Arc::new(State::new(world_with_test_domains(), kura, query_handle));
+        let state_view = state.view();
+
+        let time_source = TimeSource::new_fixed(Duration::default());
+
+        let queue = Queue::test(config_factory(), &time_source);
+
+        queue
+            .push(accepted_tx_by_someone(&time_source), &state_view)
+            .expect("Failed to push tx into queue");
+    }
+
+    #[test]
+    async fn push_tx_with_duplicate_idempotency_key() {
+        let chain_id = ChainId::from("00000000-0000-0000-0000-000000000000");
+        let (alice_id, alice_keypair) = gen_account_in("wonderland");
+        let kura = Kura::blank_kura_for_testing();
+        let query_handle = LiveQueryStore::test().start();
+        let state = State::new(world_with_test_domains(), kura, query_handle);
+        let state_view = state.view();
+
+        let (time_handle, time_source) = TimeSource::new_mock(Duration::default());
+        let queue = Queue::test(config_factory(), &time_source);
+        let retry_tx = |key: &str| {
+            let mut metadata = Metadata::default();
+            metadata.insert(IDEMPOTENCY_KEY.parse().unwrap(), key);
+            let tx = TransactionBuilder::new_with_time_source(
+                chain_id.clone(),
+                alice_id.clone(),
+                &time_source,
+            )
+            .with_instructions([Unregister::domain("dummy".parse().unwrap())])
+            .with_metadata(metadata)
+            .sign(alice_keypair.private_key());
+            let limits = TransactionParameters {
+                max_instructions: nonzero!(4096_u64),
+                smart_contract_size: nonzero!(1024_u64),
+            };
+            AcceptedTransaction::accept(tx, &chain_id, limits)
+                .expect("Failed to accept Transaction.")
+        };
+
+        let first = retry_tx("transfer-1");
+        queue
+            .push(first.clone(), &state_view)
+            .expect("Failed to push tx into queue");
+        // Retry created later has a different hash
+        time_handle.advance(Duration::from_millis(10));
+        let retry = retry_tx("transfer-1");
+        assert_ne!(first.as_ref().hash(), retry.as_ref().hash());
+        assert!(matches!(
+            queue.push(retry.clone(), &state_view),
+            Err(Failure {
+                err: Error::DuplicateIdempotencyKey,
+                ..
+            })
+        ));
+        queue
+            .push(retry_tx("transfer-2"), &state_view)
+            .expect("Failed to push tx into queue");
+        assert_eq!(queue.len_for_account(&alice_id), 2);
+
+        // The key is released once the transaction leaves the queue
+        queue.remove_by_account(&alice_id);
+        queue
+            .push(retry, &state_view)
+            .expect("Failed to push tx into queue");
+    }
+
+    #[test]
+    async fn would_accept_matches_push() {
+        let kura = Kura::blank_kura_for_testing();
+        let query_handle = LiveQueryStore::test().start();
+        let state = State::new(world_with_test_domains(), kura, query_handle);
+        let state_view = state.view();
+
+        let time_source = TimeSource::new_fixed(Duration::default());
+        let queue = Queue::test(
+            Config {
+                capacity: nonzero!(1_usize),
+                ..config_factory()
+            },
+            &time_source,
+        );
+        let tx = accepted_tx_by_someone(&time_source);
+        assert!(queue.would_accept(&tx, &state_view).is_ok());
+        assert_eq!(queue.tx_len(), 0);
+        queue
+            .push(tx.clone(), &state_view)
+            .expect("Failed to push tx into queue");
+
+        assert!(matches!(
+            queue.would_accept(&tx, &state_view),
+            Err(Error::IsInQueue)
+        ));
+        let other_tx = accepted_tx_by_someone(&time_source);
+        assert!(matches!(
+            queue.would_accept(&other_tx, &state_view),
+            Err(Error::Full)
+        ));
+        assert!(matches!(
+            queue.push(other_tx, &state_view),
+            Err(Failure {
+                err: Error::Full,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    async fn push_blocking_waits_for_space() {
+        let kura = Kura::blank_kura_for_testing();
+        let query_handle = LiveQueryStore::test().start();
+        let state = State::new(world_with_test_domains(), kura, query_handle);
+
+        let (time_handle, time_source) = TimeSource::new_mock(Duration::default());
+
+        let queue = Queue::test(
+            Config {
+                transaction_time_to_live: Duration::from_millis(100),
+                capacity: nonzero!(1_usize),
+                ..config_factory()
+            },
+            &time_source,
+        );
+        queue
+            .push(accepted_tx_by_someone(&time_source), &state.view())
+            .expect("Failed to push tx into queue");
+
+        time_handle.advance(Duration::from_millis(101));
+        let tx = accepted_tx_by_someone(&time_source);
+        assert!(matches!(
+            queue.push_blocking(tx.clone(), &state.view(), Duration::from_millis(10)),
+            Err(Failure {
+                err: Error::Full,
+                ..
+            })
+        ));
+
+        thread::scope(|scope| {
+            let pusher =
+                scope.spawn(|| queue.push_blocking(tx, &state.view(), Duration::from_secs(10)));
+            // Removes the expired transaction freeing the slot
+            while !pusher.is_finished() {
+                queue.collect_transactions_for_block(&state.view(), nonzero!(1_usize));
+                thread::sleep(Duration::from_millis(1));
+            }
+            pusher
+                .join()
+                .unwrap()
+                .expect("Failed to push tx into queue");
+        });
+        assert_eq!(queue.tx_len(), 1);
+    }
+
+    #[test]
+    async fn push_tx_from_unregistered_authority() {
+        let kura = Kura::blank_kura_for_testing();
+        let query_handle = LiveQueryStore::test().start();
+        let state = State::new(world_with_test_domains(), kura, query_handle);
+        let state_view = state.view();
+
+        let time_source = TimeSource::new_fixed(Duration::default());
+
+        let queue = Queue::test(config_factory(), &time_source);
+        let (account_id, key_pair) = gen_account_in("wonderland");
+        assert!(state_view.world().account(&account_id).is_err());
+
+        queue
+            .push(
+                accepted_tx_by(account_id, &key_pair, &time_source),
+                &state_view,
+            )
+            .expect("Failed to push tx into queue");
+        assert_eq!(
+            queue
+                .collect_transactions_for_block(&state_view, nonzero!(1_usize))
+                .len(),
+            1
+        );
+    }
+
+    #[test]
+    async fn remove_by_account() {
+        let kura = Kura::blank_kura_for_testing();
+        let query_handle = LiveQueryStore::test().start();
+        let state = State::new(world_with_test_domains(), kura, query_handle);
+        let state_view = state.view();
+
+        let (time_handle, time_source) = TimeSource::new_mock(Duration::default());
+
+        let queue = Queue::test(config_factory(), &time_source);
+        let (alice_id, alice_keypair) = gen_account_in("wonderland");
+        let (bob_id, bob_keypair) = gen_account_in("wonderland");
+        for _ in 0..2 {
+            queue
+                .push(
+                    accepted_tx_by(alice_id.clone(), &alice_keypair, &time_source),
+                    &state_view,
+                )
+                .expect("Failed to push tx into queue");
+            // Make transactions distinct
+            time_handle.advance(Duration::from_millis(1));
+        }
+        let bob_tx = accepted_tx_by(bob_id.clone(), &bob_keypair, &time_source);
+        queue
+            .push(bob_tx.clone(), &state_view)
+            .expect("Failed to push tx into queue");
+        let mut active_users = queue.active_users();
+        active_users.sort();
+        let mut expected_users = vec![alice_id.clone(), bob_id.clone()];
+        expected_users.sort();
+        assert_eq!(active_users, expected_users);
+
+        let removed = queue.remove_by_account(&alice_id);
+        assert_eq!(removed.len(), 2);
+        assert!(removed.iter().all(|tx| tx.authority() == &alice_id));
+        assert_eq!(queue.len_for_account(&alice_id), 0);
+        assert_eq!(queue.len_for_account(&bob_id), 1);
+        assert_eq!(queue.active_users(), vec![bob_id.clone()]);
+
+        let collected = queue.collect_transactions_for_block(&state_view, nonzero!(10_usize));
+        assert_eq!(collected, vec![bob_tx]);
+        assert!(queue.tombstones.is_empty());
+    }
+
+    #[test]
+    async fn retain_removes_rejected_transactions() {
+        let kura = Kura::blank_kura_for_testing();
+        let query_handle = LiveQueryStore::test().start();
+        let state = State::new(world_with_test_domains(), kura, query_handle);
+        let state_view = state.view();
+
+        let (time_handle, time_source) = TimeSource::new_mock(Duration::default());
+        let mut queue = Queue::test(config_factory(), &time_source);
+        let (event_sender, mut event_receiver) = tokio::sync::broadcast::channel(10);
+        queue.events = QueueEvents::new(
+            event_sender,
+            config_factory().events_capacity,
+            config_factory().events_on_overflow,
+        );
+        let txs: Vec<_> = (0..4)
+            .map(|_| {
+                let tx = accepted_tx_by_someone(&time_source);
+                queue
+                    .push(tx.clone(), &state_view)
+                    .expect("Failed to push tx into queue");
+                let _queued = event_receiver.try_recv().expect("Queued event is sent");
+                time_handle.advance(Duration::from_millis(1));
+                tx
+            })
+            .collect();
+
+        let kept = [&txs[1], &txs[3]].map(|tx| tx.as_ref().hash());
+        // The predicate may look into the queue without deadlocking
+        let removed = queue.retain(|tx| {
+            queue.accepted_txs.contains_key(&tx.as_ref().hash())
+                && kept.contains(&tx.as_ref().hash())
+        });
+        let removed_hashes: BTreeSet<_> = removed.iter().map(|tx| tx.as_ref().hash()).collect();
+        assert_eq!(
+            removed_hashes,
+            BTreeSet::from([txs[0].as_ref().hash(), txs[2].as_ref().hash()])
+        );
+        assert_eq!(queue.tx_len(), 2);
+        for hash in removed.iter().map(|tx| tx.as_ref().hash()) {
+            assert_eq!(
+                event_receiver.try_recv().expect("Cancelled event is sent"),
+                TransactionEvent {
+                    hash,
+                    block_height: None,
+                    status: TransactionStatus::Cancelled(None),
+                }
+                .into()
+            );
+        }
+
+        let collected: BTreeSet<_> = queue
+            .collect_transactions_for_block(&state_view, nonzero!(10_usize))
+            .iter()
+            .map(|tx| tx.as_ref().hash())
+            .collect();
+        assert_eq!(collected, BTreeSet::from(kept));
+        assert!(queue.tombstones.is_empty());
+    }
+
+    #[test]
+    async fn removed_transaction_can_be_pushed_again() {
+        let kura = Kura::blank_kura_for_testing();
+        let query_handle = LiveQueryStore::test().start();
+        let state = State::new(world_with_test_domains(), kura, query_handle);
+        let state_view = state.view();
+
+        let time_source = TimeSource::new_fixed(Duration::default());
+        let queue = Queue::test(
+            Config {
+                capacity: nonzero!(1_usize),
+                ..config_factory()
+            },
+            &time_source,
+        );
+        let tx = accepted_tx_by_someone(&time_source);
+        queue.push(tx.clone(), &state_view).unwrap();
+
+        // The hash of the removed transaction is dropped from the sub-queue right away
+        assert_eq!(queue.retain(|_| false).len(), 1);
+        assert_eq!(queue.sub_queues[0].tx_hashes.len(), 0);
+        assert!(queue.tombstones.is_empty());
+        queue.push(tx.clone(), &state_view).unwrap();
+        check_queue_invariants(&queue).unwrap();
+
+        // During a collection the hash of the removed transaction is taken over instead
+        let collection = queue.lock_collection();
+        assert_eq!(queue.retain(|_| false).len(), 1);
+        assert!(queue.tombstones.contains(&tx.as_ref().hash()));
+        queue.push(tx.clone(), &state_view).unwrap();
+        assert!(queue.tombstones.is_empty());
+        drop(collection);
+        check_queue_invariants(&queue).unwrap();
+
+        assert_eq!(
+            queue.collect_transactions_for_block(&state_view, nonzero!(10_usize)),
+            vec![tx]
+        );
+    }
+
+    #[test]
+    async fn halted_full_queue_accepts_transactions_after_resume() {
+        let kura = Kura::blank_kura_for_testing();
+        let query_handle = LiveQueryStore::test().start();
+        let state = State::new(world_with_test_domains(), kura, query_handle);
+        let state_view = state.view();
+
+        let (time_handle, time_source) = TimeSource::new_mock(Duration::default());
+        let queue = Queue::test(
+            Config {
+                capacity: nonzero!(2_usize),
+                ..config_factory()
+            },
+            &time_source,
+        );
+        for _ in 0..2 {
+            queue
+                .push(accepted_tx_by_someone(&time_source), &state_view)
+                .unwrap();
+            time_handle.advance(Duration::from_millis(1));
+        }
+
+        assert_eq!(queue.halt("maintenance".to_owned()), 2);
+        queue.resume();
+        for _ in 0..2 {
+            queue
+                .push(accepted_tx_by_someone(&time_source), &state_view)
+                .expect("Space of the removed transactions is freed");
+            time_handle.advance(Duration::from_millis(1));
+        }
+        check_queue_invariants(&queue).unwrap();
+    }
+
+    #[test]
+    async fn reaper_clears_expired_transactions() {
+        let kura = Kura::blank_kura_for_testing();
+        let query_handle = LiveQueryStore::test().start();
+        let state = State::new(world_with_test_domains(), kura, query_handle);
+
+        let (time_handle, time_source) = TimeSource::new_mock(Duration::default());
+
+        let queue = Arc::new(Queue::test(
+            Config {
+                transaction_time_to_live: Duration::from_millis(100),
+                ..config_factory()
+            },
+            &time_source,
+        ));
+        queue
+            .push(accepted_tx_by_someone(&time_source), &state.view())
+            .expect("Failed to push tx into queue");
+
+        let reaper = QueueReaper::from_config(
+            Config {
+                reaping_interval: Duration::from_millis(10),
+                ..config_factory()
+            },
+            Arc::clone(&queue),
+            Arc::new(tokio::sync::Notify::new()),
+        )
+        .start();
+
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(queue.tx_len(), 1);
+
+        time_handle.advance(Duration::from_millis(101));
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(queue.tx_len(), 0);
+        assert!(queue.txs_per_user.is_empty());
+        assert_eq!(queue.metrics().expired_total.get(), 1);
+
+        reaper.stop().await;
+    }
+
+    #[test]
+    async fn full_queue_accepts_transactions_after_expired_are_cleared() {
+        let kura = Kura::blank_kura_for_testing();
+        let query_handle = LiveQueryStore::test().start();
+        let state = State::new(world_with_test_domains(), kura, query_handle);
+        let state_view = state.view();
+
+        let (time_handle, time_source) = TimeSource::new_mock(Duration::default());
+        let queue = Queue::test(
+            Config {
+                transaction_time_to_live: Duration::from_millis(100),
+                capacity: nonzero!(3_usize),
+                ..config_factory()
+            },
+            &time_source,
+        );
+        for _ in 0..3 {
+            queue
+                .push(accepted_tx_by_someone(&time_source), &state_view)
+                .expect("Failed to push tx into queue");
+            time_handle.advance(Duration::from_millis(1));
+        }
+        assert!(matches!(
+            queue.push(accepted_tx_by_someone(&time_source), &state_view),
+            Err(Failure {
+                err: Error::Full,
+                ..
+            })
+        ));
+
+        time_handle.advance(Duration::from_millis(101));
+        assert_eq!(queue.clear_expired(), 3);
+        assert_eq!(queue.sub_queues[0].tx_hashes.len(), 0);
+        for _ in 0..3 {
+            queue
+                .push(accepted_tx_by_someone(&time_source), &state_view)
+                .expect("Space of expired transactions is freed");
+            time_handle.advance(Duration::from_millis(1));
+        }
+        check_queue_invariants(&queue).unwrap();
+    }
+
+    #[test]
+    async fn expired_event_is_emitted_after_grace() {
+        let kura = Kura::blank_kura_for_testing();
+        let query_handle = LiveQueryStore::test().start();
+        let state = State::new(world_with_test_domains(), kura, query_handle);
+        let state_view = state.view();
+
+        let (time_handle, time_source) = TimeSource::new_mock(Duration::default());
+        let mut queue = Queue::test(
+            Config {
+                transaction_time_to_live: Duration::from_millis(100),
+                expiry_grace: Duration::from_millis(50),
+                ..config_factory()
+            },
+            &time_source,
+        );
+        let (event_sender, mut event_receiver) = tokio::sync::broadcast::channel(10);
+        queue.events = QueueEvents::new(
+            event_sender,
+            config_factory().events_capacity,
+            config_factory().events_on_overflow,
+        );
+        let tx = accepted_tx_by_someone(&time_source);
+        queue
+            .push(tx.clone(), &state_view)
+            .expect("Failed to push tx into queue");
+        let _queued = event_receiver.try_recv().expect("Queued event is sent");
+
+        // Expired, but within the grace period: not selected and not announced
+        time_handle.advance(Duration::from_millis(120));
+        assert!(queue
+            .collect_transactions_for_block(&state_view, nonzero!(10_usize))
+            .is_empty());
+        assert_eq!(queue.clear_expired(), 0);
+        assert_eq!(queue.tx_len(), 1);
+        assert!(event_receiver.try_recv().is_err());
+
+        time_handle.advance(Duration::from_millis(50));
+        assert!(queue
+            .collect_transactions_for_block(&state_view, nonzero!(10_usize))
+            .is_empty());
+        assert_eq!(queue.tx_len(), 0);
+        assert_eq!(
+            event_receiver.try_recv().expect("Expired event is sent"),
+            TransactionEvent {
+                hash: tx.as_ref().hash(),
+                block_height: None,
+                status: TransactionStatus::Expired,
+            }
+            .into()
+        );
+    }
+
+    #[test]
+    async fn transactions_expired_together_are_announced_in_batch() {
+        let (time_handle, time_source) = TimeSource::new_mock(Duration::default());
+        let mut queue = Queue::test(config_factory(), &time_source);
+        let (event_sender, mut event_receiver) = tokio::sync::broadcast::channel(100);
+        queue.events = QueueEvents::new(
+            event_sender,
+            config_factory().events_capacity,
+            config_factory().events_on_overflow,
+        );
+        let state = MockState::default();
+
+        let mut hashes: Vec<_> = (0..10)
+            .map(|_| {
+                let tx = accepted_tx_by_someone(&time_source);
+                queue.push(tx.clone(), &state).unwrap();
+                let _queued = event_receiver.try_recv().expect("Queued event is sent");
+                tx.as_ref().hash()
+            })
+            .collect();
+
+        time_handle.advance(Duration::from_secs(200));
+        assert!(queue
+            .collect_transactions_for_block(&state, nonzero!(10_usize))
+            .is_empty());
+        assert_eq!(queue.tx_len(), 0);
+
+        let EventBox::Pipeline(PipelineEventBox::TransactionsExpired(event)) =
+            event_receiver.try_recv().expect("Expired event is sent")
+        else {
+            panic!("Expected a batch of expired transactions");
+        };
+        let mut expired = event.hashes.clone();
+        expired.sort();
+        hashes.sort();
+        assert_eq!(expired, hashes);
+        assert!(
+            event_receiver.try_recv().is_err(),
+            "Only a single event is sent"
+        );
+
+        // Same for transactions removed by the reaper
+        let mut hashes: Vec<_> = (0..10)
+            .map(|_| {
+                let tx = accepted_tx_by_someone(&time_source);
+                queue.push(tx.clone(), &state).unwrap();
+                let _queued = event_receiver.try_recv().expect("Queued event is sent");
+                tx.as_ref().hash()
+            })
+            .collect();
+
+        time_handle.advance(Duration::from_secs(200));
+        assert_eq!(queue.clear_expired(), 10);
+
+        let EventBox::Pipeline(PipelineEventBox::TransactionsExpired(event)) =
+            event_receiver.try_recv().expect("Expired event is sent")
+        else {
+            panic!("Expected a batch of expired transactions");
+        };
+        let mut expired = event.hashes.clone();
+        expired.sort();
+        hashes.sort();
+        assert_eq!(expired, hashes);
+        assert!(
+            event_receiver.try_recv().is_err(),
+            "Only a single event is sent"
+        );
+    }
+
+    #[test]
+    async fn expiry_events_can_be_disabled() {
+        let kura = Kura::blank_kura_for_testing();
+        let query_handle = LiveQueryStore::test().start();
+        let state = State::new(world_with_test_domains(), kura, query_handle);
+        let state_view = state.view();
+
+        let (time_handle, time_source) = TimeSource::new_mock(Duration::default());
+        let mut queue = Queue::test(
+            Config {
+                transaction_time_to_live: Duration::from_millis(100),
+                emit_expiry_events: false,
+                ..config_factory()
+            },
+            &time_source,
+        );
+        let (event_sender, mut event_receiver) = tokio::sync::broadcast::channel(10);
+        queue.events = QueueEvents::new(
+            event_sender,
+            config_factory().events_capacity,
+            config_factory().events_on_overflow,
+        );
+
+        // Expired transaction is removed while collecting transactions for a block
+        queue
+            .push(accepted_tx_by_someone(&time_source), &state_view)
+            .expect("Failed to push tx into queue");
+        let _queued = event_receiver.try_recv().expect("Queued event is sent");
+        time_handle.advance(Duration::from_millis(101));
+        assert!(queue
+            .collect_transactions_for_block(&state_view, nonzero!(10_usize))
+            .is_empty());
+        assert_eq!(queue.tx_len(), 0);
+        assert!(event_receiver.try_recv().is_err());
+
+        // Expired transaction is removed by `clear_expired`
+        queue
+            .push(accepted_tx_by_someone(&time_source), &state_view)
+            .expect("Failed to push tx into queue");
+        let _queued = event_receiver.try_recv().expect("Queued event is sent");
+        time_handle.advance(Duration::from_millis(101));
+        assert_eq!(queue.clear_expired(), 1);
+        assert_eq!(queue.tx_len(), 0);
+        assert!(event_receiver.try_recv().is_err());
+    }
+
+    #[test]
+    #[allow(clippy::float_cmp)] // Quarters are represented exactly
+    async fn estimated_block_fill_counts_pending_transactions() {
+        let kura = Kura::blank_kura_for_testing();
+        let query_handle = LiveQueryStore::test().start();
+        let state = State::new(world_with_test_domains(), kura, query_handle);
+
+        let (time_handle, time_source) = TimeSource::new_mock(Duration::default());
+        let queue = Queue::test(
+            Config {
+                transaction_time_to_live: Duration::from_millis(100),
+                ..config_factory()
+            },
+            &time_source,
+        );
+        assert_eq!(
+            queue.estimated_block_fill(&state.view(), nonzero!(4_usize)),
+            0.0
+        );
+
+        let committed_tx = accepted_tx_by_someone(&time_source);
+        queue.push(committed_tx.clone(), &state.view()).unwrap();
+        time_handle.advance(Duration::from_millis(50));
+        for _ in 0..2 {
+            queue
+                .push(accepted_tx_by_someone(&time_source), &state.view())
+                .unwrap();
+        }
+        assert_eq!(
+            queue.estimated_block_fill(&state.view(), nonzero!(4_usize)),
+            0.75
+        );
+        assert_eq!(
+            queue.estimated_block_fill(&state.view(), nonzero!(2_usize)),
+            1.0
+        );
+
+        let mut state_block = state.block();
+        state_block
+            .transactions
+            .insert(committed_tx.as_ref().hash(), nonzero!(1_usize));
+        state_block.commit();
+        assert_eq!(
+            queue.estimated_block_fill(&state.view(), nonzero!(4_usize)),
+            0.5
+        );
+
+        // Expired ones are left in the queue, but aren't pending
+        time_handle.advance(Duration::from_millis(110));
+        assert_eq!(
+            queue.estimated_block_fill(&state.view(), nonzero!(4_usize)),
+            0.0
+        );
+        assert_eq!(queue.tx_len(), 3);
+    }
+
+    #[test]
+    async fn labels_filter_transactions() {
+        let kura = Kura::blank_kura_for_testing();
+        let query_handle = LiveQueryStore::test().start();
+        let state = State::new(world_with_test_domains(), kura, query_handle);
+        let state_view = state.view();
+
+        let time_source = TimeSource::new_fixed(Duration::default());
+        let queue = Queue::test(config_factory(), &time_source);
+        let flagged_tx = accepted_tx_by_someone(&time_source);
+        let other_tx = accepted_tx_by_someone(&time_source);
+        let flagged = flagged_tx.as_ref().hash();
+        assert!(!queue.set_label(flagged, "review".to_owned()));
+
+        queue.push(flagged_tx.clone(), &state_view).unwrap();
+        queue.push(other_tx.clone(), &state_view).unwrap();
+        assert!(queue.set_label(flagged, "review".to_owned()));
+        assert!(queue.set_label(flagged, "compliance".to_owned()));
+        assert_eq!(queue.labels(&flagged), ["compliance", "review"]);
+        assert!(queue.labels(&other_tx.as_ref().hash()).is_empty());
+
+        let labelled: Vec<_> = queue
+            .all_transactions(&state_view)
+            .filter(|tx| queue.has_label(&tx.as_ref().hash(), "review"))
+            .collect();
+        assert_eq!(labelled, vec![flagged_tx.clone()]);
+
+        let removed = queue.retain(|tx| !queue.has_label(&tx.as_ref().hash(), "review"));
+        assert_eq!(removed, vec![flagged_tx]);
+        assert!(queue.labels(&flagged).is_empty());
+        assert_eq!(
+            queue.collect_transactions_for_block(&state_view, nonzero!(10_usize)),
+            vec![other_tx]
+        );
+    }
+
+    #[test]
+    async fn first_seen_is_recorded_on_push() {
+        let kura = Kura::blank_kura_for_testing();
+        let query_handle = LiveQueryStore::test().start();
+        let state = State::new(world_with_test_domains(), kura, query_handle);
+        let state_view = state.view();
+
+        let (time_handle, time_source) = TimeSource::new_mock(Duration::default());
+        let queue = Queue::test(config_factory(), &time_source);
+        let (account_id, key_pair) = gen_account_in("wonderland");
+        let tx = accepted_tx_by(account_id.clone(), &key_pair, &time_source);
+        let hash = tx.as_ref().hash();
+        assert_eq!(queue.first_seen(&hash), None);
+
+        // Took a while to reach this peer
+        time_handle.advance(Duration::from_millis(30));
+        queue
+            .push(tx.clone(), &state_view)
+            .expect("Failed to push tx into queue");
+        assert_eq!(queue.first_seen(&hash), Some(Duration::from_millis(30)));
+        assert_eq!(tx.as_ref().creation_time(), Duration::ZERO);
+
+        queue.remove_by_account(&account_id);
+        assert_eq!(queue.first_seen(&hash), None);
+    }
+
+    #[test]
+    async fn expiry_watcher_is_notified() {
+        use tokio::sync::oneshot::{self, error::TryRecvError};
+
+        let kura = Kura::blank_kura_for_testing();
+        let query_handle = LiveQueryStore::test().start();
+        let state = State::new(world_with_test_domains(), kura, query_handle);
+
+        let (time_handle, time_source) = TimeSource::new_mock(Duration::default());
+        let queue = Queue::test(
+            Config {
+                transaction_time_to_live: Duration::from_millis(100),
+                ..config_factory()
+            },
+            &time_source,
+        );
+        let expiring_tx = accepted_tx_by_someone(&time_source);
+        let committed_tx = accepted_tx_by_someone(&time_source);
+
+        let (unknown_sender, mut unknown_receiver) = oneshot::channel();
+        assert!(!queue.on_expiry(expiring_tx.as_ref().hash(), unknown_sender));
+        assert_eq!(unknown_receiver.try_recv(), Err(TryRecvError::Closed));
+
+        let (expiring_sender, mut expiring_receiver) = oneshot::channel();
+        let (committed_sender, mut committed_receiver) = oneshot::channel();
+        queue.push(expiring_tx.clone(), &state.view()).unwrap();
+        assert!(queue.on_expiry(expiring_tx.as_ref().hash(), expiring_sender));
+        queue.push(committed_tx.clone(), &state.view()).unwrap();
+        assert!(queue.on_expiry(committed_tx.as_ref().hash(), committed_sender));
+
+        let mut state_block = state.block();
+        state_block
+            .transactions
+            .insert(committed_tx.as_ref().hash(), nonzero!(1_usize));
+        state_block.commit();
+        assert_eq!(
+            queue.collect_transactions_for_block(&state.view(), nonzero!(10_usize)),
+            vec![expiring_tx]
+        );
+        // Committed instead of expiring
+        assert_eq!(committed_receiver.try_recv(), Err(TryRecvError::Closed));
+        assert_eq!(expiring_receiver.try_recv(), Err(TryRecvError::Empty));
+
+        time_handle.advance(Duration::from_millis(101));
+        assert!(queue
+            .collect_transactions_for_block(&state.view(), nonzero!(10_usize))
+            .is_empty());
+        assert_eq!(expiring_receiver.try_recv(), Ok(()));
+    }
+
+    #[test]
+    async fn update_config_changes_time_to_live() {
+        let kura = Kura::blank_kura_for_testing();
+        let query_handle = LiveQueryStore::test().start();
+        let state = State::new(world_with_test_domains(), kura, query_handle);
+        let state_view = state.view();
+
+        let (time_handle, time_source) = TimeSource::new_mock(Duration::default());
+        let queue = Queue::test(config_factory(), &time_source);
+        queue
+            .push(accepted_tx_by_someone(&time_source), &state_view)
+            .expect("Failed to push tx into queue");
+
+        queue
+            .update_config(&Config {
+                capacity_per_user: config_factory().capacity,
+                transaction_time_to_live: Duration::from_millis(100),
+                future_threshold: Duration::from_millis(100),
+                ..config_factory()
+            })
+            .expect("Valid configuration");
+        assert_eq!(queue.tx_time_to_live(), Duration::from_millis(100));
+        assert_eq!(queue.tx_len(), 1);
+
+        time_handle.advance(Duration::from_millis(101));
+        assert!(queue
+            .collect_transactions_for_block(&state_view, nonzero!(10_usize))
+            .is_empty());
+        assert_eq!(queue.tx_len(), 0);
+    }
+
+    #[test]
+    async fn update_config_rejects_inconsistent_values() {
+        let time_source = TimeSource::new_fixed(Duration::default());
+        let queue = Queue::test(config_factory(), &time_source);
+
+        // The capacity of the queue isn't reloaded, so it still limits the capacity per user
+        let capacity_per_user = nonzero!(200_usize);
+        assert_eq!(
+            queue.update_config(&Config {
+                capacity: nonzero!(1000_usize),
+                capacity_per_user,
+                transaction_time_to_live: Duration::from_millis(100),
+                ..config_factory()
+            }),
+            Err(vec![
+                ConfigIssue::CapacityPerUserExceedsCapacity {
+                    capacity: config_factory().capacity,
+                    capacity_per_user,
+                },
+                ConfigIssue::FutureThresholdExceedsTimeToLive {
+                    future_threshold: config_factory().future_threshold,
+                    transaction_time_to_live: Duration::from_millis(100),
+                }
+            ])
+        );
+        assert_eq!(
+            queue.tx_time_to_live(),
+            config_factory().transaction_time_to_live
+        );
+        assert_eq!(
+            queue.capacity_per_user.load(Ordering::Relaxed),
+            config_factory().capacity_per_user.get()
+        );
+    }
+
+    #[test]
+    async fn push_tx_overflow() {
+        let capacity = nonzero!(10_usize);
+
+        let kura: Arc<Kura> = Kura::blank_kura_for_testing();
+        let query_handle = LiveQueryStore::test().start();
+        let state = Arc::new(State::new(world_with_test_domains(), kura, query_handle));
+        let state_view = state.view();
+
+        let (time_handle, time_source) = TimeSource::new_mock(Duration::default());
+
+        let queue = Queue::test(
+            Config {
+                transaction_time_to_live: Duration::from_secs(100),
+                capacity,
+                ..Config::default()
+            },
+            &time_source,
+        );
+
+        for _ in 0..capacity.get() {
+            queue
+                .push(accepted_tx_by_someone(&time_source), &state_view)
+                .expect("Failed to push tx into queue");
+            time_handle.advance(Duration::from_millis(10));
+        }
+
+        assert!(matches!(
+            queue.push(accepted_tx_by_someone(&time_source), &state_view),
+            Err(Failure {
+                err: Error::Full,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    async fn push_tx_evicts_expiring_soonest_when_full() {
+        let kura = Kura::blank_kura_for_testing();
+        let query_handle = LiveQueryStore::test().start();
+        let state = State::new(world_with_test_domains(), kura, query_handle);
+        let state_view = state.view();
+
+        let (time_handle, time_source) = TimeSource::new_mock(Duration::default());
+
+        let queue = Queue::test(
+            Config {
+                capacity: nonzero!(2_usize),
+                evict_on_full: true,
+                ..config_factory()
+            },
+            &time_source,
+        );
+        let (alice_id, alice_keypair) = gen_account_in("wonderland");
+        let (bob_id, bob_keypair) = gen_account_in("wonderland");
+
+        let oldest = accepted_tx_by(alice_id.clone(), &alice_keypair, &time_source);
+        queue
+            .push(oldest.clone(), &state_view)
+            .expect("Failed to push tx into queue");
+        time_handle.advance(Duration::from_millis(10));
+        queue
+            .push(accepted_tx_by_someone(&time_source), &state_view)
+            .expect("Failed to push tx into queue");
+
+        time_handle.advance(Duration::from_millis(10));
+        queue
+            .push(
+                accepted_tx_by(bob_id.clone(), &bob_keypair, &time_source),
+                &state_view,
+            )
+            .expect("Oldest transaction should be evicted");
+
+        assert_eq!(queue.tx_len(), 2);
+        assert!(!queue.accepted_txs.contains_key(&oldest.as_ref().hash()));
+        assert!(!queue.txs_per_user.contains_key(&alice_id));
+        assert_eq!(queue.len_for_account(&bob_id), 1);
+        // The hash of the evicted transaction doesn't take up space in the sub-queue
+        assert_eq!(queue.sub_queues[0].tx_hashes.len(), 2);
+        assert!(queue.tombstones.is_empty());
+
+        // Space of a transaction evicted during a collection is available before the collection finishes
+        let collection = queue.lock_collection();
+        time_handle.advance(Duration::from_millis(10));
+        queue
+            .push(
+                accepted_tx_by(bob_id.clone(), &bob_keypair, &time_source),
+                &state_view,
+            )
+            .expect("Next oldest transaction should be evicted");
+        assert_eq!(queue.tombstones.len(), 1);
+        drop(collection);
+
+        assert_eq!(
+            queue
+                .collect_transactions_for_block(&state_view, nonzero!(10_usize))
+                .len(),
+            2
+        );
+        assert!(queue.tombstones.is_empty());
+        check_queue_invariants(&queue).unwrap();
+    }
+
+    fn queue_with_system_class(
+        cfg: Config,
+        time_source: &TimeSource,
+        system_account: AccountId,
+        class_capacity: NonZeroUsize,
+    ) -> Queue {
+        Queue::test(cfg, time_source).with_classes(
+            [
+                TransactionClass {
+                    name: "system".to_owned(),
+                    capacity: class_capacity,
+                },
+                TransactionClass {
+                    name: "user".to_owned(),
+                    capacity: class_capacity,
+                },
+            ],
+            move |tx| usize::from(tx.authority() != &system_account),
+        )
+    }
+
+    #[test]
+    async fn higher_priority_class_is_collected_first() {
+        let kura = Kura::blank_kura_for_testing();
+        let query_handle = LiveQueryStore::test().start();
+        let state = State::new(world_with_test_domains(), kura, query_handle);
+        let state_view = state.view();
+
+        let time_source = TimeSource::new_fixed(Duration::default());
+        let (system_id, system_keypair) = gen_account_in("wonderland");
+        let queue = queue_with_system_class(
+            config_factory(),
+            &time_source,
+            system_id.clone(),
+            nonzero!(10_usize),
+        );
+
+        for _ in 0..5 {
+            queue
+                .push(accepted_tx_by_someone(&time_source), &state_view)
+                .expect("Failed to push tx into queue");
+        }
+        let system_tx = accepted_tx_by(system_id, &system_keypair, &time_source);
+        queue
+            .push(system_tx.clone(), &state_view)
+            .expect("Failed to push tx into queue");
+
+        let collected = queue.collect_transactions_for_block(&state_view, nonzero!(2_usize));
+        assert_eq!(collected.len(), 2);
+        assert_eq!(collected[0], system_tx);
+
+        // User transactions are still collected once there are no system transactions
+        let collected = queue.collect_transactions_for_block(&state_view, nonzero!(10_usize));
+        assert_eq!(collected.len(), 6);
+    }
+
+    #[test]
+    async fn eviction_makes_room_in_the_class_of_the_pushed_transaction() {
+        let kura = Kura::blank_kura_for_testing();
+        let query_handle = LiveQueryStore::test().start();
+        let state = State::new(world_with_test_domains(), kura, query_handle);
+        let state_view = state.view();
+
+        let (time_handle, time_source) = TimeSource::new_mock(Duration::default());
+        let (system_id, system_keypair) = gen_account_in("wonderland");
+        let queue = queue_with_system_class(
+            Config {
+                capacity: nonzero!(3_usize),
+                evict_on_full: true,
+                ..config_factory()
+            },
+            &time_source,
+            system_id.clone(),
+            nonzero!(2_usize),
+        );
+
+        // Expires soonest, but evicting it wouldn't make room for a user transaction
+        let system_tx = accepted_tx_by(system_id, &system_keypair, &time_source);
+        queue.push(system_tx.clone(), &state_view).unwrap();
+        time_handle.advance(Duration::from_millis(10));
+        let oldest_user_tx = accepted_tx_by_someone(&time_source);
+        queue.push(oldest_user_tx.clone(), &state_view).unwrap();
+        time_handle.advance(Duration::from_millis(10));
+        queue
+            .push(accepted_tx_by_someone(&time_source), &state_view)
+            .unwrap();
+
+        let generation = *queue.space_generation.lock();
+        time_handle.advance(Duration::from_millis(10));
+        queue
+            .push(accepted_tx_by_someone(&time_source), &state_view)
+            .expect("Oldest user transaction should be evicted");
+
+        assert!(queue.accepted_txs.contains_key(&system_tx.as_ref().hash()));
+        assert!(!queue
+            .accepted_txs
+            .contains_key(&oldest_user_tx.as_ref().hash()));
+        assert_eq!(queue.tx_len(), 3);
+        // Transactions waiting for space are woken up
+        assert!(*queue.space_generation.lock() > generation);
+        check_queue_invariants(&queue).unwrap();
+    }
+
+    #[test]
+    async fn class_capacity_and_total_capacity_are_enforced() {
+        let kura = Kura::blank_kura_for_testing();
+        let query_handle = LiveQueryStore::test().start();
+        let state = State::new(world_with_test_domains(), kura, query_handle);
+        let state_view = state.view();
+
+        let time_source = TimeSource::new_fixed(Duration::default());
+        let (system_id, system_keypair) = gen_account_in("wonderland");
+        let queue = queue_with_system_class(
+            Config {
+                capacity: nonzero!(3_usize),
+                ..config_factory()
+            },
+            &time_source,
+            system_id.clone(),
+            nonzero!(2_usize),
+        );
+
+        for _ in 0..2 {
+            queue
+                .push(accepted_tx_by_someone(&time_source), &state_view)
+                .expect("Failed to push tx into queue");
+        }
+        // User class is full, but system class still has space
+        assert!(matches!(
+            queue.push(accepted_tx_by_someone(&time_source), &state_view),
+            Err(Failure {
+                err: Error::Full,
+                ..
+            })
+        ));
+        queue
+            .push(
+                accepted_tx_by(system_id.clone(), &system_keypair, &time_source),
+                &state_view,
+            )
+            .expect("Failed to push tx into queue");
+
+        // Total capacity is reached
+        assert!(matches!(
+            queue.push(
+                accepted_tx_by(system_id, &system_keypair, &time_source),
+                &state_view
+            ),
+            Err(Failure {
+                err: Error::Full,
+                ..
+            })
+        ));
+        assert_eq!(queue.tx_len(), 3);
+        assert!(queue.txs_per_user.iter().all(|entry| *entry.value() == 1));
+    }
+
+    #[test]
+    async fn tx_committed_during_collection_is_skipped() {
+        let kura = Kura::blank_kura_for_testing();
+        let query_handle = LiveQueryStore::test().start();
+        let state = State::new(world_with_test_domains(), kura, query_handle);
+        let state_view = state.view();
+
+        let time_source = TimeSource::new_fixed(Duration::default());
+        let queue = Queue::test(config_factory(), &time_source);
+        let first_tx = accepted_tx_by_someone(&time_source);
+        let second_tx = accepted_tx_by_someone(&time_source);
+        for tx in [&first_tx, &second_tx] {
+            queue
+                .push(tx.clone(), &state_view)
+                .expect("Failed to push tx into queue");
+        }
+
+        let mut transactions = Vec::new();
+        queue.get_transactions_for_block_checked(
+            &state_view,
+            nonzero!(10_usize),
+            &mut transactions,
+            |hash| {
+                if hash == first_tx.as_ref().hash() {
+                    // Simulate commit of the second transaction between two pops
+                    let mut state_block = state.block();
+                    state_block
+                        .transactions
+                        .insert(second_tx.as_ref().hash(), nonzero!(1_usize));
+                    state_block.commit();
+                }
+                StateReadOnly::has_transaction(&state.view(), hash)
+            },
+        );
+
+        assert_eq!(transactions, vec![first_tx]);
+        assert_eq!(queue.tx_len(), 1);
+    }
+
+    #[test]
+    async fn old_transactions_are_collected_after_fresh_ones() {
+        let kura = Kura::blank_kura_for_testing();
+        let query_handle = LiveQueryStore::test().start();
+        let state = State::new(world_with_test_domains(), kura, query_handle);
+        let state_view = state.view();
+
+        let (time_handle, time_source) = TimeSource::new_mock(Duration::default());
+        let queue = Queue::test(
+            Config {
+                soft_age_threshold: Some(Duration::from_secs(1)),
+                ..config_factory()
+            },
+            &time_source,
+        );
+        let old_tx = accepted_tx_by_someone(&time_source);
+        queue
+            .push(old_tx.clone(), &state_view)
+            .expect("Failed to push tx into queue");
+        time_handle.advance(Duration::from_secs(2));
+        let fresh_tx = accepted_tx_by_someone(&time_source);
+        queue
+            .push(fresh_tx.clone(), &state_view)
+            .expect("Failed to push tx into queue");
+
+        assert_eq!(
+            queue.collect_transactions_for_block(&state_view, nonzero!(1_usize)),
+            vec![fresh_tx.clone()]
+        );
+        // Old transactions aren't dropped and still fill the rest of the block
+        assert_eq!(
+            queue.collect_transactions_for_block(&state_view, nonzero!(2_usize)),
+            vec![fresh_tx, old_tx]
+        );
+    }
+
+    #[test]
+    async fn async_collection_matches_sync_collection() {
+        let max_txs_in_block = nonzero!(60_usize);
+        let kura = Kura::blank_kura_for_testing();
+        let query_handle = LiveQueryStore::test().start();
+        let state = State::new(world_with_test_domains(), kura, query_handle);
+        let state_view = state.view();
+
+        let (_time_handle, time_source) = TimeSource::new_mock(Duration::default());
+        let sync_queue = Queue::test(config_factory(), &time_source);
+        let async_queue = Queue::test(config_factory(), &time_source);
+        for _ in 0..100 {
+            let tx = accepted_tx_by_someone(&time_source);
+            sync_queue
+                .push(tx.clone(), &state_view)
+                .expect("Failed to push tx into queue");
+            async_queue
+                .push(tx, &state_view)
+                .expect("Failed to push tx into queue");
+        }
+
+        let mut sync_txs = Vec::new();
+        sync_queue.get_transactions_for_block(&state_view, max_txs_in_block, &mut sync_txs);
+        let mut async_txs = Vec::new();
+        async_queue
+            .get_transactions_for_block_async(&state_view, max_txs_in_block, &mut async_txs)
+            .await;
+
+        assert_eq!(sync_txs.len(), max_txs_in_block.get());
+        assert_eq!(sync_txs, async_txs);
+        assert_eq!(async_queue.tx_len(), 100);
+    }
+
+    #[test]
+    async fn health_check_reports_fullness_and_corruption() {
+        let kura = Kura::blank_kura_for_testing();
+        let query_handle = LiveQueryStore::test().start();
+        let state = State::new(world_with_test_domains(), kura, query_handle);
+        let state_view = state.view();
+
+        let time_source = TimeSource::new_fixed(Duration::default());
+        let queue = Queue::test(
+            Config {
+                capacity: nonzero!(10_usize),
+                ..config_factory()
+            },
+            &time_source,
+        );
+        assert_eq!(queue.health_check(), QueueHealth::Healthy);
+
+        for _ in 0..9 {
+            queue
+                .push(accepted_tx_by_someone(&time_source), &state_view)
+                .expect("Failed to push tx into queue");
+        }
+        assert!(matches!(queue.health_check(), QueueHealth::Degraded { .. }));
+
+        // Hash of a transaction which is neither stored nor removed
+        queue.sub_queues[0]
+            .tx_hashes
+            .push(HashOf::from_untyped_unchecked(Hash::new([0])))
+            .unwrap();
+        assert!(matches!(
+            queue.health_check(),
+            QueueHealth::Unhealthy { .. }
+        ));
+    }
+
+    #[test]
+    async fn duplicate_hash_is_collected_once() {
+        let kura = Kura::blank_kura_for_testing();
+        let query_handle = LiveQueryStore::test().start();
+        let state = State::new(world_with_test_domains(), kura, query_handle);
+        let state_view = state.view();
+
+        let time_source = TimeSource::new_fixed(Duration::default());
+        let queue = Queue::test(config_factory(), &time_source);
+        let tx = accepted_tx_by_someone(&time_source);
+        let other_tx = accepted_tx_by_someone(&time_source);
+        queue.push(tx.clone(), &state_view).unwrap();
+        queue.push(other_tx.clone(), &state_view).unwrap();
+        queue.sub_queues[0]
+            .tx_hashes
+            .push(tx.as_ref().hash())
+            .unwrap();
+
+        assert_eq!(
+            queue.collect_transactions_for_block(&state_view, nonzero!(10_usize)),
+            vec![tx.clone(), other_tx.clone()]
+        );
+        assert_eq!(queue.tx_len(), 2);
+        check_queue_invariants(&queue).unwrap();
+        assert_eq!(
+            queue.collect_transactions_for_block(&state_view, nonzero!(10_usize)),
+            vec![tx, other_tx]
+        );
+    }
+
+    #[test]
+    async fn get_available_txs() {
+        let max_txs_in_block = nonzero!(2_usize);
+        let kura = Kura::blank_kura_for_testing();
+        let query_handle = LiveQueryStore::test().start();
+        let state = Arc::new(State::new(world_with_test_domains(), kura, query_handle));
+        let state_view = state.view();
+
+        let (time_handle, time_source) = TimeSource::new_mock(Duration::default());
+
+        let queue = Queue::test(
+            Config {
+                transaction_time_to_live: Duration::from_secs(100),
+                ..config_factory()
+            },
+            &time_source,
+        );
+        for _ in 0..5 {
+            queue
+                .push(accepted_tx_by_someone(&time_source), &state_view)
+                .expect("Failed to push tx into queue");
+            time_handle.advance(Duration::from_millis(10));
+        }
+
+        let available = queue.collect_transactions_for_block(&state_view, max_txs_in_block);
+        assert_eq!(available.len(), max_txs_in_block.get());
+    }
+
+    #[test]
+    async fn drain_for_block_removes_yielded_transactions() {
+        let kura = Kura::blank_kura_for_testing();
+        let query_handle = LiveQueryStore::test().start();
+        let state = State::new(world_with_test_domains(), kura, query_handle);
+        let state_view = state.view();
+
+        let (time_handle, time_source) = TimeSource::new_mock(Duration::default());
+        let queue = Queue::test(config_factory(), &time_source);
+        let txs = (0..3)
+            .map(|_| {
+                let tx = accepted_tx_by_someone(&time_source);
+                queue
+                    .push(tx.clone(), &state_view)
+                    .expect("Failed to push tx into queue");
+                time_handle.advance(Duration::from_millis(10));
+                tx
+            })
+            .collect::<Vec<_>>();
+
+        // Every transaction expires within the padding
+        assert_eq!(
+            queue
+                .drain_for_block(&state_view, nonzero!(2_usize), Duration::from_secs(200))
+                .count(),
+            0
+        );
+        assert_eq!(queue.tx_len(), 3);
+
+        let drained = queue
+            .drain_for_block(&state_view, nonzero!(2_usize), Duration::ZERO)
+            .collect::<Vec<_>>();
+        assert_eq!(drained, txs[..2]);
+        assert_eq!(queue.tx_len(), 1);
+        assert_eq!(queue.active_users().len(), 1);
+        assert_eq!(
+            queue.collect_transactions_for_block(&state_view, nonzero!(10_usize)),
+            txs[2..]
+        );
+    }
+
+    #[test]
+    async fn drained_transaction_is_requeued() {
+        let kura = Kura::blank_kura_for_testing();
+        let query_handle = LiveQueryStore::test().start();
+        let state = State::new(world_with_test_domains(), kura, query_handle);
+        let state_view = state.view();
+
+        let (time_handle, time_source) = TimeSource::new_mock(Duration::default());
+        let queue = Queue::test(config_factory(), &time_source);
+        let tx = accepted_tx_by_someone(&time_source);
+        queue.push(tx.clone(), &state_view).unwrap();
+
+        let drained = queue
+            .drain_for_block(&state_view, nonzero!(1_usize), Duration::ZERO)
+            .collect::<Vec<_>>();
+        assert_eq!(drained, [tx.clone()]);
+        assert_eq!(queue.tx_len(), 0);
+        assert_eq!(queue.len_for_account(tx.authority()), 0);
+
+        queue.requeue(tx.clone(), &state_view).unwrap();
+        assert_eq!(queue.tx_len(), 1);
+        assert_eq!(queue.len_for_account(tx.authority()), 1);
+        assert_eq!(
+            queue.collect_transactions_for_block(&state_view, nonzero!(1_usize)),
+            [tx.clone()]
+        );
+        let metrics = queue.metrics();
+        assert_eq!(metrics.pushes_total.get(), 1);
+        assert_eq!(metrics.requeues_total.get(), 1);
+
+        // Expired in the meantime
+        let drained = queue
+            .drain_for_block(&state_view, nonzero!(1_usize), Duration::ZERO)
+            .collect::<Vec<_>>();
+        time_handle.advance(Duration::from_secs(101));
+        assert!(matches!(
+            queue.requeue(drained.into_iter().next().unwrap(), &state_view),
+            Err(Failure {
+                err: Error::Expired,
+                ..
+            })
+        ));
+        assert_eq!(queue.metrics().requeues_total.get(), 1);
+    }
+
+    #[test]
+    async fn requeued_transaction_keeps_first_seen() {
+        let kura = Kura::blank_kura_for_testing();
+        let query_handle = LiveQueryStore::test().start();
+        let state = State::new(world_with_test_domains(), kura, query_handle);
+        let state_view = state.view();
+
+        let (time_handle, time_source) = TimeSource::new_mock(Duration::default());
+        let queue = Queue::test(
+            Config {
+                transaction_time_to_live: Duration::from_millis(100),
+                capacity: nonzero!(1_usize),
+                ..config_factory()
+            },
+            &time_source,
+        );
+        let tx = accepted_tx_by_someone(&time_source);
+        let hash = tx.as_ref().hash();
+        time_handle.advance(Duration::from_millis(10));
+        queue.push(tx.clone(), &state_view).unwrap();
+
+        let drained = queue
+            .drain_for_block(&state_view, nonzero!(1_usize), Duration::ZERO)
+            .collect::<Vec<_>>();
+        assert_eq!(drained, [tx.clone()]);
+        assert_eq!(queue.first_seen(&hash), None);
+
+        // Wall-clock jumps past the time to live, but little time has actually passed
+        time_handle.set(Duration::from_secs(60 * 60));
+        time_handle.advance(Duration::from_millis(20));
+        queue
+            .requeue(tx.clone(), &state_view)
+            .expect("Time to live is measured since the transaction was first pushed");
+        assert_eq!(queue.first_seen(&hash), Some(Duration::from_millis(10)));
+
+        let drained = queue
+            .drain_for_block(&state_view, nonzero!(1_usize), Duration::ZERO)
+            .collect::<Vec<_>>();
+        assert_eq!(drained, [tx.clone()]);
+        queue
+            .push(accepted_tx_by_someone(&time_source), &state_view)
+            .unwrap();
+        assert!(matches!(
+            queue.requeue(tx.clone(), &state_view),
+            Err(Failure {
+                err: Error::Full,
+                ..
+            })
+        ));
+        assert_eq!(queue.metrics().requeues_total.get(), 1);
+
+        // Created 100ms ago by the clock readings taken when it was first pushed
+        time_handle.advance(Duration::from_millis(70));
+        assert!(!queue.is_expired(&tx));
+        time_handle.advance(Duration::from_millis(1));
+        assert!(queue.is_expired(&tx));
+    }
+
+    #[test]
+    async fn pinned_transaction_is_selected_first() {
+        let kura = Kura::blank_kura_for_testing();
+        let query_handle = LiveQueryStore::test().start();
+        let state = State::new(world_with_test_domains(), kura, query_handle);
+        let state_view = state.view();
+
+        let (time_handle, time_source) = TimeSource::new_mock(Duration::default());
+        let queue = Queue::test(
+            Config {
+                max_pinned_transactions: 1,
+                ..config_factory()
+            },
+            &time_source,
+        );
+        let txs = (0..3)
+            .map(|_| {
+                let tx = accepted_tx_by_someone(&time_source);
+                queue
+                    .push(tx.clone(), &state_view)
+                    .expect("Failed to push tx into queue");
+                time_handle.advance(Duration::from_millis(10));
+                tx
+            })
+            .collect::<Vec<_>>();
+
+        let pinned = txs[2].as_ref().hash();
+        assert!(queue.pin(&pinned));
+        assert!(!queue.pin(&txs[1].as_ref().hash()));
+        assert_eq!(
+            queue.collect_transactions_for_block(&state_view, nonzero!(2_usize)),
+            vec![txs[2].clone(), txs[0].clone()]
+        );
+        // Pinned transaction isn't selected twice
+        assert_eq!(
+            queue
+                .collect_transactions_for_block(&state_view, nonzero!(10_usize))
+                .len(),
+            3
+        );
+
+        assert!(queue.unpin(&pinned));
+        assert!(!queue.unpin(&pinned));
+        assert!(!queue.pin(&accepted_tx_by_someone(&time_source).as_ref().hash()));
+    }
+
+    #[test]
+    async fn snapshot_roundtrip() {
+        use parity_scale_codec::{Decode as _, Encode as _};
+
+        use super::snapshot::{MempoolSnapshot, SnapshotError};
+
+        let chain_id = ChainId::from("00000000-0000-0000-0000-000000000000");
+        let limits = TransactionParameters {
+            max_instructions: nonzero!(4096_u64),
+            smart_contract_size: nonzero!(1024_u64),
+        };
+        let kura = Kura::blank_kura_for_testing();
+        let query_handle = LiveQueryStore::test().start();
+        let state = State::new(world_with_test_domains(), kura, query_handle);
+        let state_view = state.view();
+
+        let (time_handle, time_source) = TimeSource::new_mock(Duration::default());
+        let queue = Queue::test(config_factory(), &time_source);
+        for _ in 0..3 {
+            queue
+                .push(accepted_tx_by_someone(&time_source), &state_view)
+                .expect("Failed to push tx into queue");
+            time_handle.advance(Duration::from_millis(10));
+        }
+
+        let snapshot = queue.export_snapshot();
+        assert_eq!(snapshot.transactions.len(), 3);
+        let decoded = MempoolSnapshot::decode(&mut snapshot.encode().as_slice())
+            .expect("Snapshot should decode");
+        assert_eq!(decoded, snapshot);
+
+        let restored = Queue::test(config_factory(), &time_source);
+        assert_eq!(
+            restored
+                .import_snapshot(decoded.clone(), &chain_id, limits, &state_view)
+                .expect("Snapshot version is supported"),
+            3
+        );
+        assert_eq!(
+            restored.collect_transactions_for_block(&state_view, nonzero!(10_usize)),
+            queue.collect_transactions_for_block(&state_view, nonzero!(10_usize))
+        );
+
+        let unsupported = MempoolSnapshot {
+            version: MempoolSnapshot::VERSION + 1,
+            ..decoded
+        };
+        assert!(matches!(
+            Queue::test(config_factory(), &time_source).import_snapshot(
+                unsupported,
+                &chain_id,
+                limits,
+                &state_view
+            ),
+            Err(SnapshotError::UnsupportedVersion { .. })
+        ));
+    }
+
+    #[test]
+    async fn iter_ordered_matches_collection_order() {
+        let kura = Kura::blank_kura_for_testing();
+        let query_handle = LiveQueryStore::test().start();
+        let state = State::new(world_with_test_domains(), kura, query_handle);
+        let state_view = state.view();
+
+        let (time_handle, time_source) = TimeSource::new_mock(Duration::default());
+        let queue = Queue::test(config_factory(), &time_source);
+        let txs: Vec<_> = (0..4)
+            .map(|_| {
+                time_handle.advance(Duration::from_millis(1));
+                accepted_tx_by_someone(&time_source)
+            })
+            .collect();
+        for tx in &txs {
+            queue.push(tx.clone(), &state_view).unwrap();
+        }
+        assert!(queue.pin(&txs[2].as_ref().hash()));
+
+        let ordered = queue.iter_ordered(&state_view);
+        assert_eq!(
+            ordered,
+            vec![
+                txs[2].clone(),
+                txs[0].clone(),
+                txs[1].clone(),
+                txs[3].clone()
+            ]
+        );
+        // The queue is restored
+        assert_eq!(
+            queue.collect_transactions_for_block(&state_view, nonzero!(10_usize)),
+            ordered
+        );
+
+        // Hashes of transactions removed during a collection are dropped rather than restored
+        let collection = queue.lock_collection();
+        let removed = txs[0].as_ref().hash();
+        queue.retain(|tx| tx.as_ref().hash() != removed);
+        drop(collection);
+        assert_eq!(queue.iter_ordered(&state_view).len(), 3);
+        assert!(queue.tombstones.is_empty());
+        assert_eq!(queue.sub_queues[0].tx_hashes.len(), 3);
+    }
+
+    #[test]
+    async fn position_of_queued_transactions() {
+        let kura = Kura::blank_kura_for_testing();
+        let query_handle = LiveQueryStore::test().start();
+        let state = State::new(world_with_test_domains(), kura, query_handle);
+        let state_view = state.view();
+
+        let (time_handle, time_source) = TimeSource::new_mock(Duration::default());
+        let queue = Queue::test(config_factory(), &time_source);
+        let hashes = (0..3)
+            .map(|_| {
+                let tx = accepted_tx_by_someone(&time_source);
+                let hash = tx.as_ref().hash();
+                queue
+                    .push(tx, &state_view)
+                    .expect("Failed to push tx into queue");
+                time_handle.advance(Duration::from_millis(10));
+                hash
+            })
+            .collect::<Vec<_>>();
+
+        for (expected, hash) in hashes.iter().enumerate() {
+            assert_eq!(queue.position(hash), Some(expected));
+        }
+        // Order is preserved after looking up positions
+        for (expected, hash) in hashes.iter().enumerate() {
+            assert_eq!(queue.position(hash), Some(expected));
+        }
+        assert_eq!(
+            queue.position(&accepted_tx_by_someone(&time_source).as_ref().hash()),
+            None
+        );
+    }
+
+    #[test]
+    async fn position_is_estimated_during_concurrent_pushes() {
+        let kura = Kura::blank_kura_for_testing();
+        let query_handle = LiveQueryStore::test().start();
+        let state = State::new(world_with_test_domains(), kura, query_handle);
+
+        let time_source = TimeSource::new_fixed(Duration::default());
+        let queue = Queue::test(
+            Config {
+                capacity: nonzero!(4_usize),
+                ..config_factory()
+            },
+            &time_source,
+        );
+        let tx = accepted_tx_by_someone(&time_source);
+        let hash = tx.as_ref().hash();
+        queue.push(tx, &state.view()).unwrap();
+
+        let pushing = AtomicBool::new(true);
+        thread::scope(|scope| {
+            scope.spawn(|| {
+                // Fills up the space freed by draining the sub-queue
+                while pushing.load(Ordering::Relaxed) {
+                    for _ in 0..3 {
+                        let _ = queue.push(accepted_tx_by_someone(&time_source), &state.view());
+                    }
+                    queue.retain(|tx| tx.as_ref().hash() == hash);
+                }
+            });
+            for _ in 0..100 {
+                assert!(queue.position(&hash).is_some());
+            }
+            pushing.store(false, Ordering::Relaxed);
+        });
+        check_queue_invariants(&queue).unwrap();
+    }
+
+    #[test]
+    async fn push_tx_already_in_blockchain() {
+        let kura = Kura::blank_kura_for_testing();
+        let query_handle = LiveQueryStore::test().start();
+        let state = State::new(world_with_test_domains(), kura, query_handle);
+        let time_source = TimeSource::new_fixed(Duration::default());
+        let tx = accepted_tx_by_someone(&time_source);
+        let mut state_block = state.block();
+        state_block
+            .transactions
+            .insert(tx.as_ref().hash(), nonzero!(1_usize));
+        state_block.commit();
+        let state_view = state.view();
+        let queue = Queue::test(config_factory(), &time_source);
+        assert!(matches!(
+            queue.push(tx, &state_view),
+            Err(Failure {
+                err: Error::InBlockchain,
+                ..
+            })
+        ));
+        assert_eq!(queue.accepted_txs.len(), 0);
+    }
+
+    #[test]
+    async fn weighted_transactions_favor_higher_weight() {
+        let kura = Kura::blank_kura_for_testing();
+        let query_handle = LiveQueryStore::test().start();
+        let state = State::new(world_with_test_domains(), kura, query_handle);
+        let state_view = state.view();
+
+        let time_source = TimeSource::new_fixed(Duration::default());
+        let queue = Queue::test(config_factory(), &time_source);
+        let heavy_tx = accepted_tx_by_someone(&time_source);
+        let light_tx = accepted_tx_by_someone(&time_source);
+        let ignored_tx = accepted_tx_by_someone(&time_source);
+        for tx in [&heavy_tx, &light_tx, &ignored_tx] {
+            queue
+                .push(tx.clone(), &state_view)
+                .expect("Failed to push tx into queue");
+        }
+        let weight = |tx: &AcceptedTransaction| {
+            if *tx == heavy_tx {
+                9
+            } else {
+                u64::from(*tx == light_tx)
+            }
+        };
+
+        let mut heavy_selected = 0;
+        for _ in 0..1000 {
+            let selected = queue.n_weighted_transactions(1, &state_view, weight);
+            assert_eq!(selected.len(), 1);
+            assert_ne!(selected[0], ignored_tx);
+            if selected[0] == heavy_tx {
+                heavy_selected += 1;
+            }
+        }
+        // Expected to be around 900
+        assert!(heavy_selected > 700, "{heavy_selected}");
+
+        // Transactions are distinct and zero weight ones are never selected
+        let selected = queue.n_weighted_transactions(3, &state_view, weight);
+        assert_eq!(selected.len(), 2);
+        assert_ne!(selected[0], selected[1]);
+    }
+
+    #[test]
+    async fn get_tx_drop_if_in_blockchain() {
+        let max_txs_in_block = nonzero!(2_usize);
+        let kura = Kura::blank_kura_for_testing();
+        let query_handle = LiveQueryStore::test().start();
+        let state = State::new(world_with_test_domains(), kura, query_handle);
+        let time_source = TimeSource::new_fixed(Duration::default());
+        let tx = accepted_tx_by_someone(&time_source);
+        let queue = Queue::test(config_factory(), &time_source);
+        queue.push(tx.clone(), &state.view()).unwrap();
+        let mut state_block = state.block();
+        state_block
+            .transactions
+            .insert(tx.as_ref().hash(), nonzero!(1_usize));
+        state_block.commit();
+        assert_eq!(
+            queue
+                .collect_transactions_for_block(&state.view(), max_txs_in_block)
+                .len(),
+            0
+        );
+        assert_eq!(queue.accepted_txs.len(), 0);
+    }
+
+    #[test]
+    async fn get_transactions_for_block_reports_reaped() {
+        let max_txs_in_block = nonzero!(10_usize);
+        let kura = Kura::blank_kura_for_testing();
+        let query_handle = LiveQueryStore::test().start();
+        let state = State::new(world_with_test_domains(), kura, query_handle);
+        let (time_handle, time_source) = TimeSource::new_mock(Duration::default());
+        let queue = Queue::test(
+            Config {
+                transaction_time_to_live: Duration::from_millis(100),
+                ..config_factory()
+            },
+            &time_source,
+        );
+
+        let expiring_tx = accepted_tx_by_someone(&time_source);
+        queue.push(expiring_tx, &state.view()).unwrap();
+        time_handle.advance(Duration::from_millis(60));
+        let committed_tx = accepted_tx_by_someone(&time_source);
+        queue.push(committed_tx.clone(), &state.view()).unwrap();
+        let fresh_tx = accepted_tx_by_someone(&time_source);
+        queue.push(fresh_tx.clone(), &state.view()).unwrap();
+        time_handle.advance(Duration::from_millis(60));
+
+        let mut state_block = state.block();
+        state_block
+            .transactions
+            .insert(committed_tx.as_ref().hash(), nonzero!(1_usize));
+        state_block.commit();
+
+        let mut txs = Vec::new();
+        let summary = queue.get_transactions_for_block_with_summary(
+            &state.view(),
+            max_txs_in_block,
+            &mut txs,
+        );
+        assert_eq!(
+            summary,
+            ReapSummary {
+                expired: 1,
+                in_blockchain: 1,
+            }
+        );
+        assert_eq!(txs, vec![fresh_tx]);
+        assert_eq!(queue.tx_len(), 1);
+    }
+
+    #[test]
+    async fn get_available_txs_with_timeout() {
+        let max_txs_in_block = nonzero!(6_usize);
+        let kura = Kura::blank_kura_for_testing();
+        let query_handle = LiveQueryStore::test().start();
+        let state = Arc::new(State::new(world_with_test_domains(), kura, query_handle));
+        let state_view = state.view();
+
+        let (time_handle, time_source) = TimeSource::new_mock(Duration::default());
+
+        let queue = Queue::test(
+            Config {
+                transaction_time_to_live: Duration::from_millis(200),
+                ..config_factory()
+            },
+            &time_source,
+        );
+        for _ in 0..(max_txs_in_block.get() - 1) {
+            queue
+                .push(accepted_tx_by_someone(&time_source), &state_view)
+                .expect("Failed to push tx into queue");
+            time_handle.advance(Duration::from_millis(100));
+        }
+
+        queue
+            .push(accepted_tx_by_someone(&time_source), &state_view)
+            .expect("Failed to push tx into queue");
+        time_handle.advance(Duration::from_millis(101));
+        assert_eq!(
+            queue
+                .collect_transactions_for_block(&state_view, max_txs_in_block)
+                .len(),
+            1
+        );
+
+        queue
+            .push(accepted_tx_by_someone(&time_source), &state_view)
+            .expect("Failed to push tx into queue");
+        time_handle.advance(Duration::from_millis(210));
+        assert_eq!(
+            queue
+                .collect_transactions_for_block(&state_view, max_txs_in_block)
+                .len(),
+            0
+        );
+    }
+
+    #[test]
+    async fn vacant_pops_are_bounded() {
+        let kura = Kura::blank_kura_for_testing();
+        let query_handle = LiveQueryStore::test().start();
+        let state = State::new(world_with_test_domains(), kura, query_handle);
+        let state_view = state.view();
+
+        let time_source = TimeSource::new_fixed(Duration::default());
+        let queue = Queue::test(config_factory(), &time_source);
+        // Hashes without transactions, as if they were lost in a race
+        for i in 0..MAX_CONSECUTIVE_VACANT_POPS {
+            let hash = HashOf::from_untyped_unchecked(Hash::new(i.to_le_bytes()));
+            queue.sub_queues[0].tx_hashes.push(hash).unwrap();
+        }
+        let tx = accepted_tx_by_someone(&time_source);
+        queue.push(tx.clone(), &state_view).unwrap();
+
+        assert!(queue
+            .collect_transactions_for_block(&state_view, nonzero!(10_usize))
+            .is_empty());
+        assert_eq!(
+            queue.metrics().vacant_pops_total.get(),
+            MAX_CONSECUTIVE_VACANT_POPS as u64
+        );
+        // Vacant hashes are dropped, so the next collection gets to the transaction
+        assert_eq!(
+            queue.collect_transactions_for_block(&state_view, nonzero!(10_usize)),
+            vec![tx]
+        );
+    }
+
+    #[test]
+    async fn waiting_for_non_empty_queue() {
+        let kura = Kura::blank_kura_for_testing();
+        let query_handle = LiveQueryStore::test().start();
+        let state = Arc::new(State::new(world_with_test_domains(), kura, query_handle));
+
+        let time_source = TimeSource::new_fixed(Duration::default());
+        let queue = Arc::new(Queue::test(config_factory(), &time_source));
+        let waiter = tokio::spawn({
+            let queue = Arc::clone(&queue);
+            async move { queue.wait_non_empty().await }
+        });
+        tokio::task::yield_now().await;
+        assert!(!waiter.is_finished());
+
+        let pusher = tokio::spawn({
+            let queue = Arc::clone(&queue);
+            let state = Arc::clone(&state);
+            async move {
+                queue
+                    .push(accepted_tx_by_someone(&time_source), &state.view())
+                    .unwrap();
+            }
+        });
+        pusher.await.unwrap();
+        tokio::time::timeout(Duration::from_secs(1), waiter)
+            .await
+            .expect("Waiter should be woken up by the push")
+            .unwrap();
+
+        // Doesn't wait for a non-empty queue
+        tokio::time::timeout(Duration::from_secs(1), queue.wait_non_empty())
+            .await
+            .unwrap();
+    }
+
+    #[test]
+    async fn instruction_budget_is_enforced() {
+        let kura = Kura::blank_kura_for_testing();
+        let query_handle = LiveQueryStore::test().start();
+        let state = State::new(world_with_test_domains(), kura, query_handle);
+        let state_view = state.view();
+
+        let (time_handle, time_source) = TimeSource::new_mock(Duration::default());
+        let queue = Queue::test(
+            Config {
+                max_total_instructions: Some(nonzero!(3_usize)),
+                ..config_factory()
+            },
+            &time_source,
+        );
+        // Every transaction has a single instruction
+        for _ in 0..3 {
+            queue
+                .push(accepted_tx_by_someone(&time_source), &state_view)
+                .unwrap();
+        }
+        assert_eq!(queue.total_instructions(), 3);
+        let rejected = accepted_tx_by_someone(&time_source);
+        assert!(matches!(
+            queue.would_accept(&rejected, &state_view),
+            Err(Error::InstructionBudgetExceeded)
+        ));
+        assert!(matches!(
+            queue.push(rejected.clone(), &state_view),
+            Err(Failure {
+                err: Error::InstructionBudgetExceeded,
+                ..
+            })
+        ));
+        assert_eq!(queue.tx_len(), 3);
+
+        // Removal for a block frees the budget
+        let drained = queue
+            .drain_for_block(&state_view, nonzero!(1_usize), Duration::ZERO)
+            .count();
+        assert_eq!(drained, 1);
+        assert_eq!(queue.total_instructions(), 2);
+        queue.push(rejected, &state_view).unwrap();
+        assert_eq!(queue.total_instructions(), 3);
+
+        // So does expiry
+        time_handle.advance(Duration::from_secs(101));
+        assert_eq!(queue.clear_expired(), 3);
+        assert_eq!(queue.total_instructions(), 0);
+    }
+
+    #[test]
+    async fn drifted_user_counts_are_rebuilt() {
+        let kura = Kura::blank_kura_for_testing();
+        let query_handle = LiveQueryStore::test().start();
+        let state = State::new(world_with_test_domains(), kura, query_handle);
+        let state_view = state.view();
+
+        let time_source = TimeSource::new_fixed(Duration::default());
+        let queue = Queue::test(config_factory(), &time_source);
+        let (alice_id, alice_keypair) = gen_account_in("wonderland");
+        let (bob_id, bob_keypair) = gen_account_in("wonderland");
+        for _ in 0..3 {
+            queue
+                .push(
+                    accepted_tx_by(alice_id.clone(), &alice_keypair, &time_source),
+                    &state_view,
+                )
+                .unwrap();
+        }
+        let bob_tx = accepted_tx_by(bob_id.clone(), &bob_keypair, &time_source);
+        queue.push(bob_tx.clone(), &state_view).unwrap();
+
+        queue.txs_per_user.insert(alice_id.clone(), 7);
+        queue.rebuild_user_counts();
+        assert_eq!(queue.len_for_account(&alice_id), 3);
+        assert_eq!(queue.len_for_account(&bob_id), 1);
+
+        // Removing a transaction of a user without any is recovered from instead of panicking
+        queue.txs_per_user.remove(&bob_id);
+        queue.txs_per_user.insert(alice_id.clone(), 1);
+        let bob_hash = bob_tx.as_ref().hash();
+        queue.retain(|tx| tx.as_ref().hash() != bob_hash);
+        queue
+            .push(
+                accepted_tx_by(alice_id.clone(), &alice_keypair, &time_source),
+                &state_view,
+            )
+            .unwrap();
+        assert_eq!(queue.len_for_account(&alice_id), 4);
+        assert_eq!(queue.len_for_account(&bob_id), 0);
+    }
+
+    #[test]
+    async fn rejected_push_keeps_rate_token() {
+        let kura = Kura::blank_kura_for_testing();
+        let query_handle = LiveQueryStore::test().start();
+        let state = State::new(world_with_test_domains(), kura, query_handle);
+        let state_view = state.view();
+
+        let time_source = TimeSource::new_fixed(Duration::default());
+        let queue = Queue::test(
+            Config {
+                capacity_per_user: nonzero!(1_usize),
+                rate_limit_burst: Some(nonzero!(2_usize)),
+                rate_limit_refill_interval: Duration::from_secs(1),
+                ..config_factory()
+            },
+            &time_source,
+        );
+        let (alice_id, alice_keypair) = gen_account_in("wonderland");
+        let tx = || accepted_tx_by(alice_id.clone(), &alice_keypair, &time_source);
+        let tokens = || queue.rate_limits.get(&alice_id).map(|bucket| bucket.tokens);
+
+        queue.push(tx(), &state_view).unwrap();
+        assert_eq!(tokens(), Some(1));
+        assert!(matches!(
+            queue.push(tx(), &state_view),
+            Err(Failure {
+                err: Error::MaximumTransactionsPerUser,
+                ..
+            })
+        ));
+        assert_eq!(tokens(), Some(1));
+
+        assert_eq!(queue.remove_by_account(&alice_id).len(), 1);
+        queue
+            .push(tx(), &state_view)
+            .expect("Token isn't spent on the rejected transaction");
+        assert_eq!(tokens(), Some(0));
+    }
+
+    #[test]
+    async fn rate_limited_tokens_are_refilled() {
+        let kura = Kura::blank_kura_for_testing();
+        let query_handle = LiveQueryStore::test().start();
+        let state = State::new(world_with_test_domains(), kura, query_handle);
+        let state_view = state.view();
+
+        let (time_handle, time_source) = TimeSource::new_mock(Duration::default());
+        let queue = Queue::test(
+            Config {
+                rate_limit_burst: Some(nonzero!(2_usize)),
+                rate_limit_refill_interval: Duration::from_millis(10),
+                ..config_factory()
+            },
+            &time_source,
+        );
+        let (alice_id, alice_keypair) = gen_account_in("wonderland");
+        let tx = || accepted_tx_by(alice_id.clone(), &alice_keypair, &time_source);
+
+        queue.push(tx(), &state_view).unwrap();
+        queue.push(tx(), &state_view).unwrap();
+        let rejected = tx();
+        assert!(matches!(
+            queue.would_accept(&rejected, &state_view),
+            Err(Error::RateLimited)
+        ));
+        assert!(matches!(
+            queue.push(rejected, &state_view),
+            Err(Failure {
+                err: Error::RateLimited,
+                ..
+            })
+        ));
+        // Other accounts aren't limited
+        queue
+            .push(accepted_tx_by_someone(&time_source), &state_view)
+            .unwrap();
+
+        time_handle.advance(Duration::from_millis(15));
+        queue.push(tx(), &state_view).unwrap();
+        assert!(matches!(
+            queue.push(tx(), &state_view),
+            Err(Failure {
+                err: Error::RateLimited,
+                ..
+            })
+        ));
+
+        // Refills up to the burst only
+        time_handle.advance(Duration::from_secs(1));
+        queue.push(tx(), &state_view).unwrap();
+        queue.push(tx(), &state_view).unwrap();
+        assert!(queue.push(tx(), &state_view).is_err());
+    }
+
+    #[test]
+    async fn recent_rejections_are_logged() {
+        let kura = Kura::blank_kura_for_testing();
+        let query_handle = LiveQueryStore::test().start();
+        let state = State::new(world_with_test_domains(), kura, query_handle);
+        let state_view = state.view();
+
+        let (time_handle, time_source) = TimeSource::new_mock(Duration::default());
+        let disabled = Queue::test(config_factory(), &time_source);
+        let queue = Queue::test(
+            Config {
+                rejection_log_capacity: 2,
+                ..config_factory()
+            },
+            &time_source,
+        );
+        let tx = accepted_tx_by_someone(&time_source);
+        let hash = tx.as_ref().hash();
+        disabled.push(tx.clone(), &state_view).unwrap();
+        queue.push(tx.clone(), &state_view).unwrap();
+
+        for _ in 0..3 {
+            time_handle.advance(Duration::from_millis(1));
+            assert!(disabled.push(tx.clone(), &state_view).is_err());
+            assert!(queue.push(tx.clone(), &state_view).is_err());
+        }
+
+        assert!(disabled.recent_rejections().is_empty());
+        let rejections: Vec<_> = queue
+            .recent_rejections()
+            .into_iter()
+            .map(|(rejected, err, time)| {
+                assert_eq!(rejected, hash);
+                assert!(matches!(err, Error::IsInQueue));
+                time
+            })
+            .collect();
+        // Only the last ones are kept
+        assert_eq!(
+            rejections,
+            [Duration::from_millis(2), Duration::from_millis(3)]
+        );
+    }
+
+    #[test]
+    async fn equal_time_transactions_are_selected_in_stable_order() {
+        let kura = Kura::blank_kura_for_testing();
+        let query_handle = LiveQueryStore::test().start();
+        let state = State::new(world_with_test_domains(), kura, query_handle);
+        let state_view = state.view();
+
+        let time_source = TimeSource::new_fixed(Duration::default());
+        let txs: Vec<_> = (0..5)
+            .map(|_| accepted_tx_by_someone(&time_source))
+            .collect();
+        let mut expected_hashes: Vec<_> = txs.iter().map(|tx| tx.as_ref().hash()).collect();
+        expected_hashes.sort();
+
+        for order in [txs.clone(), txs.into_iter().rev().collect()] {
+            let queue = Queue::test(config_factory(), &time_source);
+            for tx in order {
+                queue.push(tx, &state_view).unwrap();
+            }
+
+            let hashes: Vec<_> = queue
+                .n_random_transactions(5, &state_view)
+                .iter()
+                .map(|tx| tx.as_ref().hash())
+                .collect();
+            assert_eq!(hashes, expected_hashes);
+            let hashes: Vec<_> = queue
+                .export_snapshot()
+                .transactions
+                .iter()
+                .map(SignedTransaction::hash)
+                .collect();
+            assert_eq!(hashes, expected_hashes);
+        }
+    }
+
+    #[test]
+    async fn equal_time_transactions_are_selected_by_fee() {
+        let chain_id = ChainId::from("00000000-0000-0000-0000-000000000000");
+        let (alice_id, alice_keypair) = gen_account_in("wonderland");
+        let kura = Kura::blank_kura_for_testing();
+        let query_handle = LiveQueryStore::test().start();
+        let state = State::new(world_with_test_domains(), kura, query_handle);
+        let state_view = state.view();
+
+        let time_source = TimeSource::new_fixed(Duration::default());
+        let tx_with_fee = |amount: u32| {
+            let mut builder = TransactionBuilder::new_with_time_source(
+                chain_id.clone(),
+                alice_id.clone(),
+                &time_source,
+            )
+            .with_instructions([Log::new(
+                iroha_data_model::Level::DEBUG,
+                format!("fee of {amount}"),
+            )]);
+            builder.set_fee(TransactionFee {
+                asset_definition: "rose#wonderland".parse().unwrap(),
+                amount: amount.into(),
+            });
+            let tx = builder.sign(alice_keypair.private_key());
+            let limits = TransactionParameters {
+                max_instructions: nonzero!(4096_u64),
+                smart_contract_size: nonzero!(1024_u64),
+            };
+            AcceptedTransaction::accept(tx, &chain_id, limits).expect("Valid")
+        };
+        let cheap_tx = tx_with_fee(1);
+        let expensive_tx = tx_with_fee(2);
+        let queue = Queue::test(config_factory(), &time_source);
+        queue.push(cheap_tx.clone(), &state_view).unwrap();
+        queue.push(expensive_tx.clone(), &state_view).unwrap();
+
+        assert!(queue.selection_key(&expensive_tx) < queue.selection_key(&cheap_tx));
+        let hashes: Vec<_> = queue
+            .n_random_transactions(2, &state_view)
+            .iter()
+            .map(|tx| tx.as_ref().hash())
+            .collect();
+        assert_eq!(
+            hashes,
+            [expensive_tx.as_ref().hash(), cheap_tx.as_ref().hash()]
+        );
+    }
+
+    #[test]
+    async fn halted_queue_rejects_transactions_until_resumed() {
+        let kura = Kura::blank_kura_for_testing();
+        let query_handle = LiveQueryStore::test().start();
+        let state = State::new(world_with_test_domains(), kura, query_handle);
+        let state_view = state.view();
+
+        let time_source = TimeSource::new_fixed(Duration::default());
+        let mut queue = Queue::test(config_factory(), &time_source);
+        let (event_sender, mut event_receiver) = tokio::sync::broadcast::channel(10);
+        queue.events = QueueEvents::new(
+            event_sender,
+            config_factory().events_capacity,
+            config_factory().events_on_overflow,
+        );
+        let tx = accepted_tx_by_someone(&time_source);
+        queue.push(tx.clone(), &state_view).unwrap();
+        let _queued = event_receiver.try_recv().expect("Queued event is sent");
+
+        assert_eq!(queue.halt("maintenance".to_owned()), 1);
+        assert_eq!(queue.tx_len(), 0);
+        assert_eq!(queue.halt_reason().as_deref(), Some("maintenance"));
+        assert_eq!(
+            event_receiver.try_recv().expect("Cancelled event is sent"),
+            TransactionEvent {
+                hash: tx.as_ref().hash(),
+                block_height: None,
+                status: TransactionStatus::Cancelled(Some("maintenance".to_owned())),
+            }
+            .into()
+        );
+        let tx = accepted_tx_by_someone(&time_source);
+        assert!(matches!(
+            queue.would_accept(&tx, &state_view),
+            Err(Error::Halted(reason)) if reason == "maintenance"
+        ));
+        let failure = queue.push(tx.clone(), &state_view).unwrap_err();
+        assert!(matches!(&failure.err, Error::Halted(reason) if reason == "maintenance"));
+        assert_eq!(failure.err.to_string(), "Queue is halted: maintenance");
+
+        queue.resume();
+        assert_eq!(queue.halt_reason(), None);
+        queue.push(tx, &state_view).unwrap();
+        assert_eq!(queue.tx_len(), 1);
+    }
+
+    #[test]
+    async fn collecting_from_empty_queue_returns_early() {
+        let time_source = TimeSource::new_fixed(Duration::default());
+        let queue = Queue::test(config_factory(), &time_source);
+        let state = MockState::default();
+        assert!(queue.is_empty());
+
+        let mut transactions = Vec::new();
+        queue.get_transactions_for_block(&state, nonzero!(10_usize), &mut transactions);
+        assert!(transactions.is_empty());
+        assert_eq!(transactions.capacity(), 0);
+        // Not even a collection span is created
+        assert_eq!(queue.collection_attempts.load(Ordering::Relaxed), 0);
+
+        let tx = accepted_tx_by_someone(&time_source);
+        queue.push(tx.clone(), &state).unwrap();
+        assert!(!queue.is_empty());
+        queue.get_transactions_for_block(&state, nonzero!(10_usize), &mut transactions);
+        assert_eq!(transactions, [tx]);
+        assert_eq!(queue.collection_attempts.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    async fn push_updates_metrics() {
+        let kura = Kura::blank_kura_for_testing();
+        let query_handle = LiveQueryStore::test().start();
+        let state = State::new(world_with_test_domains(), kura, query_handle);
+        let state_view = state.view();
+
+        let time_source = TimeSource::new_fixed(Duration::default());
+
+        let queue = Queue::test(config_factory(), &time_source);
+        let tx = accepted_tx_by_someone(&time_source);
+        queue
+            .push(tx.clone(), &state_view)
+            .expect("Failed to push tx into queue");
+        assert!(matches!(
+            queue.push(tx, &state_view),
+            Err(Failure {
+                err: Error::IsInQueue,
+                ..
+            })
+        ));
+
+        let metrics = queue.metrics();
+        assert_eq!(metrics.len.get(), 1);
+        assert_eq!(metrics.pushes_total.get(), 1);
+        assert_eq!(
+            metrics
+                .rejections_total
+                .with_label_values(&["is_in_queue"])
+                .get(),
+            1
+        );
+        assert_eq!(metrics.expired_total.get(), 0);
+    }
+
+    #[test]
+    async fn rejections_are_counted_by_reason() {
+        let kura = Kura::blank_kura_for_testing();
+        let query_handle = LiveQueryStore::test().start();
+        let state = State::new(world_with_test_domains(), kura, query_handle);
+        let state_view = state.view();
+
+        let time_source = TimeSource::new_fixed(Duration::from_secs(200));
+        let queue = Queue::test(
+            Config {
+                capacity_per_user: nonzero!(1_usize),
+                ..config_factory()
+            },
+            &time_source,
+        );
+        assert!(queue.rejection_counts().is_empty());
+
+        let (alice_id, alice_keypair) = gen_account_in("wonderland");
+        let tx = accepted_tx_by(alice_id.clone(), &alice_keypair, &time_source);
+        queue.push(tx.clone(), &state_view).unwrap();
+        for _ in 0..2 {
+            assert!(queue.push(tx.clone(), &state_view).is_err());
+        }
+        if !cfg!(feature = "no-throttling") {
+            let another = accepted_tx_by(alice_id, &alice_keypair, &time_source);
+            assert!(queue.push(another, &state_view).is_err());
+        }
+        let expired = accepted_tx_by_someone(&TimeSource::new_fixed(Duration::ZERO));
+        assert!(queue.push(expired, &state_view).is_err());
+        let in_future = accepted_tx_by_someone(&TimeSource::new_fixed(Duration::from_secs(1000)));
+        assert!(queue.push(in_future, &state_view).is_err());
+
+        let mut expected = BTreeMap::from([("is_in_queue", 2), ("expired", 1), ("in_future", 1)]);
+        if !cfg!(feature = "no-throttling") {
+            expected.insert("maximum_transactions_per_user", 1);
+        }
+        assert_eq!(queue.rejection_counts(), expected);
+    }
+
+    #[test]
+    async fn wall_clock_step_does_not_expire_transactions() {
+        let max_txs_in_block = nonzero!(2_usize);
+        let kura = Kura::blank_kura_for_testing();
+        let query_handle = LiveQueryStore::test().start();
+        let state = Arc::new(State::new(world_with_test_domains(), kura, query_handle));
+        let state_view = state.view();
+
+        let (time_handle, time_source) = TimeSource::new_mock(Duration::default());
+
+        let queue = Queue::test(
+            Config {
+                transaction_time_to_live: Duration::from_millis(100),
+                ..config_factory()
+            },
+            &time_source,
+        );
+        queue
+            .push(accepted_tx_by_someone(&time_source), &state_view)
+            .expect("Failed to push tx into queue");
+
+        // Wall-clock jumps forward, but no time has actually passed
+        time_handle.set(Duration::from_secs(60 * 60));
+        assert_eq!(
+            queue
+                .collect_transactions_for_block(&state_view, max_txs_in_block)
+                .len(),
+            1
+        );
+
+        time_handle.advance(Duration::from_millis(101));
+        assert_eq!(
+            queue
+                .collect_transactions_for_block(&state_view, max_txs_in_block)
+                .len(),
+            0
+        );
+    }
+
+    // Queue should only drop transactions which are already committed or ttl expired.
+    // Others should stay in the queue until that moment.
+    #[test]
+    async fn transactions_available_after_pop() {
+        let max_txs_in_block = nonzero!(2_usize);
+        let kura = Kura::blank_kura_for_testing();
+        let query_handle = LiveQueryStore::test().start();
+        let state = Arc::new(State::new(world_with_test_domains(), kura, query_handle));
+        let state_view = state.view();
+
+        let time_source = TimeSource::new_fixed(Duration::default());
+
+        let queue = Queue::test(config_factory(), &time_source);
+        queue
+            .push(accepted_tx_by_someone(&time_source), &state_view)
+            .expect("Failed to push tx into queue");
+
+        let a = queue
+            .collect_transactions_for_block(&state_view, max_txs_in_block)
+            .into_iter()
+            .map(|tx| tx.as_ref().hash())
+            .collect::<Vec<_>>();
+        let b = queue
+            .collect_transactions_for_block(&state_view, max_txs_in_block)
+            .into_iter()
+            .map(|tx| tx.as_ref().hash())
+            .collect::<Vec<_>>();
+        assert_eq!(a.len(), 1);
+        assert_eq!(a, b);
+    }
+
+    #[test]
+    async fn custom_expired_transaction_is_rejected() {
+        const TTL_MS: u64 = 200;
+
+        let chain_id = ChainId::from("00000000-0000-0000-0000-000000000000");
+
+        let max_txs_in_block = nonzero!(2_usize);
+        let (alice_id, alice_keypair) = gen_account_in("wonderland");
+        let kura = Kura::blank_kura_for_testing();
+        let query_handle = LiveQueryStore::test().start();
+        let state = Arc::new(State::new(world_with_test_domains(), kura, query_handle));
+        let state_view = state.view();
+
+        let (time_handle, time_source) = TimeSource::new_mock(Duration::default());
+        let mut queue = Queue::test(config_factory(), &time_source);
+        let (event_sender, mut event_receiver) = tokio::sync::broadcast::channel(1);
+        queue.events = QueueEvents::new(
+            event_sender,
+            config_factory().events_capacity,
+            config_factory().events_on_overflow,
+        );
+        let fail_isi = Unregister::domain("dummy".parse().unwrap());
+        let instructions = [fail_isi];
+        let mut tx =
+            TransactionBuilder::new_with_time_source(chain_id.clone(), alice_id, &time_source)
+                .with_instructions(instructions);
+        tx.set_ttl(Duration::from_millis(TTL_MS));
+        let tx = tx.sign(alice_keypair.private_key());
+        let limits = TransactionParameters {
+            max_instructions: nonzero!(4096_u64),
+            smart_contract_size: nonzero!(1024_u64),
+        };
+        let tx_hash = tx.hash();
+        let tx = AcceptedTransaction::accept(tx, &chain_id, limits)
+            .expect("Failed to accept Transaction.");
+        queue
+            .push(tx.clone(), &state_view)
+            .expect("Failed to push tx into queue");
+        let queued_tx_event = event_receiver.recv().await.unwrap();
+
+        assert_eq!(
+            queued_tx_event,
+            TransactionEvent {
+                hash: tx_hash,
+                block_height: None,
+                status: TransactionStatus::Queued,
+            }
+            .into()
+        );
+
+        let mut txs = Vec::new();
+        time_handle.advance(Duration::from_millis(TTL_MS + 1));
+        queue.get_transactions_for_block(&state_view, max_txs_in_block, &mut txs);
+        let expired_tx_event = event_receiver.recv().await.unwrap();
+        assert!(txs.is_empty());
+
+        assert_eq!(
+            expired_tx_event,
+            TransactionEvent {
+                hash: tx_hash,
+                block_height: None,
+                status: TransactionStatus::Expired,
+            }
+            .into()
+        )
+    }
+
+    #[test]
+    async fn push_tx_with_denied_instruction_kind() {
+        let kura = Kura::blank_kura_for_testing();
+        let query_handle = LiveQueryStore::test().start();
+        let state = State::new(world_with_test_domains(), kura, query_handle);
+        let state_view = state.view();
+
+        let time_source = TimeSource::new_fixed(Duration::default());
+        let queue = Queue::test(
+            Config {
+                denied_instruction_kinds: [InstructionType::Transfer].into(),
+                ..config_factory()
+            },
+            &time_source,
+        );
+        // Transactions of `accepted_tx_by_someone` unregister a domain
+        queue
+            .push(accepted_tx_by_someone(&time_source), &state_view)
+            .expect("Unregister isn't denied");
+
+        queue
+            .update_config(&Config {
+                denied_instruction_kinds: [InstructionType::Unregister].into(),
+                capacity_per_user: config_factory().capacity,
+                ..config_factory()
+            })
+            .expect("Valid configuration");
+        assert!(matches!(
+            queue.push(accepted_tx_by_someone(&time_source), &state_view),
+            Err(Failure {
+                err: Error::InstructionKindDenied(InstructionType::Unregister),
+                ..
+            })
+        ));
+        // Denied transactions already in the queue are dropped
+        assert!(queue
+            .collect_transactions_for_block(&state_view, nonzero!(10_usize))
+            .is_empty());
+    }
+
+    #[test]
+    async fn denied_transaction_in_queue_is_cancelled() {
+        let kura = Kura::blank_kura_for_testing();
+        let query_handle = LiveQueryStore::test().start();
+        let state = State::new(world_with_test_domains(), kura, query_handle);
+        let state_view = state.view();
+
+        let time_source = TimeSource::new_fixed(Duration::default());
+        let mut queue = Queue::test(config_factory(), &time_source);
+        let (event_sender, mut event_receiver) = tokio::sync::broadcast::channel(10);
+        queue.events = QueueEvents::new(
+            event_sender,
+            config_factory().events_capacity,
+            config_factory().events_on_overflow,
+        );
+        let tx = accepted_tx_by_someone(&time_source);
+        queue.push(tx.clone(), &state_view).unwrap();
+        let _queued = event_receiver.try_recv().expect("Queued event is sent");
+
+        // Transactions of `accepted_tx_by_someone` unregister a domain
+        queue
+            .update_config(&Config {
+                denied_instruction_kinds: [InstructionType::Unregister].into(),
+                capacity_per_user: config_factory().capacity,
+                ..config_factory()
+            })
+            .expect("Valid configuration");
+        assert!(queue
+            .collect_transactions_for_block(&state_view, nonzero!(10_usize))
+            .is_empty());
+        assert_eq!(
+            event_receiver.try_recv().expect("Cancelled event is sent"),
+            TransactionEvent {
+                hash: tx.as_ref().hash(),
+                block_height: None,
+                status: TransactionStatus::Cancelled(Some(
+                    "Instructions of kind `Unregister` are denied".to_owned()
+                )),
+            }
+            .into()
+        );
+        assert_eq!(queue.tx_len(), 0);
+    }
+
+    #[test]
+    async fn push_tx_with_too_long_ttl() {
+        let chain_id = ChainId::from("00000000-0000-0000-0000-000000000000");
+        let (alice_id, alice_keypair) = gen_account_in("wonderland");
+        let kura = Kura::blank_kura_for_testing();
+        let query_handle = LiveQueryStore::test().start();
+        let state = State::new(world_with_test_domains(), kura, query_handle);
+        let state_view = state.view();
+
+        let (_time_handle, time_source) = TimeSource::new_mock(Duration::default());
+        let queue = Queue::test(
+            Config {
+                max_transaction_time_to_live: Duration::from_secs(60 * 60),
+                ..config_factory()
+            },
+            &time_source,
+        );
+        let fail_isi = Unregister::domain("dummy".parse().unwrap());
+        let mut tx =
+            TransactionBuilder::new_with_time_source(chain_id.clone(), alice_id, &time_source)
+                .with_instructions([fail_isi]);
+        tx.set_ttl(Duration::from_secs(10 * 365 * 24 * 60 * 60));
+        let tx = tx.sign(alice_keypair.private_key());
+        let limits = TransactionParameters {
+            max_instructions: nonzero!(4096_u64),
+            smart_contract_size: nonzero!(1024_u64),
+        };
+        let tx = AcceptedTransaction::accept(tx, &chain_id, limits)
+            .expect("Failed to accept Transaction.");
+
+        assert!(matches!(
+            queue.push(tx, &state_view),
+            Err(Failure {
+                err: Error::TtlTooLong,
+                ..
+            })
+        ));
+        assert_eq!(queue.tx_len(), 0);
+    }
+
+    #[test]
+    async fn tx_is_dropped_past_valid_until_block() {
+        let chain_id = ChainId::from("00000000-0000-0000-0000-000000000000");
+        let (alice_id, alice_keypair) = gen_account_in("wonderland");
+        let kura = Kura::blank_kura_for_testing();
+        let query_handle = LiveQueryStore::test().start();
+        let state = State::new(world_with_test_domains(), kura, query_handle);
+        let commit_block = |height: u8| {
+            let mut state_block = state.block();
+            state_block
+                .block_hashes
+                .push(HashOf::from_untyped_unchecked(Hash::new([height])));
+            state_block.commit();
+        };
+
+        let time_source = TimeSource::new_fixed(Duration::default());
+        let queue = Queue::test(config_factory(), &time_source);
+        let fail_isi = Unregister::domain("dummy".parse().unwrap());
+        let mut tx =
+            TransactionBuilder::new_with_time_source(chain_id.clone(), alice_id, &time_source)
+                .with_instructions([fail_isi]);
+        tx.set_valid_until_block(nonzero!(2_u64));
+        let tx = tx.sign(alice_keypair.private_key());
+        let limits = TransactionParameters {
+            max_instructions: nonzero!(4096_u64),
+            smart_contract_size: nonzero!(1024_u64),
+        };
+        let tx = AcceptedTransaction::accept(tx, &chain_id, limits)
+            .expect("Failed to accept Transaction.");
+
+        queue.push(tx.clone(), &state.view()).unwrap();
+        commit_block(1);
+        assert_eq!(
+            queue.collect_transactions_for_block(&state.view(), nonzero!(10_usize)),
+            vec![tx.clone()]
+        );
+
+        // The next block would be past the limit
+        commit_block(2);
+        assert!(queue
+            .collect_transactions_for_block(&state.view(), nonzero!(10_usize))
+            .is_empty());
+        assert_eq!(queue.tx_len(), 0);
+        assert!(matches!(
+            queue.push(tx, &state.view()),
+            Err(Failure {
+                err: Error::BlockHeightExpired {
+                    height: 2,
+                    limit: 2
+                },
+                ..
+            })
+        ));
+    }
+
+    /// State of a chain of `height` blocks with `committed` transactions
+    #[derive(Default)]
+    struct MockState {
+        height: usize,
+        committed: BTreeSet<HashOf<SignedTransaction>>,
+    }
+
+    impl QueueStateProbe for MockState {
+        fn has_transaction(&self, hash: HashOf<SignedTransaction>) -> bool {
+            self.committed.contains(&hash)
+        }
+
+        fn height(&self) -> usize {
+            self.height
+        }
+    }
+
+    #[test]
+    async fn queue_works_with_mock_state() {
+        let time_source = TimeSource::new_fixed(Duration::default());
+        let queue = Queue::test(config_factory(), &time_source);
+        let mut state = MockState::default();
+        let committed_tx = accepted_tx_by_someone(&time_source);
+        let pending_tx = accepted_tx_by_someone(&time_source);
+
+        queue.push(committed_tx.clone(), &state).unwrap();
+        queue.push(pending_tx.clone(), &state).unwrap();
+        state.height = 1;
+        state.committed.insert(committed_tx.as_ref().hash());
+
+        assert_eq!(
+            queue.collect_transactions_for_block(&state, nonzero!(10_usize)),
+            vec![pending_tx]
+        );
+        assert_eq!(queue.tx_len(), 1);
+        assert!(matches!(
+            queue.push(committed_tx, &state),
+            Err(Failure {
+                err: Error::InBlockchain,
+                ..
+            })
+        ));
+    }
+
+    /// Never expires transactions of the `exempt` account
+    #[derive(Debug)]
+    struct ExemptAccountPolicy {
+        exempt: AccountId,
+    }
+
+    impl ExpiryPolicy for ExemptAccountPolicy {
+        fn is_expired(
+            &self,
+            tx: &AcceptedTransaction,
+            now: Duration,
+            config_ttl: Duration,
+        ) -> bool {
+            *tx.authority() != self.exempt && DefaultExpiryPolicy.is_expired(tx, now, config_ttl)
+        }
+    }
+
+    #[test]
+    async fn expiry_policy_exempts_account() {
+        let kura = Kura::blank_kura_for_testing();
+        let query_handle = LiveQueryStore::test().start();
+        let state = State::new(world_with_test_domains(), kura, query_handle);
+        let state_view = state.view();
+
+        let (time_handle, time_source) = TimeSource::new_mock(Duration::default());
+        let (admin_id, admin_keypair) = gen_account_in("wonderland");
+        let queue =
+            Queue::test(config_factory(), &time_source).with_expiry_policy(ExemptAccountPolicy {
+                exempt: admin_id.clone(),
+            });
+        let admin_tx = accepted_tx_by(admin_id, &admin_keypair, &time_source);
+        queue.push(admin_tx.clone(), &state_view).unwrap();
+        let user_tx = accepted_tx_by_someone(&time_source);
+        queue.push(user_tx.clone(), &state_view).unwrap();
+
+        time_handle.advance(Duration::from_secs(101));
+        assert!(queue.is_expired(&user_tx));
+        assert!(!queue.is_expired(&admin_tx));
+        assert_eq!(queue.clear_expired(), 1);
+        assert_eq!(
+            queue
+                .drain_for_block(&state_view, nonzero!(10_usize), Duration::from_secs(10))
+                .collect::<Vec<_>>(),
+            [admin_tx]
+        );
+    }
+
+    #[test]
+    async fn expiring_transactions_are_reported_with_remaining_time() {
+        let (time_handle, time_source) = TimeSource::new_mock(Duration::default());
+        let queue = Queue::test(config_factory(), &time_source);
+        let state = MockState::default();
+
+        let mut txs = Vec::new();
+        for _ in 0..3 {
+            let tx = accepted_tx_by_someone(&time_source);
+            queue.push(tx.clone(), &state).unwrap();
+            txs.push(tx);
+            time_handle.advance(Duration::from_secs(30));
+        }
+        // Transactions are 90, 60 and 30 seconds old and live for 100 seconds
+
+        let mut expiring = queue.expiring_within(Duration::from_secs(45), &state);
+        expiring.sort_by_key(|(_, remaining)| *remaining);
+        assert_eq!(
+            expiring,
+            [
+                (txs[0].as_ref().hash(), Duration::from_secs(10)),
+                (txs[1].as_ref().hash(), Duration::from_secs(40)),
+            ]
+        );
+        assert!(queue
+            .expiring_within(Duration::from_secs(5), &state)
+            .is_empty());
+
+        time_handle.advance(Duration::from_secs(20));
+        let expiring = queue.expiring_within(Duration::from_secs(45), &state);
+        assert_eq!(
+            expiring,
+            [(txs[1].as_ref().hash(), Duration::from_secs(20))],
+            "Expired transactions aren't pending"
+        );
+    }
+
+    #[test]
+    async fn transaction_not_matching_its_hash_is_dropped() {
+        let time_source = TimeSource::new_fixed(Duration::default());
+        let queue = Queue::test(config_factory(), &time_source).with_hash_verification(true);
+        let state = MockState::default();
+
+        let (account_id, key_pair) = gen_account_in("wonderland");
+        let tx = accepted_tx_by(account_id.clone(), &key_pair, &time_source);
+        queue.push(tx.clone(), &state).unwrap();
+        // Corrupt the entry with another transaction of the same authority
+        let other_tx = accepted_tx_by(account_id.clone(), &key_pair, &time_source);
+        queue.accepted_txs.insert(tx.as_ref().hash(), other_tx);
+
+        assert!(queue
+            .collect_transactions_for_block(&state, nonzero!(10_usize))
+            .is_empty());
+        assert_eq!(queue.tx_len(), 0);
+        assert_eq!(queue.len_for_account(&account_id), 0);
+    }
+
+    #[test]
+    async fn queue_built_with_builder_accepts_transactions() {
+        let (time_handle, time_source) = TimeSource::new_mock(Duration::default());
+        let (events_sender, mut events) = tokio::sync::broadcast::channel(10);
+        let queue = Queue::builder()
+            .capacity(nonzero!(10_usize))
+            .ttl(Duration::from_secs(10))
+            .time_source(time_source.clone())
+            .events_sender(events_sender)
+            .build()
+            .expect("Valid configuration");
+        assert_eq!(queue.capacity(), nonzero!(10_usize));
+        assert_eq!(queue.capacity_per_user(), nonzero!(10_usize));
+        let state = MockState::default();
+
+        let tx = accepted_tx_by_someone(&time_source);
+        queue.push(tx.clone(), &state).unwrap();
+        events.try_recv().expect("Queued event is sent");
+        assert_eq!(queue.tx_len(), 1);
+
+        time_handle.advance(Duration::from_secs(11));
+        assert!(queue.is_expired(&tx));
+    }
+
+    #[test]
+    async fn builder_rejects_invalid_configuration() {
+        let issues = Queue::builder()
+            .capacity(nonzero!(10_usize))
+            .capacity_per_user(nonzero!(20_usize))
+            .build()
+            .map(|_| ())
+            .unwrap_err();
+        assert_eq!(
+            issues,
+            [ConfigIssue::CapacityPerUserExceedsCapacity {
+                capacity: nonzero!(10_usize),
+                capacity_per_user: nonzero!(20_usize),
+            }]
+        );
+    }
+
+    /// Tallies reads of the state
+    #[derive(Debug, Default)]
+    struct TallyObserver {
+        has_transaction: AtomicUsize,
+        height: AtomicUsize,
+    }
+
+    impl QueueObserver for TallyObserver {
+        fn on_has_transaction(&self, _hash: &HashOf<SignedTransaction>) {
+            self.has_transaction.fetch_add(1, Ordering::Relaxed);
+        }
+
+        fn on_height(&self) {
+            self.height.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    #[test]
+    async fn observer_tallies_state_reads() {
+        let time_source = TimeSource::new_fixed(Duration::default());
+        let observer = Arc::new(TallyObserver::default());
+        let queue = Queue::test(config_factory(), &time_source).with_observer(observer.clone());
+        let state = MockState::default();
+
+        for _ in 0..3 {
+            queue
+                .push(accepted_tx_by_someone(&time_source), &state)
+                .unwrap();
+        }
+        // One lookup of the transaction per push, the height is only read for transactions valid until a block
+        assert_eq!(observer.has_transaction.load(Ordering::Relaxed), 3);
+        assert_eq!(observer.height.load(Ordering::Relaxed), 0);
+
+        assert_eq!(
+            queue
+                .collect_transactions_for_block(&state, nonzero!(10_usize))
+                .len(),
+            3
+        );
+        assert_eq!(observer.has_transaction.load(Ordering::Relaxed), 6);
+    }
+
+    #[test]
+    async fn extreme_times_do_not_panic() {
+        let chain_id = ChainId::from("00000000-0000-0000-0000-000000000000");
+        let (alice_id, alice_keypair) = gen_account_in("wonderland");
+        let kura = Kura::blank_kura_for_testing();
+        let query_handle = LiveQueryStore::test().start();
+        let state = State::new(world_with_test_domains(), kura, query_handle);
+        let state_view = state.view();
+
+        let limits = TransactionParameters {
+            max_instructions: nonzero!(4096_u64),
+            smart_contract_size: nonzero!(1024_u64),
+        };
+        let tx_created_at = |creation_time: Duration, time_to_live: Option<Duration>| {
+            let fail_isi = Unregister::domain("dummy".parse().unwrap());
+            let mut tx = TransactionBuilder::new_with_time_source(
+                chain_id.clone(),
+                alice_id.clone(),
+                &TimeSource::new_fixed(Duration::ZERO),
+            )
+            .with_instructions([fail_isi]);
+            tx.set_creation_time(creation_time);
+            if let Some(time_to_live) = time_to_live {
+                tx.set_ttl(time_to_live);
+            }
+            let tx = tx.sign(alice_keypair.private_key());
+            AcceptedTransaction::accept(tx, &chain_id, limits)
+                .expect("Failed to accept Transaction.")
+        };
+        let max_millis = Duration::from_millis(u64::MAX);
+
+        for now in [Duration::ZERO, max_millis, Duration::MAX] {
+            let time_source = TimeSource::new_fixed(now);
+            let queue = Queue::test(
+                Config {
+                    transaction_time_to_live: Duration::MAX,
+                    max_transaction_time_to_live: Duration::MAX,
+                    future_threshold: Duration::MAX,
+                    expiry_grace: Duration::MAX,
+                    soft_age_threshold: Some(Duration::MAX),
+                    ..config_factory()
+                },
+                &time_source,
+            );
+
+            for tx in [
+                tx_created_at(Duration::ZERO, None),
+                tx_created_at(Duration::ZERO, Some(max_millis)),
+                tx_created_at(max_millis, None),
+                tx_created_at(max_millis, Some(max_millis)),
+            ] {
+                let _ = queue.is_expired(&tx);
+                let _ = queue.push_blocking(tx.clone(), &state_view, Duration::MAX);
+                let _ = queue
+                    .push_blocking_async(tx, &state_view, Duration::MAX)
+                    .await;
+            }
+            let _ = queue.collect_transactions_for_block(&state_view, nonzero!(10_usize));
+            let _: Vec<_> = queue
+                .drain_for_block(&state_view, nonzero!(10_usize), Duration::MAX)
+                .collect();
+            let _ = queue.clear_expired();
+        }
+    }
+
+    #[test]
+    async fn concurrent_collections_are_serialized() {
+        const TXS: usize = 20;
+        let kura = Kura::blank_kura_for_testing();
+        let query_handle = LiveQueryStore::test().start();
+        let state = Arc::new(State::new(world_with_test_domains(), kura, query_handle));
+
+        let time_source = TimeSource::new_fixed(Duration::default());
+        let queue = Arc::new(Queue::test(config_factory(), &time_source));
+        for _ in 0..TXS {
+            queue
+                .push(accepted_tx_by_someone(&time_source), &state.view())
+                .expect("Failed to push tx into queue");
+        }
+
+        let start = Arc::new(std::sync::Barrier::new(2));
+        let collectors: Vec<_> = (0..2)
+            .map(|_| {
+                let queue = Arc::clone(&queue);
+                let state = Arc::clone(&state);
+                let start = Arc::clone(&start);
+                thread::spawn(move || {
+                    start.wait();
+                    (0..50)
+                        .map(|_| {
+                            queue
+                                .collect_transactions_for_block(&state.view(), nonzero!(100_usize))
+                                .len()
+                        })
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect();
+
+        for collector in collectors {
+            // Neither collection misses the transactions popped by the other one
+            assert!(collector.join().unwrap().iter().all(|len| *len == TXS));
+        }
+        assert_eq!(queue.sub_queues[0].tx_hashes.len(), TXS);
+    }
+
+    #[test]
+    async fn concurrent_stress_test() {
+        let max_txs_in_block = nonzero!(10_usize);
+        let kura = Kura::blank_kura_for_testing();
+        let query_handle = LiveQueryStore::test().start();
+        let state = Arc::new(State::new(world_with_test_domains(), kura, query_handle));
+
+        let (time_handle, time_source) = TimeSource::new_mock(Duration::default());
+
+        let queue = Arc::new(Queue::test(
+            Config {
+                transaction_time_to_live: Duration::from_secs(100),
+                capacity: 100_000_000.try_into().unwrap(),
+                ..Config::default()
+            },
+            &time_source,
+        ));
+
+        let start_time = std::time::Instant::now();
+        let run_for = Duration::from_secs(5);
+
+        let push_txs_handle = {
+            let queue_arc_clone = Arc::clone(&queue);
+            let state = state.clone();
+
+            // Spawn a thread where we push transactions
+            thread::spawn(move || {
+                while start_time.elapsed() < run_for {
+                    let tx = accepted_tx_by_someone(&time_source);
+                    match queue_arc_clone.push(tx, &state.view()) {
+                        Ok(())
+                        | Err(Failure {
+                            err: Error::Full | Error::MaximumTransactionsPerUser,
+                            ..
+                        }) => (),
+                        Err(Failure { err, .. }) => panic!("{err}"),
+                    }
+                }
+            })
+        };
+
+        // Spawn a thread where we get_transactions_for_block and add them to state
+        let get_txs_handle = {
+            let queue = Arc::clone(&queue);
+
+            thread::spawn(move || {
+                while start_time.elapsed() < run_for {
+                    for tx in queue.collect_transactions_for_block(&state.view(), max_txs_in_block)
+                    {
+                        let mut state_block = state.block();
+                        state_block
+                            .transactions
+                            .insert(tx.as_ref().hash(), nonzero!(1_usize));
+                        state_block.commit();
+                    }
+                    // Simulate random small delays
+                    let delay = Duration::from_millis(rand::thread_rng().gen_range(0..25));
+                    thread::sleep(delay);
+                    time_handle.advance(delay);
+                }
+            })
+        };
+
+        push_txs_handle.join().unwrap();
+        get_txs_handle.join().unwrap();
+
+        // Validate the queue state.
+        let array_queue: Vec<_> =
+            core::iter::from_fn(|| queue.sub_queues[0].tx_hashes.pop()).collect();
+
+        assert_eq!(array_queue.len(), queue.accepted_txs.len());
+        for tx in array_queue {
+            assert!(queue.accepted_txs.contains_key(&tx));
+        }
+    }
+
+    /// Operation on the queue performed by [`queue_invariants_hold`]
+    #[derive(Debug, Clone)]
+    enum QueueOp {
+        Push {
+            user: usize,
+        },
+        Collect {
+            max: NonZeroUsize,
+        },
+        Commit {
+            max: NonZeroUsize,
+        },
+        Drain {
+            max: NonZeroUsize,
+        },
+        AdvanceTime(Duration),
+        ClearExpired,
+        RemoveByAccount {
+            user: usize,
+        },
+        /// Push a transaction removed earlier again, if there is any
+        PushRemoved {
+            index: usize,
+        },
+    }
+
+    const QUEUE_OP_USERS: usize = 3;
+    const QUEUE_OP_CAPACITY: NonZeroUsize = nonzero!(8_usize);
+    const QUEUE_OP_CAPACITY_PER_USER: NonZeroUsize = nonzero!(3_usize);
+
+    fn queue_op_strategy() -> impl Strategy<Value = QueueOp> {
+        let max = (1..5_usize).prop_map(|max| NonZeroUsize::new(max).expect("Non zero"));
+        prop_oneof![
+            4 => (0..QUEUE_OP_USERS).prop_map(|user| QueueOp::Push { user }),
+            1 => max.clone().prop_map(|max| QueueOp::Collect { max }),
+            1 => max.clone().prop_map(|max| QueueOp::Commit { max }),
+            1 => max.prop_map(|max| QueueOp::Drain { max }),
+            1 => (0..80_u64).prop_map(|ms| QueueOp::AdvanceTime(Duration::from_millis(ms))),
+            1 => Just(QueueOp::ClearExpired),
+            1 => (0..QUEUE_OP_USERS).prop_map(|user| QueueOp::RemoveByAccount { user }),
+            1 => any::<usize>().prop_map(|index| QueueOp::PushRemoved { index }),
+        ]
+    }
+
+    /// Push `tx`, checking that it's rejected only for the reasons justified by the transactions stored in the queue.
+    fn push_checked(
+        queue: &Queue,
+        tx: AcceptedTransaction,
+        state: &State,
+        evict_on_full: bool,
+    ) -> Result<(), TestCaseError> {
+        let hash = tx.as_ref().hash();
+        let stored: Vec<_> = queue
+            .accepted_txs
+            .iter()
+            .map(|entry| entry.value().clone())
+            .collect();
+        let is_queued = stored.iter().any(|queued| queued.as_ref().hash() == hash);
+        let is_expired = queue.is_expired(&tx);
+        let in_blockchain = QueueStateProbe::has_transaction(&state.view(), hash);
+        let is_full = stored.len() >= QUEUE_OP_CAPACITY.get()
+            && !(evict_on_full
+                && stored
+                    .iter()
+                    .any(|queued| queue.expires_at(queued) < queue.expires_at(&tx)));
+        let user_is_at_limit = !cfg!(feature = "no-throttling")
+            && stored
+                .iter()
+                .filter(|queued| queued.authority() == tx.authority())
+                .count()
+                >= QUEUE_OP_CAPACITY_PER_USER.get();
+
+        match queue.push(tx, &state.view()) {
+            Ok(()) => {
+                prop_assert!(!is_full, "Pushed into the full queue");
+                Ok(())
+            }
+            Err(Failure { err, .. }) => match err {
+                Error::Expired if is_expired => Ok(()),
+                Error::InBlockchain if in_blockchain => Ok(()),
+                Error::IsInQueue if is_queued => Ok(()),
+                Error::Full if is_full => Ok(()),
+                Error::MaximumTransactionsPerUser if user_is_at_limit => Ok(()),
+                err => Err(TestCaseError::fail(format!(
+                    "Unexpected push failure: {err}"
+                ))),
+            },
+        }
+    }
+
+    /// Check the bookkeeping of the `queue` against the transactions stored in it.
+    fn check_queue_invariants(queue: &Queue) -> Result<(), TestCaseError> {
+        let queued: Vec<_> = queue
+            .sub_queues
+            .iter()
+            .enumerate()
+            .flat_map(|(class, sub_queue)| {
+                core::iter::from_fn(|| sub_queue.tx_hashes.pop()).map(move |hash| (class, hash))
+            })
+            .collect();
+        for (class, hash) in &queued {
+            queue.sub_queues[*class]
+                .tx_hashes
+                .push(*hash)
+                .expect("Hash was popped from this sub-queue");
+        }
+
+        let queued_hashes: BTreeSet<_> = queued.iter().map(|(_, hash)| *hash).collect();
+        prop_assert_eq!(queued_hashes.len(), queued.len(), "Hash queued twice");
+        prop_assert_eq!(
+            queued.len(),
+            queue.accepted_txs.len() + queue.tombstones.len()
+        );
+        for entry in &queue.accepted_txs {
+            prop_assert!(queued_hashes.contains(entry.key()));
+            prop_assert!(!queue.tombstones.contains(entry.key()));
+        }
+        for hash in queue.tombstones.iter() {
+            prop_assert!(queued_hashes.contains(&*hash));
+        }
+        for hash in queue.pinned.iter() {
+            prop_assert!(queue.accepted_txs.contains_key(&*hash));
+        }
+        let mut txs_per_class = vec![0; queue.sub_queues.len()];
+        for entry in &queue.accepted_txs {
+            txs_per_class[queue.classify(entry.value())] += 1;
+        }
+        for (sub_queue, txs) in queue.sub_queues.iter().zip(txs_per_class) {
+            prop_assert_eq!(sub_queue.len.load(Ordering::Relaxed), txs);
+        }
+
+        if !cfg!(feature = "no-throttling") {
+            let mut txs_per_user = BTreeMap::<AccountId, usize>::new();
+            for entry in &queue.accepted_txs {
+                *txs_per_user
+                    .entry(entry.value().authority().clone())
+                    .or_default() += 1;
+            }
+            let counted: BTreeMap<_, _> = queue
+                .txs_per_user
+                .iter()
+                .map(|entry| (entry.key().clone(), *entry.value()))
+                .collect();
+            prop_assert_eq!(counted, txs_per_user);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    async fn queue_invariants_hold() {
+        let mut runner = TestRunner::new(ProptestConfig {
+            cases: 64,
+            failure_persistence: None,
+            ..ProptestConfig::default()
+        });
+        let strategy = (any::<bool>(), vec(queue_op_strategy(), 1..64));
+
+        runner
+            .run(&strategy, |(evict_on_full, ops)| {
+                let kura = Kura::blank_kura_for_testing();
+                let query_handle = LiveQueryStore::test().start();
+                let state = State::new(world_with_test_domains(), kura, query_handle);
+                let (time_handle, time_source) = TimeSource::new_mock(Duration::default());
+                let queue = Queue::test(
+                    Config {
+                        transaction_time_to_live: Duration::from_millis(100),
+                        capacity: QUEUE_OP_CAPACITY,
+                        capacity_per_user: QUEUE_OP_CAPACITY_PER_USER,
+                        evict_on_full,
+                        ..Config::default()
+                    },
+                    &time_source,
+                );
+                let users: Vec<_> = (0..QUEUE_OP_USERS)
+                    .map(|_| gen_account_in("wonderland"))
+                    .collect();
+                let mut removed = Vec::new();
+
+                for op in ops {
+                    match op {
+                        QueueOp::Push { user } => {
+                            let (account_id, key_pair) = &users[user];
+                            let tx = accepted_tx_by(account_id.clone(), key_pair, &time_source);
+                            push_checked(&queue, tx, &state, evict_on_full)?;
+                        }
+                        QueueOp::Collect { max } => {
+                            let txs = queue.collect_transactions_for_block(&state.view(), max);
+                            prop_assert!(txs.len() <= max.get());
+                        }
+                        QueueOp::Commit { max } => {
+                            let txs = queue.collect_transactions_for_block(&state.view(), max);
+                            let mut state_block = state.block();
+                            for tx in txs {
+                                state_block
+                                    .transactions
+                                    .insert(tx.as_ref().hash(), nonzero!(1_usize));
+                            }
+                            state_block.commit();
+                        }
+                        QueueOp::Drain { max } => {
+                            let state_view = state.view();
+                            let txs: Vec<_> = queue
+                                .drain_for_block(&state_view, max, Duration::ZERO)
+                                .collect();
+                            prop_assert!(txs.len() <= max.get());
+                            for tx in txs {
+                                prop_assert!(!queue.accepted_txs.contains_key(&tx.as_ref().hash()));
+                            }
+                        }
+                        QueueOp::AdvanceTime(duration) => time_handle.advance(duration),
+                        QueueOp::ClearExpired => {
+                            queue.clear_expired();
+                        }
+                        QueueOp::RemoveByAccount { user } => {
+                            removed.extend(queue.remove_by_account(&users[user].0));
+                        }
+                        QueueOp::PushRemoved { index } => {
+                            if !removed.is_empty() {
+                                let tx = removed[index % removed.len()].clone();
+                                push_checked(&queue, tx, &state, evict_on_full)?;
+                            }
+                        }
+                    }
+                    check_queue_invariants(&queue)?;
+                }
+
+                Ok(())
+            })
+            .unwrap_or_else(|err| panic!("{err}"));
+    }
+
+    #[test]
+    async fn push_tx_in_future() {
+        let future_threshold = Duration::from_secs(1);
+
+        let kura = Kura::blank_kura_for_testing();
+        let query_handle = LiveQueryStore::test().start();
+        let state = Arc::new(State::new(world_with_test_domains(), kura, query_handle));
+        let state_view = state.view();
+
+        let (time_handle, time_source) = TimeSource::new_mock(Duration::default());
+        let queue = Queue::test(
+            Config {
+                future_threshold,
+                ..Config::default()
+            },
+            &time_source,
+        );
+
+        let tx = accepted_tx_by_someone(&time_source);
+        assert!(queue.push(tx.clone(), &state_view).is_ok());
+
+        // create the same tx but with timestamp in the future
+        time_handle.advance(future_threshold * 2);
+        let tx = accepted_tx_by_someone(&time_source);
+        time_handle.rewind(future_threshold * 2);
+
+        assert!(matches!(
+            queue.push(tx, &state_view),
+            Err(Failure {
+                err: Error::InFuture,
+                clock_skew: Some(clock_skew),
+                ..
+            }) if clock_skew == future_threshold * 2
+        ));
+        assert_eq!(queue.accepted_txs.len(), 1);
+    }
+
+    #[test]
+    #[cfg(not(feature = "no-throttling"))]
+    async fn queue_throttling() {
+        let kura = Kura::blank_kura_for_testing();
+        let (alice_id, alice_keypair) = gen_account_in("wonderland");
+        let (bob_id, bob_keypair) = gen_account_in("wonderland");
+        let world = {
+            let domain_id = DomainId::from_str("wonderland").expect("Valid");
+            let domain = Domain::new(domain_id).build(&alice_id);
+            let alice_account = Account::new(alice_id.clone()).build(&alice_id);
+            let bob_account = Account::new(bob_id.clone()).build(&bob_id);
+            World::with([domain], [alice_account, bob_account], [])
+        };
+        let query_handle = LiveQueryStore::test().start();
+        let state = State::new(world, kura, query_handle);
+
+        let time_source = TimeSource::new_fixed(Duration::default());
+
+        let queue = Queue::test(
+            Config {
+                transaction_time_to_live: Duration::from_secs(100),
+                capacity: 100.try_into().unwrap(),
+                capacity_per_user: 1.try_into().unwrap(),
+                ..Config::default()
+            },
+            &time_source,
+        );
+
+        // First push by Alice should be fine
+        queue
+            .push(
+                accepted_tx_by(alice_id.clone(), &alice_keypair, &time_source),
+                &state.view(),
+            )
+            .expect("Failed to push tx into queue");
+
+        // Second push by Alice excide limit and will be rejected
+        let result = queue.push(
+            accepted_tx_by(alice_id.clone(), &alice_keypair, &time_source),
+            &state.view(),
+        );
+        assert!(
+            matches!(
+                result,
+                Err(Failure {
+                    err: Error::MaximumTransactionsPerUser,
+                    ..
+                }),
+            ),
+            "Failed to match: {result:?}",
+        );
+
+        // First push by Bob should be fine despite previous Alice error
+        queue
+            .push(
+                accepted_tx_by(bob_id.clone(), &bob_keypair, &time_source),
+                &state.view(),
+            )
+            .expect("Failed to push tx into queue");
+
+        let transactions = queue.collect_transactions_for_block(&state.view(), nonzero!(10_usize));
+        assert_eq!(transactions.len(), 2);
+        let mut state_block = state.block();
+        for transaction in transactions {
+            // Put transaction hashes into state as if they were in the blockchain
+            state_block
+                .transactions
+                .insert(transaction.as_ref().hash(), nonzero!(1_usize));
+        }
+        state_block.commit();
+        // Cleanup transactions
+        let transactions = queue.collect_transactions_for_block(&state.view(), nonzero!(10_usize));
+        assert!(transactions.is_empty());
+
+        // After cleanup Alice and Bob pushes should work fine
+        queue
+            .push(
+                accepted_tx_by(alice_id, &alice_keypair, &time_source),
+                &state.view(),
+            )
+            .expect("Failed to push tx into queue");
+
+        queue
+            .push(
+                accepted_tx_by(bob_id, &bob_keypair, &time_source),
+                &state.view(),
+            )
+            .expect("Failed to push tx into queue");
+    }
+}
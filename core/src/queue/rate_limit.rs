@@ -0,0 +1,107 @@
+//! Per account token bucket limiting the rate at which transactions are admitted into the [`Queue`]
+
+use std::time::Duration;
+
+use dashmap::mapref::entry::Entry;
+use iroha_data_model::account::AccountId;
+use iroha_logger::warn;
+
+use super::{Error, Queue};
+
+/// Token bucket of a single account.
+///
+/// Holds up to the configured burst of tokens and gains one token per refill interval.
+/// Every admitted transaction takes a token.
+#[derive(Debug, Clone, Copy)]
+pub(super) struct TokenBucket {
+    pub(super) tokens: usize,
+    /// Monotonic time at which the last token was gained
+    refilled_at: Duration,
+}
+
+impl TokenBucket {
+    /// Add the tokens gained since the last refill, up to `burst`.
+    fn refill(&mut self, now: Duration, burst: usize, interval: Duration) {
+        let elapsed = now.saturating_sub(self.refilled_at);
+        let gained = elapsed.as_nanos() / interval.as_nanos().max(1);
+        if gained == 0 {
+            return;
+        }
+        match (usize::try_from(gained), u32::try_from(gained)) {
+            (Ok(tokens), Ok(intervals)) if self.tokens.saturating_add(tokens) < burst => {
+                self.tokens += tokens;
+                // Keep the remainder, so that frequent refills don't delay the next token
+                self.refilled_at += interval * intervals;
+            }
+            _ => {
+                self.tokens = burst;
+                self.refilled_at = now;
+            }
+        }
+    }
+}
+
+impl Queue {
+    /// Take a token of the rate limiter of `account_id`, see [`Config::rate_limit_burst`](super::Config).
+    pub(super) fn take_rate_token(&self, account_id: &AccountId) -> Result<(), Error> {
+        let Some(burst) = self.rate_limit_burst else {
+            return Ok(());
+        };
+        let now = self.time_source.get_monotonic_time();
+        let mut bucket = match self.rate_limits.entry(account_id.clone()) {
+            Entry::Occupied(occupied) => occupied.into_ref(),
+            Entry::Vacant(vacant) => vacant.insert(TokenBucket {
+                tokens: burst.get(),
+                refilled_at: now,
+            }),
+        };
+        bucket.refill(now, burst.get(), self.rate_limit_refill_interval);
+        if bucket.tokens == 0 {
+            warn!(%account_id, "Account exceeded the rate of admitted transactions");
+            return Err(Error::RateLimited);
+        }
+        bucket.tokens -= 1;
+        Ok(())
+    }
+
+    /// Return the token taken by [`Self::take_rate_token`] for a transaction which wasn't admitted after all.
+    pub(super) fn refund_rate_token(&self, account_id: &AccountId) {
+        let Some(burst) = self.rate_limit_burst else {
+            return;
+        };
+        if let Some(mut bucket) = self.rate_limits.get_mut(account_id) {
+            bucket.tokens = bucket.tokens.saturating_add(1).min(burst.get());
+        }
+    }
+
+    /// Check that the rate limiter of `account_id` has a token, without taking it.
+    pub(super) fn check_rate_limit(&self, account_id: &AccountId) -> Result<(), Error> {
+        let Some(burst) = self.rate_limit_burst else {
+            return Ok(());
+        };
+        let Some(mut bucket) = self.rate_limits.get(account_id).map(|bucket| *bucket) else {
+            return Ok(());
+        };
+        bucket.refill(
+            self.time_source.get_monotonic_time(),
+            burst.get(),
+            self.rate_limit_refill_interval,
+        );
+        if bucket.tokens == 0 {
+            return Err(Error::RateLimited);
+        }
+        Ok(())
+    }
+
+    /// Drop the rate limiters of accounts which have refilled their burst, they are recreated on demand.
+    pub(super) fn prune_rate_limits(&self) {
+        let Some(burst) = self.rate_limit_burst else {
+            return;
+        };
+        let now = self.time_source.get_monotonic_time();
+        self.rate_limits.retain(|_, bucket| {
+            bucket.refill(now, burst.get(), self.rate_limit_refill_interval);
+            bucket.tokens < burst.get()
+        });
+    }
+}
@@ -0,0 +1,81 @@
+//! Actor which periodically removes expired transactions from the [`Queue`]
+
+use std::{sync::Arc, time::Duration};
+
+use iroha_config::parameters::actual::Queue as Config;
+use iroha_logger::{info, trace};
+use tokio::{sync::Notify, task::JoinHandle};
+
+use super::Queue;
+
+/// Actor which periodically calls [`Queue::clear_expired`].
+///
+/// Without it expired transactions are only removed while collecting transactions for a block,
+/// so memory can stay elevated between blocks.
+pub struct QueueReaper {
+    queue: Arc<Queue>,
+    /// Interval between removals of expired transactions
+    reaping_interval: Duration,
+    notify_shutdown: Arc<Notify>,
+}
+
+/// [`QueueReaper`] actor handle.
+///
+/// Dropping the handle detaches the actor, which then runs until the node is shut down.
+pub struct QueueReaperHandle {
+    cancel: Arc<Notify>,
+    join_handle: JoinHandle<()>,
+}
+
+impl QueueReaperHandle {
+    /// Stop the actor and wait for it to finish.
+    pub async fn stop(self) {
+        self.cancel.notify_one();
+        let _ = self.join_handle.await;
+    }
+}
+
+impl QueueReaper {
+    /// Construct [`Self`] from configuration
+    pub fn from_config(
+        Config {
+            reaping_interval, ..
+        }: Config,
+        queue: Arc<Queue>,
+        notify_shutdown: Arc<Notify>,
+    ) -> Self {
+        Self {
+            queue,
+            reaping_interval,
+            notify_shutdown,
+        }
+    }
+
+    /// Start [`Self`] actor. Requires a [`tokio::runtime::Runtime`] being run
+    /// as it will create new [`tokio::task`].
+    pub fn start(self) -> QueueReaperHandle {
+        let cancel = Arc::new(Notify::new());
+        let join_handle = tokio::task::spawn(self.run(Arc::clone(&cancel)));
+        QueueReaperHandle {
+            cancel,
+            join_handle,
+        }
+    }
+
+    async fn run(self, cancel: Arc<Notify>) {
+        let mut reaping_interval = tokio::time::interval(self.reaping_interval);
+        loop {
+            tokio::select! {
+                _ = reaping_interval.tick() => {
+                    let removed = self.queue.clear_expired();
+                    trace!(removed, "Removed expired transactions from the queue");
+                }
+                () = cancel.notified() => break,
+                () = self.notify_shutdown.notified() => {
+                    info!("QueueReaper is being shut down.");
+                    break;
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,102 @@
+//! Versioned wire format of the [`Queue`] contents, used to persist them across restarts and to sync peers
+
+use iroha_data_model::{parameter::TransactionParameters, transaction::SignedTransaction, ChainId};
+use iroha_logger::debug;
+use parity_scale_codec::{Decode, Encode};
+use thiserror::Error;
+
+use super::{Queue, QueueStateProbe};
+use crate::prelude::*;
+
+/// Snapshot of the transactions in the [`Queue`], see [`Queue::export_snapshot`]
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+pub struct MempoolSnapshot {
+    /// Version of the format, checked on import
+    pub version: u8,
+    /// Unix time at which the snapshot was taken, in milliseconds
+    pub timestamp_ms: u64,
+    /// Transactions in the queue, ordered by [`TxSelectionKey`](super::TxSelectionKey)
+    pub transactions: Vec<SignedTransaction>,
+}
+
+impl MempoolSnapshot {
+    /// Version of the format produced by this peer
+    pub const VERSION: u8 = 1;
+}
+
+/// Failure to import a [`MempoolSnapshot`]
+#[derive(Error, Copy, Clone, Debug, displaydoc::Display)]
+pub enum SnapshotError {
+    /// Unsupported mempool snapshot version {actual}, expected {expected}
+    UnsupportedVersion {
+        /// Version supported by this peer
+        expected: u8,
+        /// Version of the snapshot
+        actual: u8,
+    },
+}
+
+impl Queue {
+    /// Take a snapshot of the transactions in the queue.
+    pub fn export_snapshot(&self) -> MempoolSnapshot {
+        let mut transactions: Vec<_> = self
+            .accepted_txs
+            .iter()
+            .map(|tx| (self.selection_key(tx.value()), tx.value().as_ref().clone()))
+            .collect();
+        transactions.sort_unstable_by_key(|(key, _)| *key);
+        let transactions = transactions.into_iter().map(|(_, tx)| tx).collect();
+
+        MempoolSnapshot {
+            version: MempoolSnapshot::VERSION,
+            timestamp_ms: self
+                .time_source
+                .get_unix_time()
+                .as_millis()
+                .try_into()
+                .expect("Unix time in milliseconds should fit into u64"),
+            transactions,
+        }
+    }
+
+    /// Push transactions of the `snapshot` into the queue.
+    ///
+    /// Transactions are accepted and pushed the same way as submitted ones,
+    /// those failing any check (e.g. expired since the snapshot was taken) are skipped.
+    /// Returns the number of pushed transactions.
+    ///
+    /// # Errors
+    /// Fails if the version of the `snapshot` isn't supported.
+    pub fn import_snapshot(
+        &self,
+        snapshot: MempoolSnapshot,
+        chain_id: &ChainId,
+        limits: TransactionParameters,
+        state_view: &impl QueueStateProbe,
+    ) -> Result<usize, SnapshotError> {
+        if snapshot.version != MempoolSnapshot::VERSION {
+            return Err(SnapshotError::UnsupportedVersion {
+                expected: MempoolSnapshot::VERSION,
+                actual: snapshot.version,
+            });
+        }
+
+        let mut imported = 0;
+        for tx in snapshot.transactions {
+            let hash = tx.hash();
+            let tx = match AcceptedTransaction::accept(tx, chain_id, limits) {
+                Ok(tx) => tx,
+                Err(err) => {
+                    debug!(tx=%hash, %err, "Skipping snapshot transaction");
+                    continue;
+                }
+            };
+            match self.push(tx, state_view) {
+                Ok(()) => imported += 1,
+                Err(failure) => debug!(tx=%hash, err=?failure.err, "Skipping snapshot transaction"),
+            }
+        }
+
+        Ok(imported)
+    }
+}
@@ -764,6 +764,13 @@ impl<'world> WorldBlock<'world> {
         }
     }
 
+    /// Events produced during execution of the block so far, in the order of emission.
+    ///
+    /// Events of rejected transactions are discarded along with their changes.
+    pub fn events(&self) -> &[EventBox] {
+        &self.events_buffer
+    }
+
     /// Commit block's changes
     pub fn commit(self) {
         // NOTE: intentionally destruct self not to forget commit some fields
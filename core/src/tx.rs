@@ -8,6 +8,8 @@
 //! This is also where the actual execution of instructions, as well
 //! as various forms of validation are performed.
 
+use core::time::Duration;
+
 use eyre::Result;
 use iroha_crypto::SignatureOf;
 pub use iroha_data_model::prelude::*;
@@ -22,7 +24,7 @@ use storage::storage::StorageReadOnly;
 
 use crate::{
     smartcontracts::wasm,
-    state::{StateBlock, StateTransaction},
+    state::{StateBlock, StateReadOnly, StateTransaction},
 };
 
 /// `AcceptedTransaction` — a transaction accepted by Iroha peer.
@@ -60,6 +62,28 @@ pub enum AcceptTransactionFail {
     ChainIdMismatch(Mismatch<ChainId>),
 }
 
+impl AcceptTransactionFail {
+    /// HTTP status code best describing the failure to API clients
+    pub fn http_status(&self) -> u16 {
+        match self {
+            Self::TransactionLimit(_) => 413,
+            Self::SignatureVerification(_) => 401,
+            Self::UnexpectedGenesisAccountSignature => 403,
+            Self::ChainIdMismatch(_) => 400,
+        }
+    }
+
+    /// Stable machine-readable code of the failure, unique per variant
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::TransactionLimit(_) => "transaction_limit",
+            Self::SignatureVerification(_) => "signature_verification",
+            Self::UnexpectedGenesisAccountSignature => "unexpected_genesis_account_signature",
+            Self::ChainIdMismatch(_) => "chain_id_mismatch",
+        }
+    }
+}
+
 impl AcceptedTransaction {
     /// Accept genesis transaction. Transition from [`SignedTransaction`] to [`AcceptedTransaction`].
     ///
@@ -158,6 +182,49 @@ impl AcceptedTransaction {
 
         Ok(Self(tx))
     }
+
+    /// Instructions of this transaction in the exact order they are executed.
+    ///
+    /// Instructions are always executed in payload order, so consensus code can
+    /// compare this slice against the committed block to verify execution order.
+    /// Returns `None` if the transaction carries a WASM smart contract.
+    pub fn instructions(&self) -> Option<&[InstructionBox]> {
+        match self.0.instructions() {
+            Executable::Instructions(instructions) => Some(instructions),
+            Executable::Wasm(_) => None,
+        }
+    }
+
+    /// Fee offered by the authority of this transaction.
+    ///
+    /// Whether the authority can afford it is checked during validation.
+    pub fn fee(&self) -> Option<&TransactionFee> {
+        self.0.fee()
+    }
+
+    /// Creation time of this transaction, as unix time.
+    pub fn creation_time(&self) -> Duration {
+        self.0.creation_time()
+    }
+
+    /// Time to live declared by this transaction, if any.
+    ///
+    /// The queue may drop the transaction earlier, see [`Queue::is_expired`](crate::queue::Queue::is_expired).
+    pub fn time_to_live(&self) -> Option<Duration> {
+        self.0.time_to_live()
+    }
+
+    /// Account on whose behalf this transaction is executed.
+    pub fn authority(&self) -> &AccountId {
+        self.0.authority()
+    }
+
+    /// Public keys which signed this transaction.
+    ///
+    /// Transactions are currently signed by the single signatory of their authority.
+    pub fn signatories(&self) -> impl Iterator<Item = &PublicKey> {
+        core::iter::once(self.0.authority().signatory())
+    }
 }
 
 impl From<AcceptedTransaction> for SignedTransaction {
@@ -200,6 +267,11 @@ impl TransactionExecutor {
     ///
     /// Validation is skipped for genesis.
     ///
+    /// Instructions are executed on a copy-on-write [`StateTransaction`] on top of
+    /// the given [`StateBlock`] which records only the mutations made by this transaction.
+    /// They are applied to the block if validation succeeds and discarded otherwise,
+    /// so the world state is never cloned.
+    ///
     /// # Errors
     /// Fails if validation of instruction fails (e.g. permissions mismatch).
     pub fn validate(
@@ -216,6 +288,54 @@ impl TransactionExecutor {
         Ok(tx.0)
     }
 
+    /// Same as [`Self::validate`], but also returns the changes the transaction made to the world state,
+    /// e.g. asset balance changes and created or removed entities, as [`DataEvent`]s in the order they were made.
+    ///
+    /// # Errors
+    /// Fails if validation of instruction fails (e.g. permissions mismatch).
+    pub fn validate_with_diff(
+        &self,
+        tx: AcceptedTransaction,
+        state_block: &mut StateBlock<'_>,
+    ) -> Result<(SignedTransaction, Vec<DataEvent>), (SignedTransaction, TransactionRejectionReason)>
+    {
+        let start = state_block.world.events().len();
+        let tx = self.validate(tx, state_block)?;
+        let diff = state_block.world.events()[start..]
+            .iter()
+            .filter_map(|event| match event {
+                EventBox::Data(event) => Some(event.clone()),
+                _ => None,
+            })
+            .collect();
+
+        Ok((tx, diff))
+    }
+
+    /// Validate `txs` one after another the same way as they are validated in a block,
+    /// returning the valid ones and the rejected ones with the reasons, both in the original order.
+    ///
+    /// Each transaction sees the effects of the valid transactions before it, so e.g.
+    /// a transaction of an account registered earlier in the batch is valid.
+    pub fn validate_batch(
+        &self,
+        txs: impl IntoIterator<Item = AcceptedTransaction>,
+        state_block: &mut StateBlock<'_>,
+    ) -> (
+        Vec<SignedTransaction>,
+        Vec<(SignedTransaction, TransactionRejectionReason)>,
+    ) {
+        let mut valid = Vec::new();
+        let mut rejected = Vec::new();
+        for tx in txs {
+            match self.validate(tx, state_block) {
+                Ok(tx) => valid.push(tx),
+                Err(rejection) => rejected.push(rejection),
+            }
+        }
+        (valid, rejected)
+    }
+
     fn validate_internal(
         &self,
         tx: AcceptedTransaction,
@@ -229,6 +349,23 @@ impl TransactionExecutor {
             ));
         }
 
+        if let Some(valid_until_block) = tx.as_ref().valid_until_block() {
+            // The block being validated isn't counted in the height yet
+            let block_height = state_transaction.height() as u64 + 1;
+            if block_height > valid_until_block.get() {
+                return Err(TransactionRejectionReason::ValidUntilBlockExceeded(
+                    ValidUntilBlockExceeded {
+                        valid_until_block: valid_until_block.get(),
+                        block_height,
+                    },
+                ));
+            }
+        }
+
+        if let Some(fee) = tx.fee() {
+            Self::validate_fee(authority, fee, state_transaction)?;
+        }
+
         debug!(tx=%tx.as_ref().hash(), "Validating transaction");
         Self::validate_with_runtime_executor(tx.clone(), state_transaction)?;
 
@@ -240,6 +377,25 @@ impl TransactionExecutor {
         Ok(())
     }
 
+    /// Check that the `authority` holds enough of the asset to cover the `fee`.
+    fn validate_fee(
+        authority: &AccountId,
+        fee: &TransactionFee,
+        state_transaction: &StateTransaction<'_, '_>,
+    ) -> Result<(), TransactionRejectionReason> {
+        let asset_id = AssetId::new(fee.asset_definition.clone(), authority.clone());
+
+        match state_transaction
+            .world
+            .assets
+            .get(&asset_id)
+            .map(|asset| &asset.value)
+        {
+            Some(AssetValue::Numeric(balance)) if *balance >= fee.amount => Ok(()),
+            _ => Err(TransactionRejectionReason::InsufficientFee(fee.clone())),
+        }
+    }
+
     fn validate_wasm(
         &self,
         authority: AccountId,
@@ -291,3 +447,137 @@ impl TransactionExecutor {
             })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr as _;
+
+    use test_samples::gen_account_in;
+
+    use super::*;
+    use crate::{
+        kura::Kura,
+        query::store::LiveQueryStore,
+        smartcontracts::isi::Registrable as _,
+        state::{State, World},
+    };
+
+    #[tokio::test]
+    async fn batch_sees_effects_of_earlier_transactions() {
+        let chain_id = ChainId::from("00000000-0000-0000-0000-000000000000");
+        let (alice_id, alice_keypair) = gen_account_in("wonderland");
+        let account = Account::new(alice_id.clone()).build(&alice_id);
+        let domain = Domain::new(DomainId::from_str("wonderland").expect("Valid")).build(&alice_id);
+        let world = World::with([domain], [account], []);
+        let kura = Kura::blank_kura_for_testing();
+        let query_handle = LiveQueryStore::test().start();
+        let state = State::new(world, kura, query_handle);
+
+        let (bob_id, bob_keypair) = gen_account_in("wonderland");
+        let mut state_block = state.block();
+        let limits = state_block.transaction_executor().limits;
+        let register_bob = TransactionBuilder::new(chain_id.clone(), alice_id)
+            .with_instructions([Register::account(Account::new(bob_id.clone()))])
+            .sign(alice_keypair.private_key());
+        let register_bob = AcceptedTransaction::accept(register_bob, &chain_id, limits).unwrap();
+        let by_bob = TransactionBuilder::new(chain_id.clone(), bob_id)
+            .with_instructions([Register::asset_definition(AssetDefinition::numeric(
+                "xor#wonderland".parse().unwrap(),
+            ))])
+            .sign(bob_keypair.private_key());
+        let by_bob = AcceptedTransaction::accept(by_bob, &chain_id, limits).unwrap();
+
+        // Bob doesn't exist yet
+        let (valid, rejected) = state_block
+            .transaction_executor()
+            .validate_batch([by_bob.clone()], &mut state_block);
+        assert!(valid.is_empty());
+        assert!(matches!(
+            rejected[..],
+            [(_, TransactionRejectionReason::AccountDoesNotExist(_))]
+        ));
+        drop(state_block);
+
+        let mut state_block = state.block();
+        let (valid, rejected) = state_block
+            .transaction_executor()
+            .validate_batch([register_bob.clone(), by_bob.clone()], &mut state_block);
+        assert_eq!(valid, [register_bob.0, by_bob.0]);
+        assert!(rejected.is_empty());
+    }
+
+    #[tokio::test]
+    async fn diff_contains_changes_of_transaction() {
+        let chain_id = ChainId::from("00000000-0000-0000-0000-000000000000");
+        let (alice_id, alice_keypair) = gen_account_in("wonderland");
+        let account = Account::new(alice_id.clone()).build(&alice_id);
+        let domain = Domain::new(DomainId::from_str("wonderland").expect("Valid")).build(&alice_id);
+        let world = World::with([domain], [account], []);
+        let kura = Kura::blank_kura_for_testing();
+        let query_handle = LiveQueryStore::test().start();
+        let state = State::new(world, kura, query_handle);
+        let mut state_block = state.block();
+        let limits = state_block.transaction_executor().limits;
+
+        let (bob_id, _bob_keypair) = gen_account_in("wonderland");
+        let register_bob = TransactionBuilder::new(chain_id.clone(), alice_id)
+            .with_instructions([Register::account(Account::new(bob_id.clone()))])
+            .sign(alice_keypair.private_key());
+        let register_bob = AcceptedTransaction::accept(register_bob, &chain_id, limits).unwrap();
+        let (_, diff) = state_block
+            .transaction_executor()
+            .validate_with_diff(register_bob.clone(), &mut state_block)
+            .unwrap();
+        assert!(diff.iter().any(|event| matches!(
+            event,
+            DataEvent::Domain(DomainEvent::Account(AccountEvent::Created(account)))
+                if *account.id() == bob_id
+        )));
+
+        // Rejected transaction changes nothing
+        let events = state_block.world.events().len();
+        assert!(state_block
+            .transaction_executor()
+            .validate_with_diff(register_bob, &mut state_block)
+            .is_err());
+        assert_eq!(state_block.world.events().len(), events);
+    }
+
+    #[test]
+    fn every_accept_failure_maps_to_error_status_and_unique_code() {
+        let chain_id = ChainId::from("00000000-0000-0000-0000-000000000000");
+        let (account_id, key_pair) = gen_account_in("wonderland");
+        let tx = TransactionBuilder::new(chain_id.clone(), account_id)
+            .with_instructions([Log::new(iroha_data_model::Level::INFO, "msg".to_owned())])
+            .sign(key_pair.private_key());
+
+        let failures = [
+            AcceptTransactionFail::TransactionLimit(TransactionLimitError {
+                reason: "too many instructions".to_owned(),
+            }),
+            AcceptTransactionFail::SignatureVerification(SignatureVerificationFail {
+                signature: tx.signature().0.clone(),
+                reason: "invalid signature".to_owned(),
+            }),
+            AcceptTransactionFail::UnexpectedGenesisAccountSignature,
+            AcceptTransactionFail::ChainIdMismatch(Mismatch {
+                expected: chain_id,
+                actual: ChainId::from("other"),
+            }),
+        ];
+
+        let mut codes = Vec::new();
+        for failure in &failures {
+            // Adding a variant fails to compile until it's covered here as well
+            match failure {
+                AcceptTransactionFail::TransactionLimit(_)
+                | AcceptTransactionFail::SignatureVerification(_)
+                | AcceptTransactionFail::UnexpectedGenesisAccountSignature
+                | AcceptTransactionFail::ChainIdMismatch(_) => {}
+            }
+            assert!((400..600).contains(&failure.http_status()), "{failure:?}");
+            assert!(!codes.contains(&failure.code()), "{failure:?}");
+            codes.push(failure.code());
+        }
+    }
+}
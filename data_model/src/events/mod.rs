@@ -7,7 +7,7 @@ use iroha_data_model_derive::model;
 use iroha_macro::FromVariant;
 use iroha_schema::IntoSchema;
 use parity_scale_codec::{Decode, Encode};
-use pipeline::{BlockEvent, TransactionEvent};
+use pipeline::{BlockEvent, TransactionEvent, TransactionsExpiredEvent};
 use serde::{Deserialize, Serialize};
 
 pub use self::model::*;
@@ -110,6 +110,12 @@ impl From<BlockEvent> for EventBox {
     }
 }
 
+impl From<TransactionsExpiredEvent> for EventBox {
+    fn from(source: TransactionsExpiredEvent) -> Self {
+        Self::Pipeline(source.into())
+    }
+}
+
 impl TryFrom<EventBox> for TransactionEvent {
     type Error = iroha_macro::error::ErrorTryFromEnum<EventBox, Self>;
 
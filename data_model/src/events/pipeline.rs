@@ -14,7 +14,7 @@ use serde::{Deserialize, Serialize};
 pub use self::model::*;
 use crate::{
     block::{BlockHeader, SignedBlock},
-    transaction::SignedTransaction,
+    transaction::{error::TransactionRejectionReason, SignedTransaction},
 };
 
 #[model]
@@ -41,6 +41,7 @@ mod model {
     pub enum PipelineEventBox {
         Transaction(TransactionEvent),
         Block(BlockEvent),
+        TransactionsExpired(TransactionsExpiredEvent),
     }
 
     #[derive(
@@ -90,6 +91,28 @@ mod model {
         pub status: TransactionStatus,
     }
 
+    /// Transactions which expired together, sent instead of a [`TransactionEvent`] with
+    /// [`TransactionStatus::Expired`] for each of them when many transactions expire at once
+    #[derive(
+        Debug,
+        Clone,
+        PartialEq,
+        Eq,
+        PartialOrd,
+        Ord,
+        Getters,
+        Decode,
+        Encode,
+        Deserialize,
+        Serialize,
+        IntoSchema,
+    )]
+    #[ffi_type]
+    #[getset(get = "pub")]
+    pub struct TransactionsExpiredEvent {
+        pub hashes: Vec<HashOf<SignedTransaction>>,
+    }
+
     /// Report of block's status in the pipeline
     #[derive(
         Debug,
@@ -141,6 +164,12 @@ mod model {
         Approved,
         /// Transaction was stored in the block as invalid
         Rejected(Box<crate::transaction::error::TransactionRejectionReason>),
+        /// Transaction was removed from the queue on request (e.g. suspension of its authority)
+        /// or because it no longer passes the checks of the queue (e.g. its instructions were denied),
+        /// with the reason if one is known
+        Cancelled(Option<String>),
+        /// Transaction was evicted from the full queue to admit another transaction
+        Dropped,
     }
 
     #[derive(
@@ -237,6 +266,23 @@ impl BlockEventFilter {
     }
 }
 
+impl TransactionStatus {
+    /// Check if the transaction was rejected
+    pub fn is_rejected(&self) -> bool {
+        matches!(self, Self::Rejected(_))
+    }
+
+    /// Reason for rejecting the transaction, if it was rejected
+    pub fn rejection_reason(&self) -> Option<&TransactionRejectionReason> {
+        match self {
+            Self::Rejected(reason) => Some(reason.as_ref()),
+            Self::Queued | Self::Expired | Self::Approved | Self::Cancelled(_) | Self::Dropped => {
+                None
+            }
+        }
+    }
+}
+
 impl TransactionEventFilter {
     /// Construct new instance
     #[must_use]
@@ -326,6 +372,26 @@ impl super::EventFilter for PipelineEventFilterBox {
             ]
             .into_iter()
             .all(core::convert::identity),
+            (
+                Self::Transaction(transaction_filter),
+                PipelineEventBox::TransactionsExpired(expired_event),
+            ) => [
+                transaction_filter
+                    .hash
+                    .as_ref()
+                    .filter(|hash| !expired_event.hashes.contains(hash))
+                    .is_none(),
+                TransactionEventFilter::field_matches(
+                    transaction_filter.block_height.as_ref(),
+                    &None,
+                ),
+                TransactionEventFilter::field_matches(
+                    transaction_filter.status.as_ref(),
+                    &TransactionStatus::Expired,
+                ),
+            ]
+            .into_iter()
+            .all(core::convert::identity),
             _ => false,
         }
     }
@@ -335,7 +401,7 @@ impl super::EventFilter for PipelineEventFilterBox {
 pub mod prelude {
     pub use super::{
         BlockEvent, BlockStatus, PipelineEventBox, PipelineEventFilterBox, TransactionEvent,
-        TransactionStatus,
+        TransactionStatus, TransactionsExpiredEvent,
     };
 }
 
@@ -467,4 +533,53 @@ mod tests {
             .into()],
         );
     }
+
+    #[test]
+    fn expired_transactions_are_matched_by_transaction_filter() {
+        let hash = |byte| HashOf::from_untyped_unchecked(Hash::prehashed([byte; Hash::LENGTH]));
+        let event: PipelineEventBox = TransactionsExpiredEvent {
+            hashes: vec![hash(0), hash(1)],
+        }
+        .into();
+
+        let matches =
+            |filter: TransactionEventFilter| PipelineEventFilterBox::from(filter).matches(&event);
+        assert!(matches(TransactionEventFilter::default()));
+        assert!(matches(TransactionEventFilter::default().for_hash(hash(1))));
+        assert!(matches(
+            TransactionEventFilter::default().for_status(TransactionStatus::Expired)
+        ));
+        assert!(matches(
+            TransactionEventFilter::default().for_block_height(None)
+        ));
+        assert!(!matches(
+            TransactionEventFilter::default().for_hash(hash(2))
+        ));
+        assert!(!matches(
+            TransactionEventFilter::default().for_status(TransactionStatus::Queued)
+        ));
+        assert!(!matches(
+            TransactionEventFilter::default().for_block_height(Some(nonzero!(1_u64)))
+        ));
+        assert!(!PipelineEventFilterBox::from(BlockEventFilter::default()).matches(&event));
+    }
+
+    #[test]
+    fn rejection_reason_is_exposed() {
+        let reason = Validation(ValidationFail::TooComplex);
+        let rejected = TransactionStatus::Rejected(Box::new(reason.clone()));
+
+        assert!(rejected.is_rejected());
+        assert_eq!(rejected.rejection_reason(), Some(&reason));
+        for status in [
+            TransactionStatus::Queued,
+            TransactionStatus::Expired,
+            TransactionStatus::Approved,
+            TransactionStatus::Cancelled(Some("maintenance".to_owned())),
+            TransactionStatus::Dropped,
+        ] {
+            assert!(!status.is_rejected());
+            assert_eq!(status.rejection_reason(), None);
+        }
+    }
 }
@@ -14,6 +14,7 @@ pub use http::*;
 use iroha_crypto::SignatureOf;
 use iroha_data_model_derive::model;
 use iroha_macro::FromVariant;
+use iroha_primitives::numeric::Numeric;
 use iroha_schema::IntoSchema;
 use iroha_version::{declare_versioned, version};
 use parity_scale_codec::{Decode, Encode};
@@ -22,6 +23,7 @@ use serde::{Deserialize, Serialize};
 pub use self::model::*;
 use crate::{
     account::AccountId,
+    asset::AssetDefinitionId,
     isi::{Instruction, InstructionBox},
     metadata::Metadata,
     ChainId,
@@ -115,6 +117,34 @@ mod model {
         pub nonce: Option<NonZeroU32>,
         /// Store for additional information.
         pub metadata: Metadata,
+        /// Fee offered by the authority for inclusion of this transaction.
+        pub fee: Option<TransactionFee>,
+        /// If transaction is not committed by the block of this height it will be dropped.
+        pub valid_until_block: Option<NonZeroU64>,
+    }
+
+    /// Fee offered by the transaction authority, used to prioritize transactions.
+    #[derive(
+        Debug,
+        Clone,
+        PartialEq,
+        Eq,
+        PartialOrd,
+        Ord,
+        Getters,
+        Decode,
+        Encode,
+        Deserialize,
+        Serialize,
+        IntoSchema,
+    )]
+    #[ffi_type]
+    #[getset(get = "pub")]
+    pub struct TransactionFee {
+        /// Definition of the asset the fee is paid in.
+        pub asset_definition: AssetDefinitionId,
+        /// Amount of the asset paid as the fee.
+        pub amount: Numeric,
     }
 
     /// Signature of transaction
@@ -273,6 +303,20 @@ impl SignedTransaction {
         tx.payload.nonce
     }
 
+    /// Fee offered by the transaction authority
+    #[inline]
+    pub fn fee(&self) -> Option<&TransactionFee> {
+        let SignedTransaction::V1(tx) = self;
+        tx.payload.fee.as_ref()
+    }
+
+    /// Height of the last block the transaction can be committed in
+    #[inline]
+    pub fn valid_until_block(&self) -> Option<NonZeroU64> {
+        let SignedTransaction::V1(tx) = self;
+        tx.payload.valid_until_block
+    }
+
     /// Transaction chain id
     #[inline]
     pub fn chain(&self) -> &ChainId {
@@ -500,6 +544,33 @@ pub mod error {
             pub reason: String,
         }
 
+        /// Transaction was rejected because it was executed in a block past the one it was valid until
+        #[derive(
+            Debug,
+            Display,
+            Clone,
+            Copy,
+            PartialEq,
+            Eq,
+            PartialOrd,
+            Ord,
+            Decode,
+            Encode,
+            Deserialize,
+            Serialize,
+            IntoSchema,
+        )]
+        #[display(
+            fmt = "Transaction was valid until block {valid_until_block}, but was executed in block {block_height}"
+        )]
+        #[ffi_type]
+        pub struct ValidUntilBlockExceeded {
+            /// Height of the last block the transaction could be committed in
+            pub valid_until_block: u64,
+            /// Height of the block the transaction was executed in
+            pub block_height: u64,
+        }
+
         /// The reason for rejecting transaction which happened because of transaction.
         #[derive(
             Debug,
@@ -541,6 +612,14 @@ pub mod error {
             InstructionExecution(#[cfg_attr(feature = "std", source)] InstructionExecutionFail),
             /// Failure in WebAssembly execution
             WasmExecution(#[cfg_attr(feature = "std", source)] WasmExecutionFail),
+            /// Authority can't afford the offered fee
+            InsufficientFee(
+                #[skip_from]
+                #[skip_try_from]
+                TransactionFee,
+            ),
+            /// Transaction was valid only until an earlier block
+            ValidUntilBlockExceeded(#[cfg_attr(feature = "std", source)] ValidUntilBlockExceeded),
         }
     }
 
@@ -571,6 +650,40 @@ pub mod error {
         }
     }
 
+    impl TransactionRejectionReason {
+        /// HTTP status code best describing the rejection to API clients
+        pub fn http_status(&self) -> u16 {
+            match self {
+                Self::AccountDoesNotExist(_) => 404,
+                Self::LimitCheck(_) => 413,
+                Self::Validation(crate::ValidationFail::NotPermitted(_)) => 403,
+                Self::Validation(crate::ValidationFail::InternalError(_)) => 500,
+                Self::Validation(
+                    crate::ValidationFail::InstructionFailed(_)
+                    | crate::ValidationFail::QueryFailed(_)
+                    | crate::ValidationFail::TooComplex,
+                )
+                | Self::InstructionExecution(_)
+                | Self::WasmExecution(_) => 422,
+                Self::InsufficientFee(_) => 402,
+                Self::ValidUntilBlockExceeded(_) => 410,
+            }
+        }
+
+        /// Stable machine-readable code of the rejection, unique per variant
+        pub fn code(&self) -> &'static str {
+            match self {
+                Self::AccountDoesNotExist(_) => "account_does_not_exist",
+                Self::LimitCheck(_) => "limit_check",
+                Self::Validation(_) => "validation",
+                Self::InstructionExecution(_) => "instruction_execution",
+                Self::WasmExecution(_) => "wasm_execution",
+                Self::InsufficientFee(_) => "insufficient_fee",
+                Self::ValidUntilBlockExceeded(_) => "valid_until_block_exceeded",
+            }
+        }
+    }
+
     #[cfg(feature = "std")]
     impl std::error::Error for TransactionLimitError {}
 
@@ -580,10 +693,16 @@ pub mod error {
     #[cfg(feature = "std")]
     impl std::error::Error for WasmExecutionFail {}
 
+    #[cfg(feature = "std")]
+    impl std::error::Error for ValidUntilBlockExceeded {}
+
     pub mod prelude {
         //! The prelude re-exports most commonly used traits, structs and macros from this module.
 
-        pub use super::{InstructionExecutionFail, TransactionRejectionReason, WasmExecutionFail};
+        pub use super::{
+            InstructionExecutionFail, TransactionRejectionReason, ValidUntilBlockExceeded,
+            WasmExecutionFail,
+        };
     }
 }
 
@@ -618,6 +737,8 @@ mod http {
                     time_to_live_ms: None,
                     instructions: Vec::<InstructionBox>::new().into(),
                     metadata: Metadata::default(),
+                    fee: None,
+                    valid_until_block: None,
                 },
             }
         }
@@ -712,6 +833,18 @@ mod http {
             self
         }
 
+        /// Set fee offered for [`Transaction`]
+        pub fn set_fee(&mut self, fee: TransactionFee) -> &mut Self {
+            self.payload.fee = Some(fee);
+            self
+        }
+
+        /// Set height of the last block [`Transaction`] can be committed in
+        pub fn set_valid_until_block(&mut self, height: NonZeroU64) -> &mut Self {
+            self.payload.valid_until_block = Some(height);
+            self
+        }
+
         /// Set creation time of transaction
         pub fn set_creation_time(&mut self, value: Duration) -> &mut Self {
             self.payload.creation_time_ms = u64::try_from(value.as_millis())
@@ -738,7 +871,8 @@ pub mod prelude {
     #[cfg(feature = "http")]
     pub use super::http::TransactionBuilder;
     pub use super::{
-        error::prelude::*, CommittedTransaction, Executable, SignedTransaction, WasmSmartContract,
+        error::prelude::*, CommittedTransaction, Executable, SignedTransaction, TransactionFee,
+        WasmSmartContract,
     };
 }
 
@@ -754,4 +888,62 @@ mod tests {
         let contract = WasmSmartContract::from_compiled(vec![0, 1, 2, 3, 4]);
         assert_eq!(format!("{contract:?}"), "WASM binary(len = 5)");
     }
+
+    #[test]
+    fn every_rejection_reason_maps_to_error_status_and_unique_code() {
+        use crate::{domain::DomainId, isi::Log, query::error::FindError, Level, ValidationFail};
+
+        let domain_id: DomainId = "wonderland".parse().expect("Valid");
+        let reasons = [
+            TransactionRejectionReason::AccountDoesNotExist(FindError::Domain(domain_id)),
+            TransactionRejectionReason::LimitCheck(TransactionLimitError {
+                reason: "too many instructions".to_owned(),
+            }),
+            TransactionRejectionReason::Validation(ValidationFail::TooComplex),
+            TransactionRejectionReason::InstructionExecution(InstructionExecutionFail {
+                instruction: Log {
+                    level: Level::INFO,
+                    msg: "msg".to_owned(),
+                }
+                .into(),
+                reason: "failed".to_owned(),
+            }),
+            TransactionRejectionReason::WasmExecution(WasmExecutionFail {
+                reason: "trap".to_owned(),
+            }),
+            TransactionRejectionReason::InsufficientFee(TransactionFee {
+                asset_definition: "rose#wonderland".parse().expect("Valid"),
+                amount: Numeric::ZERO,
+            }),
+            TransactionRejectionReason::ValidUntilBlockExceeded(ValidUntilBlockExceeded {
+                valid_until_block: 1,
+                block_height: 2,
+            }),
+        ];
+
+        let mut codes = Vec::new();
+        for reason in &reasons {
+            // Adding a variant fails to compile until it's covered here as well
+            match reason {
+                TransactionRejectionReason::AccountDoesNotExist(_)
+                | TransactionRejectionReason::LimitCheck(_)
+                | TransactionRejectionReason::Validation(_)
+                | TransactionRejectionReason::InstructionExecution(_)
+                | TransactionRejectionReason::WasmExecution(_)
+                | TransactionRejectionReason::InsufficientFee(_)
+                | TransactionRejectionReason::ValidUntilBlockExceeded(_) => {}
+            }
+            assert!((400..600).contains(&reason.http_status()), "{reason:?}");
+            assert!(!codes.contains(&reason.code()), "{reason:?}");
+            codes.push(reason.code());
+        }
+
+        assert_eq!(
+            TransactionRejectionReason::Validation(ValidationFail::NotPermitted(
+                "denied".to_owned()
+            ))
+            .http_status(),
+            403
+        );
+    }
 }
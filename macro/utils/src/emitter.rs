@@ -1,17 +1,64 @@
 //! A wrapper type around [`manyhow::Emitter`] that provides a more ergonomic API.
 
+use std::{
+    collections::HashSet,
+    fmt::{Display, Write as _},
+};
+
 use drop_bomb::DropBomb;
 use manyhow::ToTokensError;
-use proc_macro2::TokenStream;
+use proc_macro2::{Group, Span, TokenStream, TokenTree};
+use quote::{quote_spanned, ToTokens};
+
+/// Severity of a diagnostic reported by [`Emitter`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    /// Fails the compilation
+    Error,
+    /// Reported by the compiler, but doesn't fail the compilation
+    Warning,
+    /// Only available through [`Emitter::to_diagnostics_json`]
+    Note,
+}
+
+impl Severity {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Error => "error",
+            Self::Warning => "warning",
+            Self::Note => "note",
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Diagnostic {
+    severity: Severity,
+    span: Span,
+    message: String,
+}
 
 /// A wrapper type around [`manyhow::Emitter`] that provides a more ergonomic API.
 ///
-/// This type is used to accumulate errors during parsing and code generation.
+/// This type is used to accumulate errors and warnings during parsing and code generation.
 ///
 /// NOTE: you must call [`Emitter::finish`] or similar function to consume the accumulated errors.
 /// `Emitter` will panic if dropped without consuming the errors.
 pub struct Emitter {
     inner: manyhow::Emitter,
+    warnings: Vec<TokenStream>,
+    /// Rendered errors seen so far, present only in dedup mode
+    seen: Option<HashSet<String>>,
+    /// Maximum number of errors kept, unlimited if `None`
+    limit: Option<usize>,
+    emitted: usize,
+    suppressed: usize,
+    /// Contexts of the currently entered [`Emitter::scope`]s, outermost first
+    scopes: Vec<String>,
+    /// All emitted diagnostics in the order of emission
+    diagnostics: Vec<Diagnostic>,
+    /// Messages of errors grouped by their spans in the order of emission, present only in span grouping mode
+    groups: Option<Vec<(Span, Vec<String>)>>,
     bomb: DropBomb,
 }
 
@@ -20,13 +67,221 @@ impl Emitter {
     pub fn new() -> Self {
         Self {
             inner: manyhow::Emitter::new(),
+            warnings: Vec::new(),
+            seen: None,
+            limit: None,
+            emitted: 0,
+            suppressed: 0,
+            scopes: Vec::new(),
+            diagnostics: Vec::new(),
+            groups: None,
             bomb: DropBomb::new("Emitter dropped without consuming accumulated errors"),
         }
     }
 
+    /// Same as [`Emitter::new`], but the emitter will suppress exact duplicates of already emitted errors.
+    ///
+    /// Errors are compared by their rendered tokens, spans included,
+    /// so the same message reported at different locations is kept.
+    #[allow(unused)]
+    pub fn with_dedup() -> Self {
+        Self {
+            seen: Some(HashSet::new()),
+            ..Self::new()
+        }
+    }
+
+    /// Same as [`Emitter::new`], but the emitter will keep at most `max` errors.
+    ///
+    /// Errors emitted past the limit are dropped and reported by a single trailing
+    /// "N more errors suppressed" error when the emitter is consumed.
+    #[allow(unused)]
+    pub fn with_limit(max: usize) -> Self {
+        Self {
+            limit: Some(max),
+            ..Self::new()
+        }
+    }
+
+    /// Same as [`Emitter::new`], but errors pointing at the same span are combined into a single error
+    /// with all their messages, one per line, so that the user isn't buried in repetitive diagnostics.
+    ///
+    /// Only errors rendered as `compile_error!` with a message can be combined, others are kept as they are.
+    /// An error spanning several tokens is reported at its last token.
+    #[allow(unused)]
+    pub fn with_span_grouping() -> Self {
+        Self {
+            groups: Some(Vec::new()),
+            ..Self::new()
+        }
+    }
+
     /// Add a new error to the emitter.
+    ///
+    /// Inside of [`Emitter::scope`] the error message is prefixed with the contexts of all entered scopes.
     pub fn emit<E: ToTokensError + 'static>(&mut self, err: E) {
-        self.inner.emit(err);
+        if self.scopes.is_empty() {
+            self.push_error(err);
+        } else {
+            let err = ScopedError {
+                prefix: self.scope_prefix(),
+                tokens: err.to_token_stream(),
+            };
+            self.push_error(err);
+        }
+    }
+
+    fn push_error<E: ToTokensError + 'static>(&mut self, err: E) {
+        if let Some(seen) = &mut self.seen {
+            // `Debug` of a token stream includes the spans of its tokens
+            if !seen.insert(format!("{:?}", err.to_token_stream())) {
+                return;
+            }
+        }
+        if self.limit.is_some_and(|limit| self.emitted >= limit) {
+            self.suppressed += 1;
+            return;
+        }
+        self.emitted += 1;
+        let mut messages = Vec::new();
+        compile_error_messages(err.to_token_stream(), &mut messages);
+        self.diagnostics
+            .extend(messages.iter().map(|(span, message)| Diagnostic {
+                severity: Severity::Error,
+                span: *span,
+                message: message.clone(),
+            }));
+        match &mut self.groups {
+            Some(groups) if !messages.is_empty() => {
+                for (span, message) in messages {
+                    // `Debug` of a span includes its location
+                    let key = format!("{span:?}");
+                    match groups
+                        .iter_mut()
+                        .find(|(group_span, _)| format!("{group_span:?}") == key)
+                    {
+                        Some((_, group)) => group.push(message),
+                        None => groups.push((span, vec![message])),
+                    }
+                }
+            }
+            _ => self.inner.emit(err),
+        }
+    }
+
+    /// Emit the errors grouped by span, see [`Emitter::with_span_grouping`].
+    fn flush_groups(&mut self) {
+        for (span, messages) in self.groups.iter_mut().flat_map(core::mem::take) {
+            self.inner.emit(syn::Error::new(span, messages.join("\n")));
+        }
+    }
+
+    /// Add a new error with the given message pointing at `span`.
+    pub fn emit_at(&mut self, span: Span, message: impl Display) {
+        self.emit(syn::Error::new(span, message));
+    }
+
+    /// Add a new warning to the emitter.
+    ///
+    /// Warnings don't make [`Emitter::finish`] fail. They are rendered only by the `finish_*token_stream*` functions,
+    /// as a use of a deprecated item spanned at `span`, so that `rustc` reports `message` as a warning.
+    pub fn emit_warning(&mut self, span: Span, message: impl Display) {
+        let message = format!("{}{message}", self.scope_prefix());
+        self.diagnostics.push(Diagnostic {
+            severity: Severity::Warning,
+            span,
+            message: message.clone(),
+        });
+        self.warnings.push(quote_spanned! {span=>
+            const _: () = {
+                #[deprecated(note = #message)]
+                #[allow(non_camel_case_types)]
+                struct __iroha_macro_warning;
+                let _ = __iroha_macro_warning;
+            };
+        });
+    }
+
+    /// Run `f`, prefixing all errors and warnings emitted inside of it with `context`,
+    /// e.g. ``"while deriving field `foo`:"``.
+    ///
+    /// Nested scopes concatenate their contexts, outermost first.
+    #[allow(unused)]
+    pub fn scope<T>(&mut self, context: &str, f: impl FnOnce(&mut Emitter) -> T) -> T {
+        self.scopes.push(context.to_owned());
+        let result = f(self);
+        self.scopes.pop();
+        result
+    }
+
+    fn scope_prefix(&self) -> String {
+        self.scopes
+            .iter()
+            .map(|context| format!("{context} "))
+            .collect()
+    }
+
+    /// Add a new note to the emitter.
+    ///
+    /// Notes don't affect the compilation and are only exported by [`Emitter::to_diagnostics_json`].
+    #[allow(unused)]
+    pub fn emit_note(&mut self, span: Span, message: impl Display) {
+        let message = format!("{}{message}", self.scope_prefix());
+        self.diagnostics.push(Diagnostic {
+            severity: Severity::Note,
+            span,
+            message,
+        });
+    }
+
+    /// Export all emitted diagnostics as a JSON array of
+    /// `{"severity": ..., "span": {"line": ..., "column": ...}, "message": ...}` objects.
+    ///
+    /// Lines are 1-indexed and columns are 0-indexed.
+    #[allow(unused)]
+    pub fn to_diagnostics_json(&self) -> String {
+        let mut json = String::from("[");
+        for (i, diagnostic) in self.diagnostics.iter().enumerate() {
+            if i > 0 {
+                json.push(',');
+            }
+            let start = diagnostic.span.start();
+            write!(
+                json,
+                r#"{{"severity":"{}","span":{{"line":{},"column":{}}},"message":"#,
+                diagnostic.severity.as_str(),
+                start.line,
+                start.column
+            )
+            .expect("Writing to a string can't fail");
+            write_json_string(&mut json, &diagnostic.message);
+            json.push('}');
+        }
+        json.push(']');
+        json
+    }
+
+    /// Move all errors, warnings and notes accumulated by `other` into this emitter, consuming `other`.
+    ///
+    /// Lets a helper with its own emitter report into the emitter of the macro it's composed into.
+    /// Absorbed diagnostics are kept as they are: scopes, deduplication and the limit of this emitter
+    /// aren't applied to them. Errors suppressed by `other` are reported along with the ones suppressed by this emitter.
+    #[allow(unused)]
+    pub fn absorb(&mut self, mut other: Emitter) {
+        other.bomb.defuse();
+        other.flush_groups();
+        self.warnings.append(&mut other.warnings);
+        self.diagnostics.append(&mut other.diagnostics);
+        self.emitted += other.emitted;
+        self.suppressed += other.suppressed;
+        if let Err(err) = other.inner.into_result() {
+            self.inner.emit(err);
+        }
+    }
+
+    /// Returns `true` if any warnings were emitted.
+    pub fn has_warnings(&self) -> bool {
+        !self.warnings.is_empty()
     }
 
     /// Handle a [`manyhow::Result`] by either returning the value or emitting the error.
@@ -45,6 +300,17 @@ impl Emitter {
         }
     }
 
+    /// Same as [`Emitter::handle`], but prefixes the error with `context` describing the failed operation,
+    /// e.g. ``"while parsing `#[ffi]` attribute:"``, see [`Emitter::scope`].
+    #[allow(unused)]
+    pub fn handle_in<E: ToTokensError + 'static, T>(
+        &mut self,
+        context: &str,
+        result: manyhow::Result<T, E>,
+    ) -> Option<T> {
+        self.scope(context, |emitter| emitter.handle(result))
+    }
+
     /// Same as [`Emitter::handle`], but returns the default value of `T` if the passed value is `Err`.
     #[allow(unused)]
     pub fn handle_or_default<E: ToTokensError + 'static, T: Default>(
@@ -54,6 +320,17 @@ impl Emitter {
         self.handle(result).unwrap_or_default()
     }
 
+    /// Handle every result of the given iterator, emitting all errors and returning the `Ok` values.
+    #[allow(unused)]
+    pub fn collect_results<E: ToTokensError + 'static, T>(
+        &mut self,
+        iter: impl IntoIterator<Item = manyhow::Result<T, E>>,
+    ) -> Vec<T> {
+        iter.into_iter()
+            .filter_map(|result| self.handle(result))
+            .collect()
+    }
+
     /// Consume the emitter, returning a [`manyhow::Error`] if any errors were emitted.
     ///
     /// # Errors
@@ -61,6 +338,13 @@ impl Emitter {
     /// This function returns an error if the emitter has some errors accumulated.
     pub fn finish(mut self) -> manyhow::Result<()> {
         self.bomb.defuse();
+        self.flush_groups();
+        if self.suppressed > 0 {
+            self.inner.emit(manyhow::error_message!(
+                "{} more errors suppressed",
+                self.suppressed
+            ));
+        }
         self.inner.into_result()
     }
 
@@ -95,15 +379,16 @@ impl Emitter {
         }
     }
 
-    /// Consume the emitter, convert all errors into a token stream and append it to the given token stream.
-    pub fn finish_to_token_stream(self, tokens: &mut TokenStream) {
+    /// Consume the emitter, convert all errors and warnings into a token stream and append it to the given token stream.
+    pub fn finish_to_token_stream(mut self, tokens: &mut TokenStream) {
+        tokens.extend(core::mem::take(&mut self.warnings));
         match self.finish() {
             Ok(()) => {}
             Err(e) => e.to_tokens(tokens),
         }
     }
 
-    /// Consume the emitter, convert all errors into a token stream.
+    /// Consume the emitter, convert all errors and warnings into a token stream.
     pub fn finish_token_stream(self) -> TokenStream {
         let mut tokens_stream = TokenStream::new();
         self.finish_to_token_stream(&mut tokens_stream);
@@ -127,6 +412,261 @@ impl Default for Emitter {
 
 impl<E: ToTokensError + 'static> Extend<E> for Emitter {
     fn extend<T: IntoIterator<Item = E>>(&mut self, iter: T) {
-        self.inner.extend(iter)
+        for err in iter {
+            self.emit(err);
+        }
+    }
+}
+
+/// An error emitted inside of [`Emitter::scope`].
+#[derive(Debug)]
+struct ScopedError {
+    prefix: String,
+    tokens: TokenStream,
+}
+
+impl ToTokensError for ScopedError {
+    fn to_tokens(&self, tokens: &mut TokenStream) {
+        tokens.extend(prefix_messages(self.tokens.clone(), &self.prefix));
+    }
+}
+
+/// Prefix messages of all `compile_error!` invocations in the given tokens.
+fn prefix_messages(tokens: TokenStream, prefix: &str) -> TokenStream {
+    let mut output = Vec::new();
+
+    for token in tokens {
+        let token = match token {
+            TokenTree::Group(group) => {
+                let is_compile_error = matches!(
+                    &output[..],
+                    [.., TokenTree::Ident(ident), TokenTree::Punct(punct)]
+                        if ident == "compile_error" && punct.as_char() == '!'
+                );
+                let stream = if is_compile_error {
+                    prefix_first_literal(group.stream(), prefix)
+                } else {
+                    prefix_messages(group.stream(), prefix)
+                };
+                let mut new_group = Group::new(group.delimiter(), stream);
+                new_group.set_span(group.span());
+                TokenTree::Group(new_group)
+            }
+            token => token,
+        };
+        output.push(token);
+    }
+
+    output.into_iter().collect()
+}
+
+fn prefix_first_literal(tokens: TokenStream, prefix: &str) -> TokenStream {
+    let mut prefixed = false;
+
+    tokens
+        .into_iter()
+        .map(|token| match token {
+            TokenTree::Literal(literal) if !prefixed => {
+                let Ok(message) = syn::parse2::<syn::LitStr>(literal.to_token_stream()) else {
+                    return TokenTree::Literal(literal);
+                };
+                prefixed = true;
+                let message = format!("{prefix}{}", message.value());
+                TokenTree::Literal(syn::LitStr::new(&message, literal.span()).token())
+            }
+            token => token,
+        })
+        .collect()
+}
+
+/// Collect messages of all `compile_error!` invocations in the given tokens.
+fn compile_error_messages(tokens: TokenStream, messages: &mut Vec<(Span, String)>) {
+    let mut previous: [Option<TokenTree>; 2] = [None, None];
+
+    for token in tokens {
+        if let TokenTree::Group(group) = &token {
+            let is_compile_error = matches!(
+                &previous,
+                [Some(TokenTree::Ident(ident)), Some(TokenTree::Punct(punct))]
+                    if ident == "compile_error" && punct.as_char() == '!'
+            );
+            if is_compile_error {
+                let message = group.stream().into_iter().find_map(|token| match token {
+                    TokenTree::Literal(literal) => {
+                        syn::parse2::<syn::LitStr>(literal.to_token_stream()).ok()
+                    }
+                    _ => None,
+                });
+                if let Some(message) = message {
+                    messages.push((message.span(), message.value()));
+                }
+            } else {
+                compile_error_messages(group.stream(), messages);
+            }
+        }
+        previous = [previous[1].take(), Some(token)];
+    }
+}
+
+fn write_json_string(json: &mut String, value: &str) {
+    json.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\r' => json.push_str("\\r"),
+            '\t' => json.push_str("\\t"),
+            c if c.is_control() => {
+                write!(json, "\\u{:04x}", u32::from(c)).expect("Writing to a string can't fail");
+            }
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+}
+
+#[cfg(test)]
+mod tests {
+    use manyhow::error_message;
+
+    use super::*;
+
+    #[test]
+    fn errors_past_limit_are_suppressed() {
+        let mut emitter = Emitter::with_limit(2);
+        for i in 0..5 {
+            emitter.emit(error_message!("error {}", i));
+        }
+
+        let tokens = emitter.finish().unwrap_err().to_token_stream().to_string();
+        assert_eq!(tokens.matches("compile_error").count(), 3);
+        assert!(tokens.contains("error 1"));
+        assert!(!tokens.contains("error 2"));
+        assert_eq!(tokens.matches("3 more errors suppressed").count(), 1);
+    }
+
+    #[test]
+    fn duplicate_errors_are_suppressed() {
+        let tokens: TokenStream = "first second".parse().unwrap();
+        let spans: Vec<_> = tokens.into_iter().map(|token| token.span()).collect();
+
+        let mut emitter = Emitter::with_dedup();
+        emitter.emit_at(spans[0], "duplicate");
+        emitter.emit_at(spans[0], "duplicate");
+        emitter.emit_at(spans[1], "duplicate");
+
+        let tokens = emitter.finish().unwrap_err().to_token_stream().to_string();
+        assert_eq!(tokens.matches("compile_error").count(), 2);
+    }
+
+    #[test]
+    fn warnings_do_not_fail_finish() {
+        let mut emitter = Emitter::new();
+        assert!(!emitter.has_warnings());
+        emitter.emit_warning(Span::call_site(), "deprecated");
+        assert!(emitter.has_warnings());
+
+        let mut tokens = TokenStream::new();
+        emitter.finish_to_token_stream(&mut tokens);
+        assert!(tokens.to_string().contains("deprecated"));
+        assert!(!tokens.to_string().contains("compile_error"));
+
+        let mut emitter = Emitter::new();
+        emitter.emit_warning(Span::call_site(), "deprecated");
+        assert!(emitter.finish().is_ok());
+    }
+
+    #[test]
+    fn nested_scopes_prefix_errors() {
+        let mut emitter = Emitter::new();
+        emitter.scope("while deriving `Foo`:", |emitter| {
+            emitter.scope("in field `bar`:", |emitter| {
+                emitter.emit_at(Span::call_site(), "unsupported attribute");
+            });
+        });
+        emitter.emit_at(Span::call_site(), "unscoped");
+
+        let tokens = emitter.finish().unwrap_err().to_token_stream().to_string();
+        assert!(tokens.contains("while deriving `Foo`: in field `bar`: unsupported attribute"));
+        assert!(tokens.contains("\"unscoped\""));
+    }
+
+    #[test]
+    fn handle_in_prefixes_error_with_context() {
+        let mut emitter = Emitter::new();
+        let value = emitter.handle_in(
+            "while parsing `#[ffi]` attribute:",
+            Err::<(), _>(syn::Error::new(Span::call_site(), "expected identifier")),
+        );
+        assert!(value.is_none());
+        assert_eq!(
+            emitter.handle_in("unused:", Ok::<_, syn::Error>(1)),
+            Some(1)
+        );
+
+        let tokens = emitter.finish().unwrap_err().to_token_stream().to_string();
+        assert!(tokens.contains("while parsing `#[ffi]` attribute: expected identifier"));
+        assert!(!tokens.contains("unused:"));
+    }
+
+    #[test]
+    fn collect_results_emits_all_errors() {
+        let mut emitter = Emitter::new();
+        let values = emitter.collect_results(vec![
+            Ok(1),
+            Err(error_message!("first")),
+            Ok(2),
+            Err(error_message!("second")),
+        ]);
+        assert_eq!(values, vec![1, 2]);
+
+        let tokens = emitter.finish().unwrap_err().to_token_stream().to_string();
+        assert!(tokens.contains("first"));
+        assert!(tokens.contains("second"));
+    }
+
+    #[test]
+    fn absorbed_errors_are_reported() {
+        let mut parent = Emitter::new();
+        parent.emit_at(Span::call_site(), "parent error");
+        let mut child = Emitter::new();
+        child.emit_at(Span::call_site(), "child error");
+        child.emit_warning(Span::call_site(), "child warning");
+
+        parent.absorb(child);
+        assert!(parent.has_warnings());
+
+        let tokens = parent.finish().unwrap_err().to_token_stream().to_string();
+        assert!(tokens.contains("parent error"));
+        assert!(tokens.contains("child error"));
+    }
+
+    #[test]
+    fn errors_at_same_span_are_grouped() {
+        let mut emitter = Emitter::with_span_grouping();
+        emitter.emit_at(Span::call_site(), "first");
+        emitter.emit_at(Span::call_site(), "second");
+
+        let tokens = emitter.finish().unwrap_err().to_token_stream().to_string();
+        assert_eq!(tokens.matches("compile_error").count(), 1);
+        assert!(tokens.contains("first\\nsecond"));
+    }
+
+    #[test]
+    fn diagnostics_are_exported_as_json() {
+        let mut emitter = Emitter::new();
+        emitter.emit_at(Span::call_site(), "unsupported \"attr\"");
+        emitter.emit_warning(Span::call_site(), "deprecated");
+        emitter.emit_note(Span::call_site(), "consider this");
+
+        let json = emitter.to_diagnostics_json();
+        let _ = emitter.finish_token_stream();
+
+        assert!(json.starts_with('[') && json.ends_with(']'));
+        assert!(json.contains(r#""severity":"error","span":{"line":"#));
+        assert!(json.contains(r#""message":"unsupported \"attr\""}"#));
+        assert!(json.contains(r#""severity":"warning""#));
+        assert!(json.contains(r#""message":"consider this"}"#));
     }
 }
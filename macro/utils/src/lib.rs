@@ -2,7 +2,7 @@
 
 mod emitter;
 
-pub use emitter::Emitter;
+pub use emitter::{Emitter, Severity};
 
 /// Extension trait for [`darling::Error`].
 ///
@@ -1,8 +1,8 @@
-//! Provides a [`TimeSource`] - a mockable abstraction over [`std::time::SystemTime`]
+//! Provides a [`TimeSource`] - a mockable abstraction over [`std::time::SystemTime`] and [`std::time::Instant`]
 
 use std::{
-    sync::Arc,
-    time::{Duration, SystemTime},
+    sync::{Arc, OnceLock},
+    time::{Duration, Instant, SystemTime},
 };
 
 use parking_lot::Mutex;
@@ -13,7 +13,22 @@ enum TimeSourceInner {
     #[default]
     SystemTime,
     /// The time will come from the mock implementation
-    MockTime(Arc<Mutex<Duration>>),
+    MockTime(Arc<Mutex<MockTime>>),
+}
+
+/// Readings of the mock clocks
+#[derive(Debug, Clone, Copy)]
+struct MockTime {
+    /// Wall-clock time since unix epoch
+    unix_time: Duration,
+    /// Monotonic time since the creation of the mock
+    monotonic_time: Duration,
+}
+
+/// The point in time monotonic readings of the system clock are measured from
+fn monotonic_start() -> Instant {
+    static START: OnceLock<Instant> = OnceLock::new();
+    *START.get_or_init(Instant::now)
 }
 
 /// A time source that either relies on [`std::time::SystemTime::now()`] or uses a mock clock that must be advanced manually
@@ -34,13 +49,18 @@ impl TimeSource {
         (handle, source)
     }
 
+    /// Creates a mock [`TimeSource`] frozen at `unix_time` which can never be advanced
+    pub fn new_fixed(unix_time: Duration) -> Self {
+        MockTimeHandle::new(unix_time).source()
+    }
+
     /// Returns the [`SystemTime`] corresponding to "now".
     ///
     /// It can either come from [`SystemTime::now()`] or from a mock time source
     pub fn get_system_time(&self) -> SystemTime {
         match &self.0 {
             TimeSourceInner::SystemTime => SystemTime::now(),
-            TimeSourceInner::MockTime(time) => SystemTime::UNIX_EPOCH + *time.lock(),
+            TimeSourceInner::MockTime(time) => SystemTime::UNIX_EPOCH + time.lock().unix_time,
         }
     }
 
@@ -52,19 +72,37 @@ impl TimeSource {
             TimeSourceInner::SystemTime => SystemTime::now()
                 .duration_since(SystemTime::UNIX_EPOCH)
                 .expect("Failed to get the current system time"),
-            TimeSourceInner::MockTime(time) => *time.lock(),
+            TimeSourceInner::MockTime(time) => time.lock().unix_time,
+        }
+    }
+
+    /// Returns the reading of a monotonic clock, i.e. one which is not affected by steps of the wall-clock.
+    ///
+    /// Readings are measured from an arbitrary point in time, so only differences between them are meaningful.
+    /// It can either come from [`Instant::now()`] or from a mock time source
+    pub fn get_monotonic_time(&self) -> Duration {
+        match &self.0 {
+            TimeSourceInner::SystemTime => monotonic_start().elapsed(),
+            TimeSourceInner::MockTime(time) => time.lock().monotonic_time,
         }
     }
 }
 
 /// A handle that can be used to advance the mock [`TimeSource`].
+///
+/// The mock keeps wall-clock and monotonic readings independently:
+/// [`Self::advance`] moves both of them, as it simulates the passage of time,
+/// while [`Self::set`] and [`Self::rewind`] only step the wall-clock.
 #[derive(Clone)]
-pub struct MockTimeHandle(Arc<Mutex<Duration>>);
+pub struct MockTimeHandle(Arc<Mutex<MockTime>>);
 
 impl MockTimeHandle {
     /// Creates a [`MockTimeHandle`] set to a specific unix timestamp.
     pub fn new(start_unix_time: Duration) -> Self {
-        Self(Arc::new(Mutex::new(start_unix_time)))
+        Self(Arc::new(Mutex::new(MockTime {
+            unix_time: start_unix_time,
+            monotonic_time: Duration::ZERO,
+        })))
     }
 
     /// Gets a [`TimeSource`] corresponding to this mock handle
@@ -72,21 +110,26 @@ impl MockTimeHandle {
         TimeSource(TimeSourceInner::MockTime(self.0.clone()))
     }
 
-    /// Set the mock time to a specific unix time value
+    /// Set the mock wall-clock time to a specific unix time value
+    ///
+    /// The monotonic time is not affected
     pub fn set(&self, unix_time: Duration) {
         let mut time = self.0.lock();
-        *time = unix_time;
+        time.unix_time = unix_time;
     }
 
     /// Advance mock time
     pub fn advance(&self, advance_time: Duration) {
         let mut time = self.0.lock();
-        *time += advance_time;
+        time.unix_time += advance_time;
+        time.monotonic_time += advance_time;
     }
 
-    /// Rewind mock time
+    /// Rewind mock wall-clock time
+    ///
+    /// The monotonic time is not affected
     pub fn rewind(&self, advance_time: Duration) {
         let mut time = self.0.lock();
-        *time -= advance_time;
+        time.unix_time -= advance_time;
     }
 }
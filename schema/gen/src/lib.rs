@@ -289,6 +289,7 @@ types!(
     Option<RoleId>,
     Option<String>,
     Option<TimeInterval>,
+    Option<TransactionFee>,
     Option<TransactionRejectionReason>,
     Option<TransactionStatus>,
     Option<TriggerCompletedOutcomeType>,
@@ -377,6 +378,7 @@ types!(
     TimeSchedule,
     TransactionEvent,
     TransactionEventFilter,
+    TransactionFee,
     TransactionLimitError,
     TransactionParameter,
     TransactionParameters,
@@ -385,6 +387,7 @@ types!(
     TransactionRejectionReason,
     TransactionSignature,
     TransactionStatus,
+    TransactionsExpiredEvent,
     Transfer<Account, AssetDefinitionId, Account>,
     Transfer<Account, DomainId, Account>,
     Transfer<Asset, Metadata, Account>,
@@ -410,10 +413,12 @@ types!(
     Unregister<Trigger>,
     UnregisterBox,
     Upgrade,
+    ValidUntilBlockExceeded,
     ValidationFail,
     Vec<BlockSignature>,
     Vec<CommittedTransaction>,
     Vec<EventFilterBox>,
+    Vec<HashOf<SignedTransaction>>,
     Vec<InstructionBox>,
     Vec<Parameter>,
     Vec<PeerId>,
@@ -4,7 +4,7 @@ use std::{ops::Deref, time::Duration};
 
 use parity_scale_codec::{Compact, Decode, Encode};
 use prometheus::{
-    core::{AtomicU64, GenericGauge, GenericGaugeVec},
+    core::{AtomicU64, Collector, GenericGauge, GenericGaugeVec},
     Encoder, Histogram, HistogramOpts, HistogramVec, IntCounter, IntCounterVec, Opts, Registry,
 };
 use serde::{Deserialize, Serialize};
@@ -212,6 +212,14 @@ impl Default for Metrics {
 }
 
 impl Metrics {
+    /// Register additional metrics maintained outside of [`Metrics`], e.g. by the queue.
+    ///
+    /// # Errors
+    /// If metrics with the same names are already registered
+    pub fn register(&self, collector: impl Collector + 'static) -> prometheus::Result<()> {
+        self.registry.register(Box::new(collector))
+    }
+
     /// Convert the current [`Metrics`] into a Prometheus-readable format.
     ///
     /// # Errors
@@ -343,6 +343,9 @@ impl IntoResponse for Error {
     fn into_response(self) -> Response {
         match self {
             Self::Query(err) => (Self::query_status_code(&err), utils::Scale(err)).into_response(),
+            Self::PushIntoQueue(ref err) => {
+                (self.status_code(), format!("{self}: {err}")).into_response()
+            }
             _ => (self.status_code(), self.to_string()).into_response(),
         }
     }
@@ -354,10 +357,16 @@ impl Error {
 
         match self {
             Query(e) => Self::query_status_code(e),
-            AcceptTransaction(_) => StatusCode::BAD_REQUEST,
+            AcceptTransaction(err) => {
+                StatusCode::from_u16(err.http_status()).unwrap_or(StatusCode::BAD_REQUEST)
+            }
             Config(_) | StatusSegmentNotFound(_) => StatusCode::NOT_FOUND,
             PushIntoQueue(err) => match **err {
-                queue::Error::Full => StatusCode::INTERNAL_SERVER_ERROR,
+                queue::Error::Full | queue::Error::InstructionBudgetExceeded => {
+                    StatusCode::INTERNAL_SERVER_ERROR
+                }
+                queue::Error::RateLimited => StatusCode::TOO_MANY_REQUESTS,
+                queue::Error::Halted(_) => StatusCode::SERVICE_UNAVAILABLE,
                 _ => StatusCode::BAD_REQUEST,
             },
             #[cfg(feature = "telemetry")]
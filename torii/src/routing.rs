@@ -38,7 +38,7 @@ pub async fn handle_transaction(
         .map_err(Error::AcceptTransaction)?;
     queue
         .push(transaction, &state_view)
-        .map_err(|queue::Failure { tx, err }| {
+        .map_err(|queue::Failure { tx, err, .. }| {
             iroha_logger::warn!(
                 tx_hash=%tx.as_ref().hash(), ?err,
                 "Failed to push into queue"